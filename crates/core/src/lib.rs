@@ -3,7 +3,7 @@ pub mod prelude;
 pub use pretty_assertions;
 pub use wyst_core_traits::{WystCopy, WystData, WystDataValue, WystEmpty};
 pub use wyst_proc_macros::{
-    new, unit_test, wyst_copy, wyst_data, wyst_data_value, wyst_display, Display,
+    new, tokens, unit_test, wyst_copy, wyst_data, wyst_data_value, wyst_display, Display, Parse,
 };
 
 #[macro_export]
@@ -0,0 +1,332 @@
+use wyst_core::wyst_data;
+use wyst_source::{Span, Spanned};
+
+use crate::lex::Strip;
+
+/// A list of statements: a whole template, or the body of a block.
+#[wyst_data]
+pub struct Program {
+    pub(crate) body: Vec<Statement>,
+    pub(crate) span: Span,
+}
+
+impl Program {
+    pub fn body(&self) -> &[Statement] {
+        &self.body
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+#[wyst_data]
+pub enum Statement {
+    Content(Content),
+    Mustache(Mustache),
+    Block(Block),
+    Partial(Partial),
+    PartialBlock(PartialBlock),
+    Comment(Comment),
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Content(content) => content.span,
+            Statement::Mustache(mustache) => mustache.span,
+            Statement::Block(block) => block.span,
+            Statement::Partial(partial) => partial.span,
+            Statement::PartialBlock(block) => block.span,
+            Statement::Comment(comment) => comment.span,
+        }
+    }
+}
+
+/// Text outside of tags.
+#[wyst_data]
+pub struct Content {
+    pub(crate) span: Span,
+    pub(crate) text: Span,
+}
+
+impl Content {
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The span of the text that the content produces, after whitespace control.
+    pub fn text(&self) -> Span {
+        self.text
+    }
+}
+
+/// `{{expr}}`, or `{{{expr}}}` and `{{&expr}}`, which don't escape their output.
+#[wyst_data]
+pub struct Mustache {
+    pub(crate) call: Call,
+    pub(crate) escaped: bool,
+    pub(crate) strip: Strip,
+    pub(crate) span: Span,
+}
+
+impl Mustache {
+    pub fn call(&self) -> &Call {
+        &self.call
+    }
+
+    pub fn is_escaped(&self) -> bool {
+        self.escaped
+    }
+
+    pub fn strip(&self) -> Strip {
+        self.strip
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `{{#helper args}}...{{else}}...{{/helper}}`, or `{{^helper args}}...{{/helper}}`.
+///
+/// A chained `{{else helper args}}` becomes a block of its own, which is the only statement in
+/// the inverse of the block before it.
+#[wyst_data]
+pub struct Block {
+    pub(crate) call: Call,
+    pub(crate) inverted: bool,
+    pub(crate) params: Vec<Spanned<String>>,
+    pub(crate) program: Program,
+    pub(crate) inverse: Option<Program>,
+    pub(crate) open_strip: Strip,
+    pub(crate) inverse_strip: Option<Strip>,
+    pub(crate) close_strip: Strip,
+    pub(crate) span: Span,
+}
+
+impl Block {
+    pub fn call(&self) -> &Call {
+        &self.call
+    }
+
+    /// Whether the block was opened with `{{^` rather than `{{#`.
+    pub fn is_inverted(&self) -> bool {
+        self.inverted
+    }
+
+    /// The block parameters (`as |item index|`).
+    pub fn params(&self) -> &[Spanned<String>] {
+        &self.params
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// The statements after the block's `{{else}}`, if it has one.
+    pub fn inverse(&self) -> Option<&Program> {
+        self.inverse.as_ref()
+    }
+
+    pub fn open_strip(&self) -> Strip {
+        self.open_strip
+    }
+
+    /// The whitespace control of the block's `{{else}}`, if it has one.
+    pub fn inverse_strip(&self) -> Option<Strip> {
+        self.inverse_strip
+    }
+
+    /// The whitespace control of the block's closing tag. A chained block shares its closing tag
+    /// with the block before it, so its own is the default.
+    pub fn close_strip(&self) -> Strip {
+        self.close_strip
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `{{> name context key=value}}`.
+#[wyst_data]
+pub struct Partial {
+    pub(crate) name: Spanned<Expr>,
+    pub(crate) context: Option<Spanned<Expr>>,
+    pub(crate) hash: Vec<HashPair>,
+    pub(crate) strip: Strip,
+    pub(crate) span: Span,
+}
+
+impl Partial {
+    /// The partial's name, which is a path, a string or a subexpression.
+    pub fn name(&self) -> &Spanned<Expr> {
+        &self.name
+    }
+
+    pub fn context(&self) -> Option<&Spanned<Expr>> {
+        self.context.as_ref()
+    }
+
+    pub fn hash(&self) -> &[HashPair] {
+        &self.hash
+    }
+
+    pub fn strip(&self) -> Strip {
+        self.strip
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `{{#> name}}...{{/name}}`, whose body is the partial's fallback content.
+#[wyst_data]
+pub struct PartialBlock {
+    pub(crate) partial: Partial,
+    pub(crate) program: Program,
+    pub(crate) close_strip: Strip,
+    pub(crate) span: Span,
+}
+
+impl PartialBlock {
+    /// The partial in the opening tag.
+    pub fn partial(&self) -> &Partial {
+        &self.partial
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    pub fn close_strip(&self) -> Strip {
+        self.close_strip
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `{{! comment }}` or `{{!-- comment --}}`.
+#[wyst_data]
+pub struct Comment {
+    pub(crate) text: Span,
+    pub(crate) strip: Strip,
+    pub(crate) span: Span,
+}
+
+impl Comment {
+    /// The span of the comment's text, without the delimiters.
+    pub fn text(&self) -> Span {
+        self.text
+    }
+
+    pub fn strip(&self) -> Strip {
+        self.strip
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A helper or a value, with its positional and hash arguments (`helper a b key=c`).
+#[wyst_data]
+pub struct Call {
+    pub(crate) head: Spanned<Expr>,
+    pub(crate) params: Vec<Spanned<Expr>>,
+    pub(crate) hash: Vec<HashPair>,
+    pub(crate) span: Span,
+}
+
+impl Call {
+    pub fn head(&self) -> &Spanned<Expr> {
+        &self.head
+    }
+
+    pub fn params(&self) -> &[Spanned<Expr>] {
+        &self.params
+    }
+
+    pub fn hash(&self) -> &[HashPair] {
+        &self.hash
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// `key=value`.
+#[wyst_data]
+pub struct HashPair {
+    pub(crate) key: Spanned<String>,
+    pub(crate) value: Spanned<Expr>,
+}
+
+impl HashPair {
+    pub fn key(&self) -> &Spanned<String> {
+        &self.key
+    }
+
+    pub fn value(&self) -> &Spanned<Expr> {
+        &self.value
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.key.span().start(), self.value.span().end())
+    }
+}
+
+#[wyst_data]
+pub enum Expr {
+    Path(Path),
+    /// `(helper args)`.
+    SubExpression(Box<Call>),
+    /// The contents of a string literal, without its quotes (and with escaped characters
+    /// unescaped).
+    String(String),
+    /// The text of a number literal.
+    Number(String),
+    Boolean(bool),
+    Null,
+    Undefined,
+}
+
+/// A path expression, such as `foo.bar`, `../name`, `this/[a b]` or `@index`.
+#[wyst_data]
+pub struct Path {
+    pub(crate) data: bool,
+    pub(crate) depth: usize,
+    pub(crate) parts: Vec<Spanned<String>>,
+}
+
+impl Path {
+    /// Whether the path starts with `@`.
+    pub fn is_data(&self) -> bool {
+        self.data
+    }
+
+    /// The number of `..` segments at the start of the path.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The segments of the path, without `this`, `.` and `..` (and without the brackets around
+    /// literal segments). The path `this` has no parts.
+    pub fn parts(&self) -> &[Spanned<String>] {
+        &self.parts
+    }
+
+    /// The parts of the path, separated by `.`.
+    pub fn name(&self) -> String {
+        self.parts
+            .iter()
+            .map(|part| part.item().as_str())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
@@ -0,0 +1,485 @@
+use logos::Logos;
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_lex::{Delimiter, LeafKind, Token, TokenClass, TokenKind, TokenTree};
+use wyst_source::{AddSpan, Diagnostic, Source, Span, Spanned};
+
+/// The tokens inside of a mustache tag. Paths are single tokens (`../foo.[bar baz]` or
+/// `@root/name`), and parentheses are delimiters, so the tokens of a tag read into a tree with a
+/// delimited token for each subexpression.
+#[wyst_copy]
+#[derive(Logos)]
+#[logos(subpattern id = r"[^\s!-#%-,./;->@\[-\^`{-~]+")]
+#[logos(subpattern segment = r"((?&id)|\[[^\]]*\])")]
+pub enum HbsToken {
+    #[error]
+    Error,
+
+    #[regex(r"[ \t\r\n\f]+")]
+    Whitespace,
+
+    /// A path, whose segments are identifiers, `[literal segments]`, `.` or `..`, separated by `.`
+    /// or `/`, optionally starting with `@` for data.
+    #[regex(r"@?((?&segment)|\.\.|\.)([./]((?&segment)|\.\.))*")]
+    Path,
+
+    #[regex(r#""([^"\\]|\\.)*""#)]
+    #[regex(r"'([^'\\]|\\.)*'")]
+    String,
+
+    #[regex(r"-?[0-9]+(\.[0-9]+)?", priority = 10)]
+    Number,
+
+    #[token("true")]
+    #[token("false")]
+    Boolean,
+
+    #[token("null")]
+    Null,
+
+    #[token("undefined")]
+    Undefined,
+
+    #[token("=")]
+    Equals,
+
+    /// The pipes around block parameters (`as |item index|`).
+    #[token("|")]
+    Pipe,
+
+    #[token("(", |_| Delimiter::Paren)]
+    Open(Delimiter),
+
+    #[token(")", |_| Delimiter::Paren)]
+    Close(Delimiter),
+
+    /// The end of a tag: `}}`, `~}}`, `}}}` or `}~}}`.
+    #[token("}}")]
+    #[token("~}}")]
+    #[token("}}}")]
+    #[token("}~}}")]
+    CloseTag,
+
+    EOF,
+}
+
+impl TokenKind for HbsToken {
+    type Leaf = HbsToken;
+
+    fn classify(self) -> TokenClass<HbsToken> {
+        match self {
+            HbsToken::Error => TokenClass::Error,
+            HbsToken::Open(delimiter) => TokenClass::Open(delimiter),
+            HbsToken::Close(delimiter) => TokenClass::Close(delimiter),
+            HbsToken::EOF => TokenClass::EOF,
+            other => TokenClass::Leaf(other),
+        }
+    }
+}
+
+impl LeafKind for HbsToken {
+    fn eof() -> Self {
+        HbsToken::EOF
+    }
+
+    fn error() -> Self {
+        HbsToken::Error
+    }
+
+    fn is_trivia(self) -> bool {
+        self == HbsToken::Whitespace
+    }
+}
+
+/// Whitespace control: `{{~` strips the whitespace before a tag, and `~}}` strips the whitespace
+/// after it.
+#[wyst_copy]
+#[derive(Default)]
+pub struct Strip {
+    before: bool,
+    after: bool,
+}
+
+impl Strip {
+    pub fn before(self) -> bool {
+        self.before
+    }
+
+    pub fn after(self) -> bool {
+        self.after
+    }
+}
+
+/// What kind of tag a tag is, according to the sigil after its `{{`.
+#[wyst_copy]
+pub enum TagKind {
+    /// `{{expr}}` (which includes `{{else}}`).
+    Mustache,
+    /// `{{{expr}}}`.
+    Raw,
+    /// `{{&expr}}`.
+    Unescaped,
+    /// `{{#block}}`.
+    Block,
+    /// `{{^block}}`, or `{{^}}` (which is the same as `{{else}}`).
+    Inverse,
+    /// `{{/block}}`.
+    Close,
+    /// `{{> partial}}`.
+    Partial,
+    /// `{{#> partial}}`.
+    PartialBlock,
+    /// `{{! comment }}` or `{{!-- comment --}}`. The span is the comment's text.
+    Comment(Span),
+}
+
+/// A tag, from its `{{` to its `}}`.
+#[wyst_data]
+pub struct Tag {
+    kind: TagKind,
+    strip: Strip,
+    /// The token tree of the tag's contents, after the sigil (and ending with an EOF token at the
+    /// closing `}}`). Comments have no tokens.
+    tokens: Vec<Spanned<Token<HbsToken>>>,
+    span: Span,
+}
+
+impl Tag {
+    pub fn kind(&self) -> TagKind {
+        self.kind
+    }
+
+    pub fn strip(&self) -> Strip {
+        self.strip
+    }
+
+    pub fn tokens(&self) -> &[Spanned<Token<HbsToken>>] {
+        &self.tokens
+    }
+
+    /// The tokens other than whitespace and the EOF token.
+    pub fn significant(&self) -> Vec<&Spanned<Token<HbsToken>>> {
+        significant(&self.tokens)
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+pub(crate) fn significant(tokens: &[Spanned<Token<HbsToken>>]) -> Vec<&Spanned<Token<HbsToken>>> {
+    tokens
+        .iter()
+        .filter(|token| match token.item() {
+            Token::Leaf(leaf) => !leaf.is_trivia() && *leaf != HbsToken::EOF,
+            Token::Delimited(_) => true,
+        })
+        .collect()
+}
+
+/// A part of a template: either content (text outside of tags) or a tag.
+#[wyst_data]
+pub enum Segment {
+    /// Content, with the span of its text after whitespace control (which is the same as the
+    /// content's span unless a neighboring tag strips whitespace).
+    Content {
+        span: Span,
+        text: Span,
+    },
+    Tag(Tag),
+}
+
+/// The result of [lex_template].
+#[wyst_data]
+pub struct LexedTemplate {
+    segments: Vec<Segment>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl LexedTemplate {
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// Split a template into content and tags, and read the tokens of each tag into a token tree.
+///
+/// An unclosed tag or comment runs to the end of the source, and an unclosed parenthesis in a tag
+/// is closed at the end of the tag, so a mistake in one tag doesn't change how the rest of the
+/// template is read.
+pub fn lex_template(source: &Source) -> LexedTemplate {
+    let text = source.contents();
+    let mut segments = vec![];
+    let mut diagnostics = vec![];
+    let mut pos = 0;
+
+    while pos < text.len() {
+        let start = match text[pos..].find("{{") {
+            Some(offset) => pos + offset,
+            None => text.len(),
+        };
+
+        if pos < start {
+            let span = Span::new(pos, start);
+            segments.push(Segment::Content { span, text: span });
+        }
+
+        if start == text.len() {
+            break;
+        }
+
+        let tag = lex_tag(source, start, &mut diagnostics);
+        pos = tag.span.end().into();
+        segments.push(Segment::Tag(tag));
+    }
+
+    strip_whitespace(&mut segments, text);
+
+    LexedTemplate {
+        segments,
+        diagnostics,
+    }
+}
+
+fn lex_tag(source: &Source, start: usize, diagnostics: &mut Vec<Diagnostic>) -> Tag {
+    let text = source.contents();
+    let mut pos = start + 2;
+    let mut strip = Strip::default();
+
+    if text[pos..].starts_with('~') {
+        strip.before = true;
+        pos += 1;
+    }
+
+    if text[pos..].starts_with('!') {
+        return lex_comment(source, start, pos + 1, strip, diagnostics);
+    }
+
+    let sigils = [
+        ("{", TagKind::Raw),
+        ("&", TagKind::Unescaped),
+        ("#>", TagKind::PartialBlock),
+        ("#", TagKind::Block),
+        ("^", TagKind::Inverse),
+        ("/", TagKind::Close),
+        (">", TagKind::Partial),
+    ];
+
+    let kind = match sigils
+        .iter()
+        .find(|(sigil, _)| text[pos..].starts_with(sigil))
+    {
+        Some((sigil, kind)) => {
+            pos += sigil.len();
+            *kind
+        }
+        None => TagKind::Mustache,
+    };
+
+    let mut tokens = vec![];
+    let mut close = None;
+
+    for (token, range) in HbsToken::lexer(&text[pos..]).spanned() {
+        let span = Span::new(pos + range.start, pos + range.end);
+
+        if token != HbsToken::CloseTag {
+            tokens.push(token.spanned(span));
+            continue;
+        }
+
+        let closer = &text[pos + range.start..pos + range.end];
+        let raw_closer = closer == "}}}" || closer == "}~}}";
+
+        if kind == TagKind::Raw && !raw_closer {
+            diagnostics.push(Diagnostic::error(span, "expected `}}}` to close `{{{`"));
+        }
+
+        // A `{{x}}}` is a tag followed by a `}` in the content.
+        let end = match (kind, closer) {
+            (TagKind::Raw, _) | (_, "}~}}") => span.end().into(),
+            (_, "}}}") => pos + range.start + 2,
+            _ => span.end().into(),
+        };
+
+        strip.after = closer.contains('~');
+        close = Some((pos + range.start, end));
+        break;
+    }
+
+    let (close_start, end) = close.unwrap_or_else(|| {
+        diagnostics.push(Diagnostic::error(
+            Span::new(start, start + 2),
+            "unclosed `{{`",
+        ));
+        (text.len(), text.len())
+    });
+
+    let eof = HbsToken::EOF.spanned(Span::eof(close_start));
+    let read = TokenTree::read(source, tokens.into_iter().chain(std::iter::once(eof)));
+    diagnostics.extend(read.diagnostics().iter().cloned());
+
+    Tag {
+        kind,
+        strip,
+        tokens: read.into_tokens(),
+        span: Span::new(start, end),
+    }
+}
+
+/// `{{! comment }}` or `{{!-- comment --}}`, where `body` is the offset after the `!`.
+fn lex_comment(
+    source: &Source,
+    start: usize,
+    body: usize,
+    mut strip: Strip,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Tag {
+    let text = source.contents();
+    let (body, closers): (usize, &[&str]) = if text[body..].starts_with("--") {
+        (body + 2, &["--}}", "--~}}"])
+    } else {
+        (body, &["}}", "~}}"])
+    };
+
+    let close = closers
+        .iter()
+        .filter_map(|closer| {
+            text[body..]
+                .find(closer)
+                .map(|offset| (body + offset, closer))
+        })
+        .min_by_key(|(offset, _)| *offset);
+
+    let (text_end, end) = match close {
+        Some((offset, closer)) => {
+            strip.after = closer.contains('~');
+            (offset, offset + closer.len())
+        }
+        None => {
+            diagnostics.push(Diagnostic::error(
+                Span::new(start, start + 2),
+                "unclosed comment",
+            ));
+            (text.len(), text.len())
+        }
+    };
+
+    Tag {
+        kind: TagKind::Comment(Span::new(body, text_end)),
+        strip,
+        tokens: vec![],
+        span: Span::new(start, end),
+    }
+}
+
+/// Narrow the text of each content segment according to the whitespace control of the tags
+/// around it.
+fn strip_whitespace(segments: &mut [Segment], text: &str) {
+    for i in 0..segments.len() {
+        let strip_start = i > 0 && matches!(&segments[i - 1], Segment::Tag(tag) if tag.strip.after);
+        let strip_end = matches!(segments.get(i + 1), Some(Segment::Tag(tag)) if tag.strip.before);
+
+        if let Segment::Content {
+            span,
+            text: stripped,
+        } = &mut segments[i]
+        {
+            let (mut start, mut end): (usize, usize) = (span.start().into(), span.end().into());
+
+            if strip_start {
+                start = end - text[start..end].trim_start().len();
+            }
+
+            if strip_end {
+                end = start + text[start..end].trim_end().len();
+            }
+
+            *stripped = Span::new(start, end);
+        }
+    }
+}
+
+unit_tests!(
+    all({
+        fn describe(source: &Source) -> Vec<String> {
+            lex_template(source)
+                .segments()
+                .iter()
+                .map(|segment| match segment {
+                    Segment::Content { text, .. } => format!("{:?}", source.slice(*text)),
+                    Segment::Tag(tag) => match tag.kind() {
+                        TagKind::Comment(text) => format!("Comment {:?}", source.slice(text)),
+                        kind => format!(
+                            "{:?}{}",
+                            kind,
+                            tag.significant()
+                                .iter()
+                                .map(|token| format!(" {}", source.slice(token.span())))
+                                .collect::<String>()
+                        ),
+                    },
+                })
+                .collect()
+        }
+    }),
+    tests(
+        ("segments", {
+            let source = Source::new(
+                "<test>",
+                "Hi {{@root.user/[first name]}}! {{{body}}}{{#each (list 'a}}' 2) as |x|}}",
+            );
+
+            assert_eq!(
+                describe(&source),
+                &[
+                    "\"Hi \"",
+                    "Mustache @root.user/[first name]",
+                    "\"! \"",
+                    "Raw body",
+                    "Block each (list 'a}}' 2) as | x |",
+                ]
+            );
+            assert_eq!(lex_template(&source).diagnostics(), &[]);
+        }),
+        ("comments and whitespace control", {
+            let source = Source::new(
+                "<test>",
+                "a  {{~! hi ~}}  b {{!-- }} --}} c {{~^}} d\n{{> (p) x=1~}}\n e",
+            );
+
+            assert_eq!(
+                describe(&source),
+                &[
+                    "\"a\"",
+                    "Comment \" hi \"",
+                    "\"b \"",
+                    "Comment \" }} \"",
+                    "\" c\"",
+                    "Inverse",
+                    "\" d\\n\"",
+                    "Partial (p) x = 1",
+                    "\"e\"",
+                ]
+            );
+        }),
+        ("unclosed tags", {
+            let source = Source::new("<test>", "a {{x (y}} b {{z");
+            let lexed = lex_template(&source);
+
+            assert_eq!(
+                lexed.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(6, 7), "unclosed `(`"),
+                    Diagnostic::error(Span::new(13, 15), "unclosed `{{`"),
+                ]
+            );
+            assert_eq!(
+                describe(&source),
+                &["\"a \"", "Mustache x (y", "\" b \"", "Mustache z"]
+            );
+        })
+    )
+);
@@ -0,0 +1,21 @@
+//! A Handlebars front end built on `wyst-lex`.
+//!
+//! [lex_template] splits a template into content and tags (mustaches, blocks, partials and
+//! comments), and reads the tokens inside of each tag into a token tree in which subexpressions are
+//! delimited tokens. [read_template] reads the tags into a span-annotated AST of paths, literals,
+//! subexpressions and hash arguments, pairing each block with its `{{else}}` and closing tag and
+//! reporting closing tags that don't match.
+//!
+//! Whitespace control (`{{~` and `~}}`) narrows the text of the neighboring content. Standalone
+//! lines (a block tag alone on its line) are left alone.
+
+mod ast;
+mod lex;
+mod read;
+
+pub use ast::{
+    Block, Call, Comment, Content, Expr, HashPair, Mustache, Partial, PartialBlock, Path, Program,
+    Statement,
+};
+pub use lex::{lex_template, HbsToken, LexedTemplate, Segment, Strip, Tag, TagKind};
+pub use read::{read_template, ReadTemplate};
@@ -0,0 +1,770 @@
+use wyst_core::{unit_tests, wyst_data};
+use wyst_lex::Token;
+use wyst_source::{AddSpan, Diagnostic, Source, Span, Spanned};
+
+use crate::{
+    ast::{
+        Block, Call, Comment, Content, Expr, HashPair, Mustache, Partial, PartialBlock, Path,
+        Program, Statement,
+    },
+    lex::{lex_template, significant, HbsToken, Segment, Strip, Tag, TagKind},
+};
+
+/// The result of [read_template]. The template is always there, even if it had errors.
+#[wyst_data]
+pub struct ReadTemplate {
+    template: Program,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ReadTemplate {
+    pub fn template(&self) -> &Program {
+        &self.template
+    }
+
+    pub fn into_template(self) -> Program {
+        self.template
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+/// Read a Handlebars template into its AST.
+///
+/// A closing tag that doesn't match the innermost open block is reported. If it matches a block
+/// further out, the blocks inside of that one are reported as unclosed and closed along with it;
+/// otherwise, it closes the innermost block anyway. A tag that can't be read is reported and left
+/// out of the AST (along with the body of a block whose opening tag can't be read).
+pub fn read_template(source: &Source) -> ReadTemplate {
+    let lexed = lex_template(source);
+
+    let mut reader = TemplateReader {
+        source,
+        diagnostics: lexed.diagnostics().to_vec(),
+        root: vec![],
+        frames: vec![],
+    };
+
+    for segment in lexed.segments() {
+        reader.segment(segment);
+    }
+
+    let end = source.contents().len();
+
+    while let Some(frame) = reader.frames.pop() {
+        if !frame.chained {
+            reader.unclosed(&frame);
+        }
+
+        reader.finish(frame, end, end, Strip::default());
+    }
+
+    ReadTemplate {
+        template: Program {
+            body: reader.root,
+            span: Span::new(0, end),
+        },
+        diagnostics: reader.diagnostics,
+    }
+}
+
+type TokenRef<'a> = &'a Spanned<Token<HbsToken>>;
+
+enum Opener {
+    Block {
+        call: Option<Call>,
+        inverted: bool,
+        params: Vec<Spanned<String>>,
+        strip: Strip,
+    },
+    Partial(Option<Partial>),
+}
+
+/// A block that's still open.
+struct Frame {
+    opener: Opener,
+    /// The name that the closing tag has to use.
+    name: String,
+    /// The opening tag (such as `{{#each}}`) for messages.
+    description: String,
+    open_span: Span,
+    /// The body before the block's `{{else}}`, once there is one.
+    program: Option<Program>,
+    inverse_strip: Option<Strip>,
+    body: Vec<Statement>,
+    body_start: usize,
+    /// Whether the block was opened by a chained `{{else helper}}`, which is closed by the closing
+    /// tag of the block before it.
+    chained: bool,
+}
+
+struct TemplateReader<'a> {
+    source: &'a Source,
+    diagnostics: Vec<Diagnostic>,
+    root: Vec<Statement>,
+    frames: Vec<Frame>,
+}
+
+impl<'a> TemplateReader<'a> {
+    fn segment(&mut self, segment: &Segment) {
+        let tag = match segment {
+            Segment::Content { span, text } => {
+                self.push(Statement::Content(Content {
+                    span: *span,
+                    text: *text,
+                }));
+                return;
+            }
+            Segment::Tag(tag) => tag,
+        };
+
+        let tokens = tag.significant();
+
+        match tag.kind() {
+            TagKind::Comment(text) => self.push(Statement::Comment(Comment {
+                text,
+                strip: tag.strip(),
+                span: tag.span(),
+            })),
+            TagKind::Mustache if self.is_else(tokens.first()) => self.else_tag(tag, &tokens[1..]),
+            TagKind::Inverse if tokens.is_empty() => self.else_tag(tag, &[]),
+            TagKind::Mustache | TagKind::Raw | TagKind::Unescaped => {
+                if let Some(call) = self.call(&tokens, tag.span()) {
+                    self.push(Statement::Mustache(Mustache {
+                        call,
+                        escaped: tag.kind() == TagKind::Mustache,
+                        strip: tag.strip(),
+                        span: tag.span(),
+                    }));
+                }
+            }
+            TagKind::Block | TagKind::Inverse => {
+                let inverted = tag.kind() == TagKind::Inverse;
+                let sigil = if inverted { "^" } else { "#" };
+                self.open_block(tag, &tokens, sigil, inverted, false);
+            }
+            TagKind::Partial => {
+                if let Some(partial) = self.partial(tag, &tokens) {
+                    self.push(Statement::Partial(partial));
+                }
+            }
+            TagKind::PartialBlock => {
+                let partial = self.partial(tag, &tokens);
+                let name = self.name(tokens.first());
+
+                self.frames.push(Frame {
+                    opener: Opener::Partial(partial),
+                    description: format!("`{{{{#> {}}}}}`", name),
+                    name,
+                    open_span: tag.span(),
+                    program: None,
+                    inverse_strip: None,
+                    body: vec![],
+                    body_start: tag.span().end().into(),
+                    chained: false,
+                });
+            }
+            TagKind::Close => self.close(tag, &tokens),
+        }
+    }
+
+    fn open_block(
+        &mut self,
+        tag: &Tag,
+        tokens: &[TokenRef<'_>],
+        sigil: &str,
+        inverted: bool,
+        chained: bool,
+    ) {
+        let (tokens, params) = self.block_params(tokens);
+        let call = self.call(tokens, tag.span());
+
+        // A chained block is closed by the closing tag of the block it's chained to.
+        let (name, description) = match (chained, self.frames.last()) {
+            (true, Some(frame)) => (frame.name.clone(), frame.description.clone()),
+            _ => {
+                let name = self.name(tokens.first());
+                let description = format!("`{{{{{}{}}}}}`", sigil, name);
+                (name, description)
+            }
+        };
+
+        self.frames.push(Frame {
+            opener: Opener::Block {
+                call,
+                inverted,
+                params,
+                strip: tag.strip(),
+            },
+            name,
+            description,
+            open_span: tag.span(),
+            program: None,
+            inverse_strip: None,
+            body: vec![],
+            body_start: tag.span().end().into(),
+            chained,
+        });
+    }
+
+    /// `{{else}}`, `{{^}}` or a chained `{{else helper args}}`.
+    fn else_tag(&mut self, tag: &Tag, rest: &[TokenRef<'_>]) {
+        let frame = match self.frames.last_mut() {
+            Some(frame) if matches!(frame.opener, Opener::Block { .. }) => frame,
+            _ => {
+                self.diagnostics.push(Diagnostic::error(
+                    tag.span(),
+                    "`{{else}}` outside of a block",
+                ));
+                return;
+            }
+        };
+
+        if frame.program.is_some() {
+            self.diagnostics.push(Diagnostic::error(
+                tag.span(),
+                format!("{} already has an `{{{{else}}}}`", frame.description),
+            ));
+            return;
+        }
+
+        frame.program = Some(Program {
+            body: std::mem::take(&mut frame.body),
+            span: Span::new(frame.body_start, tag.span().start()),
+        });
+        frame.inverse_strip = Some(tag.strip());
+        frame.body_start = tag.span().end().into();
+
+        if !rest.is_empty() {
+            self.open_block(tag, rest, "#", false, true);
+        }
+    }
+
+    fn close(&mut self, tag: &Tag, tokens: &[TokenRef<'_>]) {
+        let name = self.name(tokens.first());
+        let tag_start: usize = tag.span().start().into();
+        let tag_end: usize = tag.span().end().into();
+
+        let target = self
+            .frames
+            .iter()
+            .rposition(|frame| !frame.chained && frame.name == name);
+
+        let target = match target {
+            Some(target) => target,
+            None => match self.frames.iter().rposition(|frame| !frame.chained) {
+                Some(innermost) => {
+                    let frame = &self.frames[innermost];
+                    self.diagnostics.push(Diagnostic::error(
+                        tag.span(),
+                        format!("`{{{{/{}}}}}` doesn't match {}", name, frame.description),
+                    ));
+                    self.diagnostics.push(Diagnostic::note(
+                        frame.open_span,
+                        format!("{} starts here", frame.description),
+                    ));
+                    innermost
+                }
+                None => {
+                    self.diagnostics.push(Diagnostic::error(
+                        tag.span(),
+                        format!("`{{{{/{}}}}}` doesn't close a block", name),
+                    ));
+                    return;
+                }
+            },
+        };
+
+        while self.frames.len() > target + 1 {
+            let frame = self
+                .frames
+                .pop()
+                .expect("BUG: there's a frame above the target");
+
+            if !frame.chained {
+                self.unclosed(&frame);
+            }
+
+            self.finish(frame, tag_start, tag_start, Strip::default());
+        }
+
+        let frame = self.frames.pop().expect("BUG: the target is a frame");
+        self.finish(frame, tag_start, tag_end, tag.strip());
+    }
+
+    /// Finish a block whose body ends at `body_end` and whose closing tag ends at `end`.
+    fn finish(&mut self, frame: Frame, body_end: usize, end: usize, close_strip: Strip) {
+        let body = Program {
+            body: frame.body,
+            span: Span::new(frame.body_start, body_end),
+        };
+
+        let (program, inverse) = match frame.program {
+            Some(program) => (program, Some(body)),
+            None => (body, None),
+        };
+
+        let span = Span::new(frame.open_span.start(), end);
+
+        let statement = match frame.opener {
+            Opener::Block {
+                call: Some(call),
+                inverted,
+                params,
+                strip,
+            } => Statement::Block(Block {
+                call,
+                inverted,
+                params,
+                program,
+                inverse,
+                open_strip: strip,
+                inverse_strip: frame.inverse_strip,
+                close_strip,
+                span,
+            }),
+            Opener::Partial(Some(partial)) => Statement::PartialBlock(PartialBlock {
+                partial,
+                program,
+                close_strip,
+                span,
+            }),
+            // The opening tag was already reported.
+            _ => return,
+        };
+
+        self.push(statement);
+    }
+
+    fn unclosed(&mut self, frame: &Frame) {
+        self.diagnostics.push(Diagnostic::error(
+            frame.open_span,
+            format!("unclosed {}", frame.description),
+        ));
+    }
+
+    fn push(&mut self, statement: Statement) {
+        match self.frames.last_mut() {
+            Some(frame) => frame.body.push(statement),
+            None => self.root.push(statement),
+        }
+    }
+
+    fn partial(&mut self, tag: &Tag, tokens: &[TokenRef<'_>]) -> Option<Partial> {
+        let call = self.call(tokens, tag.span())?;
+        let mut params = call.params.into_iter();
+        let context = params.next();
+
+        for extra in params {
+            self.diagnostics.push(Diagnostic::error(
+                extra.span(),
+                "a partial takes at most one context",
+            ));
+        }
+
+        Some(Partial {
+            name: call.head,
+            context,
+            hash: call.hash,
+            strip: tag.strip(),
+            span: tag.span(),
+        })
+    }
+
+    /// Split `as |a b|` off of the end of a block's opening tag.
+    fn block_params<'t>(
+        &mut self,
+        tokens: &'t [TokenRef<'t>],
+    ) -> (&'t [TokenRef<'t>], Vec<Spanned<String>>) {
+        let start = tokens.windows(2).position(|pair| {
+            self.is_path(pair[0], "as") && pair[1].item() == &Token::Leaf(HbsToken::Pipe)
+        });
+
+        let start = match start {
+            Some(start) => start,
+            None => return (tokens, vec![]),
+        };
+
+        let mut params = vec![];
+        let mut closed = false;
+
+        for token in &tokens[start + 2..] {
+            match token.item() {
+                Token::Leaf(HbsToken::Pipe) if !closed => closed = true,
+                Token::Leaf(HbsToken::Path) if !closed => params.push(
+                    self.source
+                        .slice(token.span())
+                        .to_string()
+                        .spanned(token.span()),
+                ),
+                _ => self.diagnostics.push(Diagnostic::error(
+                    token.span(),
+                    format!(
+                        "expected a block parameter, found `{}`",
+                        self.source.slice(token.span())
+                    ),
+                )),
+            }
+        }
+
+        if !closed {
+            self.diagnostics.push(Diagnostic::error(
+                tokens[start + 1].span(),
+                "unclosed block parameters",
+            ));
+        }
+
+        (&tokens[..start], params)
+    }
+
+    fn call(&mut self, tokens: &[TokenRef<'_>], span: Span) -> Option<Call> {
+        let (head, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => {
+                self.diagnostics
+                    .push(Diagnostic::error(span, "expected an expression"));
+                return None;
+            }
+        };
+
+        let head = self.expr(head)?;
+        let mut params = vec![];
+        let mut hash = vec![];
+        let mut i = 0;
+
+        while i < rest.len() {
+            let token = rest[i];
+
+            if token.item() == &Token::Leaf(HbsToken::Path)
+                && rest.get(i + 1).map(|token| token.item()) == Some(&Token::Leaf(HbsToken::Equals))
+            {
+                let key = self.source.slice(token.span());
+
+                match rest.get(i + 2) {
+                    Some(value) => {
+                        if let Some(value) = self.expr(value) {
+                            hash.push(HashPair {
+                                key: key.to_string().spanned(token.span()),
+                                value,
+                            });
+                        }
+                    }
+                    None => self.diagnostics.push(Diagnostic::error(
+                        rest[i + 1].span(),
+                        format!("expected a value after `{}=`", key),
+                    )),
+                }
+
+                i += 3;
+                continue;
+            }
+
+            if !hash.is_empty() {
+                self.diagnostics.push(Diagnostic::error(
+                    token.span(),
+                    "positional arguments have to come before hash arguments",
+                ));
+            } else if let Some(param) = self.expr(token) {
+                params.push(param);
+            }
+
+            i += 1;
+        }
+
+        let end = tokens[tokens.len() - 1].span().end();
+
+        Some(Call {
+            span: Span::new(head.span().start(), end),
+            head,
+            params,
+            hash,
+        })
+    }
+
+    fn expr(&mut self, token: TokenRef<'_>) -> Option<Spanned<Expr>> {
+        let span = token.span();
+        let text = self.source.slice(span);
+
+        let expr = match token.item() {
+            Token::Leaf(HbsToken::Path) => Expr::Path(path(text, span)),
+            Token::Leaf(HbsToken::String) => Expr::String(unquote(text)),
+            Token::Leaf(HbsToken::Number) => Expr::Number(text.to_string()),
+            Token::Leaf(HbsToken::Boolean) => Expr::Boolean(text == "true"),
+            Token::Leaf(HbsToken::Null) => Expr::Null,
+            Token::Leaf(HbsToken::Undefined) => Expr::Undefined,
+            Token::Delimited(delimited) => {
+                let children = significant(delimited.children());
+                Expr::SubExpression(Box::new(self.call(&children, span)?))
+            }
+            // The token tree already reported input that the lexer couldn't read.
+            Token::Leaf(HbsToken::Error) => return None,
+            Token::Leaf(_) => {
+                self.diagnostics.push(Diagnostic::error(
+                    span,
+                    format!("expected an expression, found `{}`", text),
+                ));
+                return None;
+            }
+        };
+
+        Some(expr.spanned(span))
+    }
+
+    fn is_else(&self, token: Option<&TokenRef<'_>>) -> bool {
+        token.is_some_and(|token| self.is_path(token, "else"))
+    }
+
+    fn is_path(&self, token: TokenRef<'_>, text: &str) -> bool {
+        token.item() == &Token::Leaf(HbsToken::Path) && self.source.slice(token.span()) == text
+    }
+
+    /// The text of the first token of a tag, which names the block that it opens or closes.
+    fn name(&self, token: Option<&TokenRef<'_>>) -> String {
+        token
+            .map(|token| self.source.slice(token.span()).to_string())
+            .unwrap_or_default()
+    }
+}
+
+fn path(text: &str, span: Span) -> Path {
+    let start: usize = span.start().into();
+    let data = text.starts_with('@');
+    let mut offset = if data { 1 } else { 0 };
+    let mut depth = 0;
+    let mut parts = vec![];
+
+    while offset < text.len() {
+        let rest = &text[offset..];
+        let len = if rest.starts_with('[') {
+            rest.find(']').map_or(rest.len(), |end| end + 1)
+        } else if rest.starts_with("..") {
+            2
+        } else if rest.starts_with('.') {
+            1
+        } else {
+            rest.find(['.', '/']).unwrap_or(rest.len())
+        };
+
+        let segment = &rest[..len];
+
+        match segment {
+            ".." if parts.is_empty() => depth += 1,
+            "." | "this" if parts.is_empty() => {}
+            _ => match segment.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                Some(literal) => parts.push(
+                    literal
+                        .to_string()
+                        .spanned(Span::new(start + offset + 1, start + offset + len - 1)),
+                ),
+                None => parts.push(
+                    segment
+                        .to_string()
+                        .spanned(Span::new(start + offset, start + offset + len)),
+                ),
+            },
+        }
+
+        // Skip the separator.
+        offset += len + 1;
+    }
+
+    Path { data, depth, parts }
+}
+
+/// The contents of a string literal, with `\` escapes removed.
+fn unquote(text: &str) -> String {
+    let mut chars = text[1..text.len() - 1].chars();
+    let mut unquoted = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.extend(chars.next()),
+            c => unquoted.push(c),
+        }
+    }
+
+    unquoted
+}
+
+unit_tests!(
+    all({
+        /// A compact rendering of a program: content is quoted, mustaches are in braces, blocks
+        /// are `#name[params](program)else(inverse)`, partials start with `>` and comments are `!`.
+        fn describe(program: &Program, source: &Source) -> String {
+            program
+                .body()
+                .iter()
+                .map(|statement| match statement {
+                    Statement::Content(content) => format!("{:?}", source.slice(content.text())),
+                    Statement::Mustache(mustache) if mustache.is_escaped() => {
+                        format!("{{{}}}", call(mustache.call(), source))
+                    }
+                    Statement::Mustache(mustache) => {
+                        format!("{{&{}}}", call(mustache.call(), source))
+                    }
+                    Statement::Block(block) => format!(
+                        "{}{}[{}]({}){}",
+                        if block.is_inverted() { "^" } else { "#" },
+                        call(block.call(), source),
+                        block
+                            .params()
+                            .iter()
+                            .map(|param| param.item().as_str())
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        describe(block.program(), source),
+                        block
+                            .inverse()
+                            .map(|inverse| format!("else({})", describe(inverse, source)))
+                            .unwrap_or_default()
+                    ),
+                    Statement::Partial(partial) => partial_text(partial, source),
+                    Statement::PartialBlock(block) => format!(
+                        "#{}({})",
+                        partial_text(block.partial(), source),
+                        describe(block.program(), source)
+                    ),
+                    Statement::Comment(_) => "!".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+
+        fn partial_text(partial: &Partial, source: &Source) -> String {
+            let mut text = format!(">{}", expr(partial.name(), source));
+
+            if let Some(context) = partial.context() {
+                text.push_str(&format!(" {}", expr(context, source)));
+            }
+
+            for pair in partial.hash() {
+                text.push_str(&format!(
+                    " {}={}",
+                    pair.key().item(),
+                    expr(pair.value(), source)
+                ));
+            }
+
+            text
+        }
+
+        fn call(call: &Call, source: &Source) -> String {
+            let mut text = expr(call.head(), source);
+
+            for param in call.params() {
+                text.push_str(&format!(" {}", expr(param, source)));
+            }
+
+            for pair in call.hash() {
+                text.push_str(&format!(
+                    " {}={}",
+                    pair.key().item(),
+                    expr(pair.value(), source)
+                ));
+            }
+
+            text
+        }
+
+        fn expr(expr: &Spanned<Expr>, source: &Source) -> String {
+            match expr.item() {
+                Expr::Path(path) if path.parts().is_empty() => "this".to_string(),
+                Expr::Path(path) => format!(
+                    "{}{}{}",
+                    if path.is_data() { "@" } else { "" },
+                    "../".repeat(path.depth()),
+                    path.name()
+                ),
+                Expr::SubExpression(inner) => format!("({})", call(inner, source)),
+                Expr::String(string) => format!("{:?}", string),
+                Expr::Number(number) => number.clone(),
+                Expr::Boolean(_) | Expr::Null | Expr::Undefined => {
+                    source.slice(expr.span()).to_string()
+                }
+            }
+        }
+
+        fn read(text: &str) -> (String, Vec<Diagnostic>) {
+            let source = Source::new("<test>", text);
+            let read = read_template(&source);
+
+            (
+                describe(read.template(), &source),
+                read.diagnostics().to_vec(),
+            )
+        }
+    }),
+    tests(
+        ("templates", {
+            let (template, diagnostics) = read(
+                "{{!-- header --}}<h1>{{title}}</h1>\n\
+                 {{#each people as |person i|~}}\n  \
+                   {{{person.[first name]}}} {{link (concat ../base 'a\\'b') class=@root.cls}}\n\
+                 {{~else}}nobody{{/each}}\
+                 {{> card this size=2}}{{#> layout}}{{&body}}{{/layout}}",
+            );
+
+            assert_eq!(diagnostics, &[]);
+            assert_eq!(
+                template,
+                "! \"<h1>\" {title} \"</h1>\\n\" #each people[person i](\"\" {&person.first name} \
+                 \" \" {link (concat ../base \"a'b\") class=@root.cls} \"\")else(\"nobody\") \
+                 >card this size=2 #>layout({&body})"
+            );
+        }),
+        ("chained else", {
+            let (template, diagnostics) =
+                read("{{#if a}}1{{else if b}}2{{^}}3{{/if}}{{^list}}none{{/list}}");
+
+            assert_eq!(diagnostics, &[]);
+            assert_eq!(
+                template,
+                "#if a[](\"1\")else(#if b[](\"2\")else(\"3\")) ^list[](\"none\")"
+            );
+
+            let source = Source::new("<test>", "{{#if a}}1{{else if b}}2{{/if}}");
+            let read = read_template(&source);
+            let outer = match &read.template().body()[0] {
+                Statement::Block(block) => block,
+                _ => panic!("expected a block"),
+            };
+            let inner = match &outer.inverse().unwrap().body()[0] {
+                Statement::Block(block) => block,
+                _ => panic!("expected a chained block"),
+            };
+
+            assert_eq!(outer.span(), Span::new(0, 31));
+            assert_eq!(inner.span(), Span::new(10, 24));
+            assert_eq!(inner.program().span(), Span::new(23, 24));
+        }),
+        ("mismatched closers", {
+            let (template, diagnostics) =
+                read("{{#each xs}}{{#if x}}a{{/each}}{{/unless}}{{#with y}}b{{/if}}{{else}}");
+
+            assert_eq!(
+                diagnostics,
+                &[
+                    Diagnostic::error(Span::new(12, 21), "unclosed `{{#if}}`"),
+                    Diagnostic::error(Span::new(31, 42), "`{{/unless}}` doesn't close a block"),
+                    Diagnostic::error(Span::new(54, 61), "`{{/if}}` doesn't match `{{#with}}`"),
+                    Diagnostic::note(Span::new(42, 53), "`{{#with}}` starts here"),
+                    Diagnostic::error(Span::new(61, 69), "`{{else}}` outside of a block"),
+                ]
+            );
+            assert_eq!(template, "#each xs[](#if x[](\"a\")) #with y[](\"b\")");
+        }),
+        ("hello.hbs", {
+            let source = Source::new("hello.hbs", include_str!("../../../hello.hbs"));
+            let read = read_template(&source);
+
+            assert_eq!(read.diagnostics(), &[]);
+            assert_eq!(describe(read.template(), &source), "\"hello\"");
+        })
+    )
+);
@@ -0,0 +1,62 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use wyst_lex::{wyst_source::Source, DefaultDelegate, FlatToken};
+
+const ITEM: &str = r#"function item(arg, [1, 2, 3]) {
+    if (check(arg)) {
+        return { key: "value", nested: [a, (b), { c }] };
+    }
+
+    call(arg) # comment
+}
+"#;
+
+/// Build a source with `items` copies of `ITEM`, nested `depth` levels deep.
+fn large_source(items: usize, depth: usize) -> Source {
+    let mut contents = String::new();
+
+    for _ in 0..depth {
+        contents.push_str("outer {\n");
+    }
+
+    for _ in 0..items {
+        contents.push_str(ITEM);
+    }
+
+    for _ in 0..depth {
+        contents.push_str("}\n");
+    }
+
+    Source::new("bench.txt", contents)
+}
+
+fn read(c: &mut Criterion) {
+    let mut group = c.benchmark_group("read");
+
+    for items in [100, 1_000, 10_000].iter().copied() {
+        let source = large_source(items, 8);
+        group.throughput(Throughput::Bytes(source.contents().len() as u64));
+
+        group.bench_with_input(
+            BenchmarkId::new("TokenTree", items),
+            &source,
+            |b, source| {
+                b.iter(|| {
+                    let tokens: Vec<_> =
+                        FlatToken::read_source::<DefaultDelegate>(source).collect();
+                    black_box(tokens)
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("TokenArena", items),
+            &source,
+            |b, source| b.iter(|| black_box(FlatToken::read_arena::<DefaultDelegate>(source))),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, read);
+criterion_main!(benches);
@@ -0,0 +1,147 @@
+//! `proptest` strategies for sources and token trees, for property-testing tools built on
+//! `wyst-lex`. These are only available with the `proptest` feature.
+//!
+//! The strategies generate an abstract [Shape] first, and render it to source text. Shrinking
+//! happens on the shape, so a shrunk source always has balanced delimiters and still lexes
+//! without errors.
+
+use proptest::prelude::*;
+use wyst_core::{unit_tests, wyst_data};
+use wyst_source::{Source, Spanned};
+
+use crate::{delegate::DefaultDelegate, standard::Delimiter, standard::FlatToken, tree::Token};
+
+/// The abstract structure of a generated source.
+#[wyst_data]
+pub enum Shape {
+    Word(String),
+    Quoted(String),
+    Delimited(Delimiter, Vec<Shape>),
+}
+
+impl Shape {
+    /// Render a list of sibling shapes, separating them with `separators`.
+    fn render_list(shapes: &[Shape], separators: &[Separator], out: &mut String) {
+        for (i, shape) in shapes.iter().enumerate() {
+            if i > 0 {
+                separators[(i - 1) % separators.len()].render(out);
+            }
+
+            shape.render(out);
+        }
+    }
+
+    fn render(&self, out: &mut String) {
+        match self {
+            Shape::Word(word) => out.push_str(word),
+            Shape::Quoted(contents) => {
+                out.push('"');
+                out.push_str(contents);
+                out.push('"');
+            }
+            Shape::Delimited(delimiter, children) => {
+                out.push(delimiter.open_char());
+
+                // A quoted token needs to be followed by whitespace, so the children are always
+                // surrounded by spaces.
+                if !children.is_empty() {
+                    out.push(' ');
+                    Shape::render_list(children, &[Separator::Space(1)], out);
+                    out.push(' ');
+                }
+
+                out.push(delimiter.close_char());
+            }
+        }
+    }
+}
+
+/// The whitespace between two sibling shapes.
+#[wyst_data]
+enum Separator {
+    Space(usize),
+    Newlines(usize),
+}
+
+impl Separator {
+    fn render(&self, out: &mut String) {
+        match self {
+            Separator::Space(n) => out.push_str(&" ".repeat(*n)),
+            Separator::Newlines(n) => out.push_str(&"\n".repeat(*n)),
+        }
+    }
+}
+
+fn delimiter() -> impl Strategy<Value = Delimiter> {
+    prop_oneof![
+        Just(Delimiter::Paren),
+        Just(Delimiter::Bracket),
+        Just(Delimiter::Brace),
+    ]
+}
+
+fn separator() -> impl Strategy<Value = Separator> {
+    prop_oneof![
+        3 => (1..3usize).prop_map(Separator::Space),
+        1 => (1..3usize).prop_map(Separator::Newlines),
+    ]
+}
+
+/// A shape with balanced delimiters, nested at most `depth` levels deep.
+pub fn shape(depth: u32) -> impl Strategy<Value = Shape> {
+    let leaf = prop_oneof![
+        4 => "[a-z][a-z0-9_.,:;=+-]{0,7}".prop_map(Shape::Word),
+        1 => "[a-z]{1,6}".prop_map(Shape::Quoted),
+    ];
+
+    leaf.prop_recursive(depth, 64, 6, |inner| {
+        (delimiter(), prop::collection::vec(inner, 0..6))
+            .prop_map(|(delimiter, children)| Shape::Delimited(delimiter, children))
+    })
+}
+
+/// Source text that lexes and reads (with the [DefaultDelegate]) without any diagnostics.
+pub fn source_text() -> impl Strategy<Value = String> {
+    (
+        prop::collection::vec(shape(4), 0..12),
+        prop::collection::vec(separator(), 1..4),
+    )
+        .prop_map(|(shapes, separators)| {
+            let mut out = String::new();
+            Shape::render_list(&shapes, &separators, &mut out);
+            out
+        })
+}
+
+/// A [Source] containing [source_text].
+pub fn source() -> impl Strategy<Value = Source> {
+    source_text().prop_map(|text| Source::new("<generated>", text))
+}
+
+/// A source, together with its token trees.
+pub fn token_trees() -> impl Strategy<Value = (Source, Vec<Spanned<Token>>)> {
+    source().prop_map(|source| {
+        let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+        (source, tokens)
+    })
+}
+
+unit_tests!(
+    all({
+        use crate::arena::TokenArena;
+    }),
+    tests(
+        ("generated sources read cleanly", {
+            proptest!(|(source in source())| {
+                let result = FlatToken::read_tree::<DefaultDelegate>(&source);
+                prop_assert_eq!(result.diagnostics(), &[]);
+            });
+        }),
+        ("generated token trees match the arena", {
+            proptest!(|((source, tokens) in token_trees())| {
+                let arena: TokenArena = FlatToken::read_arena::<DefaultDelegate>(&source);
+                prop_assert_eq!(arena.to_tokens(), tokens);
+            });
+        })
+    )
+);
@@ -0,0 +1,524 @@
+use std::fmt::Debug;
+
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_source::{AddSpan, Diagnostic, Offset, Source, Span, Spanned};
+
+use crate::{
+    kind::{LeafKind, TokenKind},
+    reader::{
+        unclosed_delimiter, unexpected_close, unexpected_input, Closing, ReadTokens, Reader,
+        ReaderNext,
+    },
+    standard::Delimiter,
+    tree::{Leaf, Token},
+};
+
+/// The index of a token inside of a [TokenArena].
+#[wyst_copy]
+pub struct TokenId {
+    index: u32,
+}
+
+impl TokenId {
+    fn new(index: usize) -> TokenId {
+        TokenId {
+            index: index as u32,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+#[wyst_copy]
+enum ArenaKind<L>
+where
+    L: LeafKind,
+{
+    Leaf(L),
+    /// `end` is the index immediately after the last descendant of this token. The descendants of
+    /// a delimited token are stored contiguously after it, so its children are found by starting
+    /// at the next index and skipping over each child's descendants.
+    Delimited {
+        delimiter: Delimiter,
+        end: TokenId,
+        closed: bool,
+    },
+}
+
+#[wyst_copy]
+struct ArenaToken<L>
+where
+    L: LeafKind,
+{
+    span: Span,
+    kind: ArenaKind<L>,
+    parent: Option<TokenId>,
+}
+
+/// `TokenArena` is a flat representation of a token tree. Every token, no matter how deeply it is
+/// nested, is stored in a single `Vec` in source order. A delimited token remembers the range of
+/// its descendants and every token remembers its parent.
+///
+/// Building a `TokenArena` doesn't move tokens around or allocate a `Vec` per delimiter, which
+/// makes it considerably cheaper than [Token] trees for large sources. The tree structure is
+/// exposed through [NodeRef] and [TokenRef], which borrow from the arena.
+#[wyst_data]
+pub struct TokenArena<L: LeafKind = Leaf> {
+    tokens: Vec<ArenaToken<L>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<L: LeafKind> Default for TokenArena<L> {
+    fn default() -> Self {
+        TokenArena {
+            tokens: vec![],
+            diagnostics: vec![],
+        }
+    }
+}
+
+impl<L: LeafKind> TokenArena<L> {
+    /// Read a stream of tokens of any [TokenKind] into an arena.
+    pub fn read<K>(source: &Source, tokens: impl Iterator<Item = Spanned<K>>) -> TokenArena<L>
+    where
+        K: TokenKind<Leaf = L>,
+    {
+        ReadTokens::new(ArenaBuilder::new(), tokens, source)
+            .next()
+            .expect("BUG: the arena builder didn't produce an arena")
+    }
+
+    /// The diagnostics that were reported while reading the arena.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn get(&self, id: TokenId) -> NodeRef<'_, L> {
+        assert!(
+            id.index() < self.tokens.len(),
+            "TokenId({}) is out of bounds for an arena of {} tokens",
+            id.index(),
+            self.tokens.len()
+        );
+
+        NodeRef { arena: self, id }
+    }
+
+    /// The top-level tokens in the arena.
+    pub fn roots(&self) -> Children<'_, L> {
+        Children {
+            arena: self,
+            next: 0,
+            end: self.tokens.len(),
+        }
+    }
+
+    /// Every token in the arena, in source order (a parent comes before its children).
+    pub fn iter(&self) -> impl Iterator<Item = NodeRef<'_, L>> + '_ {
+        (0..self.tokens.len()).map(move |index| NodeRef {
+            arena: self,
+            id: TokenId::new(index),
+        })
+    }
+
+    /// Convert the arena into the owned [Token] representation.
+    pub fn to_tokens(&self) -> Vec<Spanned<Token<L>>> {
+        self.roots().map(|node| node.to_token()).collect()
+    }
+
+    fn token(&self, id: TokenId) -> ArenaToken<L> {
+        self.tokens[id.index()]
+    }
+}
+
+/// A borrowed view of a single token in a [TokenArena].
+pub struct NodeRef<'arena, L: LeafKind = Leaf> {
+    arena: &'arena TokenArena<L>,
+    id: TokenId,
+}
+
+impl<'arena, L: LeafKind> Clone for NodeRef<'arena, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'arena, L: LeafKind> Copy for NodeRef<'arena, L> {}
+
+impl<'arena, L: LeafKind> NodeRef<'arena, L> {
+    pub fn id(self) -> TokenId {
+        self.id
+    }
+
+    pub fn span(self) -> Span {
+        self.arena.token(self.id).span
+    }
+
+    pub fn token(self) -> TokenRef<'arena, L> {
+        match self.arena.token(self.id).kind {
+            ArenaKind::Leaf(leaf) => TokenRef::Leaf(leaf),
+            ArenaKind::Delimited {
+                delimiter,
+                end,
+                closed,
+            } => TokenRef::Delimited(DelimitedRef {
+                arena: self.arena,
+                id: self.id,
+                delimiter,
+                end,
+                closed,
+            }),
+        }
+    }
+
+    pub fn parent(self) -> Option<NodeRef<'arena, L>> {
+        self.arena.token(self.id).parent.map(|id| NodeRef {
+            arena: self.arena,
+            id,
+        })
+    }
+
+    /// Convert this token (and all of its descendants) into the owned [Token] representation.
+    pub fn to_token(self) -> Spanned<Token<L>> {
+        match self.token() {
+            TokenRef::Leaf(leaf) => Token::Leaf(leaf).spanned(self.span()),
+            TokenRef::Delimited(delimited) => {
+                let children = delimited.children().map(|child| child.to_token());
+
+                if delimited.is_closed() {
+                    Token::delimited(delimited.delimiter(), children).spanned(self.span())
+                } else {
+                    Token::unclosed(delimited.delimiter(), children).spanned(self.span())
+                }
+            }
+        }
+    }
+
+    fn next_sibling_index(self) -> usize {
+        match self.arena.token(self.id).kind {
+            ArenaKind::Leaf(_) => self.id.index() + 1,
+            ArenaKind::Delimited { end, .. } => end.index(),
+        }
+    }
+}
+
+impl<'arena, L: LeafKind> Debug for NodeRef<'arena, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NodeRef({}, {:?})", self.id.index(), self.span())
+    }
+}
+
+/// The borrowing equivalent of [Token].
+#[derive(Debug, Clone, Copy)]
+pub enum TokenRef<'arena, L: LeafKind = Leaf> {
+    Leaf(L),
+    Delimited(DelimitedRef<'arena, L>),
+}
+
+/// The borrowing equivalent of [crate::Delimited].
+pub struct DelimitedRef<'arena, L: LeafKind = Leaf> {
+    arena: &'arena TokenArena<L>,
+    id: TokenId,
+    delimiter: Delimiter,
+    end: TokenId,
+    closed: bool,
+}
+
+impl<'arena, L: LeafKind> Clone for DelimitedRef<'arena, L> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'arena, L: LeafKind> Copy for DelimitedRef<'arena, L> {}
+
+impl<'arena, L: LeafKind> DelimitedRef<'arena, L> {
+    pub fn delimiter(self) -> Delimiter {
+        self.delimiter
+    }
+
+    /// See [crate::Delimited::is_closed].
+    pub fn is_closed(self) -> bool {
+        self.closed
+    }
+
+    pub fn children(self) -> Children<'arena, L> {
+        Children {
+            arena: self.arena,
+            next: self.id.index() + 1,
+            end: self.end.index(),
+        }
+    }
+}
+
+impl<'arena, L: LeafKind> Debug for DelimitedRef<'arena, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.children()).finish()
+    }
+}
+
+/// An iterator over sibling tokens in a [TokenArena].
+pub struct Children<'arena, L: LeafKind = Leaf> {
+    arena: &'arena TokenArena<L>,
+    next: usize,
+    end: usize,
+}
+
+impl<'arena, L: LeafKind> Iterator for Children<'arena, L> {
+    type Item = NodeRef<'arena, L>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.end {
+            return None;
+        }
+
+        let node = NodeRef {
+            arena: self.arena,
+            id: TokenId::new(self.next),
+        };
+
+        self.next = node.next_sibling_index();
+        Some(node)
+    }
+}
+
+/// `ArenaBuilder` is a [Reader] that builds a [TokenArena]. It produces a single item (the whole
+/// arena) once it has seen the EOF token.
+pub(crate) struct ArenaBuilder<L: LeafKind> {
+    arena: TokenArena<L>,
+    open: Vec<TokenId>,
+    done: bool,
+    emitted: bool,
+}
+
+impl<L: LeafKind> ArenaBuilder<L> {
+    pub(crate) fn new() -> ArenaBuilder<L> {
+        ArenaBuilder {
+            arena: TokenArena::default(),
+            open: vec![],
+            done: false,
+            emitted: false,
+        }
+    }
+
+    fn push(&mut self, kind: ArenaKind<L>, span: Span) -> TokenId {
+        let id = TokenId::new(self.arena.tokens.len());
+
+        self.arena.tokens.push(ArenaToken {
+            span,
+            kind,
+            parent: self.open.last().copied(),
+        });
+
+        id
+    }
+
+    fn push_leaf(&mut self, leaf: L, span: Span) {
+        self.push(ArenaKind::Leaf(leaf), span);
+    }
+
+    fn delimiter(&self, id: TokenId) -> Delimiter {
+        match self.arena.token(id).kind {
+            ArenaKind::Delimited { delimiter, .. } => delimiter,
+            ArenaKind::Leaf(leaf) => {
+                unreachable!("BUG: {:?} was pushed as an open delimiter", leaf)
+            }
+        }
+    }
+
+    /// Close the innermost open delimiter at `end`.
+    fn close_parent(&mut self, end: Offset, closed: bool) {
+        let id = self
+            .open
+            .pop()
+            .expect("BUG: close_parent called without an open delimiter");
+        let end_id = TokenId::new(self.arena.tokens.len());
+        let token = &mut self.arena.tokens[id.index()];
+
+        token.span = Span::new(token.span.start(), end);
+
+        if let ArenaKind::Delimited {
+            end, closed: flag, ..
+        } = &mut token.kind
+        {
+            *end = end_id;
+            *flag = closed;
+        }
+    }
+
+    /// Report the innermost open delimiter as unclosed, and close it at `end`.
+    fn close_unclosed(&mut self, end: Offset) {
+        if let Some(id) = self.open.last().copied() {
+            let delimiter = self.delimiter(id);
+            let open = self.arena.token(id).span.start();
+
+            self.arena.diagnostics.push(unclosed_delimiter(
+                delimiter,
+                open.char_span(delimiter.open_char()),
+            ));
+        }
+
+        self.close_parent(end, false);
+    }
+}
+
+impl<L: LeafKind> Reader<L> for ArenaBuilder<L> {
+    type Item = TokenArena<L>;
+
+    fn next(&mut self) -> ReaderNext<TokenArena<L>> {
+        if self.emitted {
+            ReaderNext::EOF
+        } else if self.done {
+            self.emitted = true;
+            ReaderNext::Token(std::mem::take(&mut self.arena))
+        } else {
+            ReaderNext::Buffer
+        }
+    }
+
+    fn leaf(&mut self, _source: &Source, span: Span, leaf: L) {
+        self.push_leaf(leaf, span);
+    }
+
+    fn open(&mut self, _source: &Source, span: Span, delimiter: Delimiter) {
+        // The end of the delimited token isn't known until it's closed.
+        let end = TokenId::new(self.arena.tokens.len() + 1);
+        let id = self.push(
+            ArenaKind::Delimited {
+                delimiter,
+                end,
+                closed: false,
+            },
+            span,
+        );
+        self.open.push(id);
+    }
+
+    fn close(&mut self, _source: &Source, span: Span, delimiter: Delimiter) {
+        let open = self.open.iter().rev().map(|id| self.delimiter(*id));
+
+        match Closing::find(open, delimiter) {
+            Closing::Matches { unclosed } => {
+                for _ in 0..unclosed {
+                    self.close_unclosed(span.start());
+                }
+
+                self.close_parent(span.end(), true);
+            }
+            Closing::Unexpected => {
+                self.arena
+                    .diagnostics
+                    .push(unexpected_close(delimiter, span));
+                self.push_leaf(L::error(), span);
+            }
+        }
+    }
+
+    fn eof(&mut self, _source: &Source, span: Span) {
+        self.done = true;
+
+        while !self.open.is_empty() {
+            self.close_unclosed(span.start());
+        }
+
+        self.push_leaf(L::eof(), span);
+    }
+
+    fn error(&mut self, _source: &Source, span: Span) {
+        self.arena.diagnostics.push(unexpected_input(span));
+        self.push_leaf(L::error(), span);
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::delegate::DefaultDelegate;
+        use crate::standard::FlatToken;
+        use std::sync::Arc;
+
+        fn source(string: &str) -> Arc<Source> {
+            Arc::new(Source::new("<test>", string))
+        }
+
+        fn assert_same_as_tree(string: &str) {
+            let s = source(string);
+            let tree = FlatToken::read_tree::<DefaultDelegate>(&s);
+            let arena = FlatToken::read_arena::<DefaultDelegate>(&s);
+
+            assert_eq!(arena.to_tokens(), tree.tokens());
+            assert_eq!(arena.diagnostics(), tree.diagnostics());
+        }
+    }),
+    tests(
+        ("same as TokenTree", {
+            assert_same_as_tree("");
+            assert_same_as_tree("   ");
+            assert_same_as_tree("   \n   ");
+            assert_same_as_tree("   hello { world() }   ");
+            assert_same_as_tree(r#"   hello( "world" )   "#);
+            assert_same_as_tree("a [b (c {d} e) f] g\n{ [ ( ) ] }");
+        }),
+        ("same as TokenTree (unbalanced)", {
+            assert_same_as_tree("a ) b");
+            assert_same_as_tree("( a");
+            assert_same_as_tree("{ ( }");
+            assert_same_as_tree("{ [ ( ] ) } }");
+        }),
+        ("children and parents", {
+            let s = source("a (b [c] d) e");
+            let arena = FlatToken::read_arena::<DefaultDelegate>(&s);
+
+            let roots: Vec<_> = arena.roots().map(|node| s.slice(node.span())).collect();
+            assert_eq!(roots, &["a", " ", "(b [c] d)", " ", "e", ""]);
+
+            let paren = arena.roots().nth(2).unwrap();
+            assert!(paren.parent().is_none());
+
+            let children = match paren.token() {
+                TokenRef::Delimited(delimited) => {
+                    assert_eq!(delimited.delimiter(), Delimiter::Paren);
+                    delimited.children().collect::<Vec<_>>()
+                }
+                TokenRef::Leaf(leaf) => panic!("expected a delimited token, got {:?}", leaf),
+            };
+
+            let texts: Vec<_> = children.iter().map(|node| s.slice(node.span())).collect();
+            assert_eq!(texts, &["b", " ", "[c]", " ", "d"]);
+
+            for child in children.iter() {
+                assert_eq!(child.parent().map(|p| p.id()), Some(paren.id()));
+            }
+
+            let bracket = children[2];
+            let c = match bracket.token() {
+                TokenRef::Delimited(delimited) => delimited.children().next().unwrap(),
+                TokenRef::Leaf(leaf) => panic!("expected a delimited token, got {:?}", leaf),
+            };
+
+            assert_eq!(s.slice(c.span()), "c");
+            assert_eq!(c.parent().map(|p| p.id()), Some(bracket.id()));
+            assert_eq!(
+                c.parent().and_then(|p| p.parent()).map(|p| p.id()),
+                Some(paren.id())
+            );
+        }),
+        ("iter (source order)", {
+            let s = source("(a)");
+            let arena = FlatToken::read_arena::<DefaultDelegate>(&s);
+            let texts: Vec<_> = arena.iter().map(|node| s.slice(node.span())).collect();
+
+            assert_eq!(arena.len(), 3);
+            assert_eq!(texts, &["(a)", "a", ""]);
+        })
+    )
+);
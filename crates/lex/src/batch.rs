@@ -0,0 +1,248 @@
+use std::{
+    borrow::Borrow,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rayon::prelude::*;
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_source::{Diagnostic, Source};
+
+use crate::{delegate::StandardDelegate, standard::FlatToken, tree::ReadResult};
+
+/// Options for [read_batch].
+#[wyst_copy]
+#[derive(Default)]
+pub struct BatchOptions {
+    threads: Option<usize>,
+    stop_on_error: bool,
+}
+
+impl BatchOptions {
+    /// Use a dedicated thread pool with `threads` threads, rather than rayon's global pool. If the
+    /// pool can't be built (because the threads can't be spawned), the sources are read one after
+    /// another on the current thread instead.
+    pub fn threads(self, threads: usize) -> BatchOptions {
+        BatchOptions {
+            threads: Some(threads),
+            ..self
+        }
+    }
+
+    /// Stop reading sources after the first source with an error.
+    pub fn stop_on_error(self) -> BatchOptions {
+        BatchOptions {
+            stop_on_error: true,
+            ..self
+        }
+    }
+}
+
+/// The result of [read_batch].
+#[wyst_data]
+pub struct BatchResult {
+    results: Vec<ReadResult>,
+    stopped_at: Option<usize>,
+}
+
+impl BatchResult {
+    /// The token trees, in the same order as the sources that were passed to [read_batch]. If the
+    /// batch stopped early, this ends with the first source that had an error.
+    pub fn results(&self) -> &[ReadResult] {
+        &self.results
+    }
+
+    /// The index of the first source with an error, if the batch stopped early because of it.
+    pub fn stopped_at(&self) -> Option<usize> {
+        self.stopped_at
+    }
+
+    /// All of the diagnostics in the batch, paired with the index of their source.
+    pub fn diagnostics(&self) -> impl Iterator<Item = (usize, &Diagnostic)> + '_ {
+        self.results
+            .iter()
+            .enumerate()
+            .flat_map(|(index, result)| result.diagnostics().iter().map(move |d| (index, d)))
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.results.iter().any(|result| result.has_errors())
+    }
+
+    pub fn into_results(self) -> Vec<ReadResult> {
+        self.results
+    }
+}
+
+/// Lex and read many sources into token trees in parallel.
+///
+/// The results are always in the same order as `sources`. When the options ask to stop on the
+/// first error, sources that come after the first source with an error are skipped, but every
+/// source before it is still read, so the result doesn't depend on how the work was scheduled.
+pub fn read_batch<S, T>(sources: &[T], options: BatchOptions) -> BatchResult
+where
+    S: for<'source> StandardDelegate<'source>,
+    T: Borrow<Source> + Sync,
+{
+    let threads = match options.threads {
+        None => return read_batch_in_pool::<S, T>(sources, options),
+        Some(threads) => threads,
+    };
+
+    match rayon::ThreadPoolBuilder::new().num_threads(threads).build() {
+        Ok(pool) => pool.install(|| read_batch_in_pool::<S, T>(sources, options)),
+        Err(_) => read_batch_sequentially::<S, T>(sources, options),
+    }
+}
+
+/// Read the sources in order on the current thread, with the same results as
+/// [read_batch_in_pool].
+fn read_batch_sequentially<S, T>(sources: &[T], options: BatchOptions) -> BatchResult
+where
+    S: for<'source> StandardDelegate<'source>,
+    T: Borrow<Source>,
+{
+    let mut results = vec![];
+    let mut stopped_at = None;
+
+    for (index, source) in sources.iter().enumerate() {
+        let result = FlatToken::read_tree::<S>(source.borrow());
+        let has_errors = result.has_errors();
+        results.push(result);
+
+        if options.stop_on_error && has_errors {
+            stopped_at = Some(index);
+            break;
+        }
+    }
+
+    BatchResult {
+        results,
+        stopped_at,
+    }
+}
+
+fn read_batch_in_pool<S, T>(sources: &[T], options: BatchOptions) -> BatchResult
+where
+    S: for<'source> StandardDelegate<'source>,
+    T: Borrow<Source> + Sync,
+{
+    // The lowest index of a source with an error (or `usize::MAX` if there hasn't been one yet).
+    let first_error = AtomicUsize::new(usize::MAX);
+
+    let results: Vec<Option<ReadResult>> = sources
+        .par_iter()
+        .enumerate()
+        .map(|(index, source)| {
+            if options.stop_on_error && index > first_error.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            let result = FlatToken::read_tree::<S>(source.borrow());
+
+            if result.has_errors() {
+                first_error.fetch_min(index, Ordering::Relaxed);
+            }
+
+            Some(result)
+        })
+        .collect();
+
+    let stopped_at = match first_error.into_inner() {
+        index if options.stop_on_error && index != usize::MAX => Some(index),
+        _ => None,
+    };
+
+    let results = results
+        .into_iter()
+        .take(stopped_at.map(|index| index + 1).unwrap_or(sources.len()))
+        .map(|result| result.expect("BUG: a source before the first error was skipped"))
+        .collect();
+
+    BatchResult {
+        results,
+        stopped_at,
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::delegate::DefaultDelegate;
+        use wyst_source::Span;
+
+        fn sources(contents: &[&str]) -> Vec<Source> {
+            contents
+                .iter()
+                .enumerate()
+                .map(|(i, contents)| Source::new(format!("{}.txt", i), *contents))
+                .collect()
+        }
+    }),
+    tests(
+        ("read_batch (same as read_tree, in order)", {
+            let inputs: Vec<String> = (0..100).map(|i| format!("item{} ( {} )", i, i)).collect();
+            let inputs: Vec<&str> = inputs.iter().map(|s| s.as_str()).collect();
+            let sources = sources(&inputs);
+
+            let batch =
+                read_batch::<DefaultDelegate, _>(&sources, BatchOptions::default().threads(4));
+
+            assert_eq!(batch.results().len(), sources.len());
+            assert_eq!(batch.stopped_at(), None);
+            assert!(!batch.has_errors());
+
+            for (source, result) in sources.iter().zip(batch.results()) {
+                assert_eq!(result, &FlatToken::read_tree::<DefaultDelegate>(source));
+            }
+        }),
+        ("read_batch (aggregates diagnostics)", {
+            let sources = sources(&["a", "b )", "( c", "d"]);
+            let batch = read_batch::<DefaultDelegate, _>(&sources, BatchOptions::default());
+
+            assert_eq!(batch.results().len(), 4);
+            assert!(batch.has_errors());
+
+            let diagnostics: Vec<_> = batch.diagnostics().collect();
+
+            assert_eq!(
+                diagnostics,
+                vec![
+                    (1, &Diagnostic::error(Span::new(2, 3), "unexpected `)`")),
+                    (2, &Diagnostic::error(Span::new(0, 1), "unclosed `(`")),
+                ]
+            );
+        }),
+        ("read_batch (stop on error)", {
+            let mut inputs = vec!["ok"; 50];
+            inputs[20] = "bad )";
+            inputs[30] = "also bad )";
+            let sources = sources(&inputs);
+
+            for _ in 0..10 {
+                let batch = read_batch::<DefaultDelegate, _>(
+                    &sources,
+                    BatchOptions::default().threads(4).stop_on_error(),
+                );
+
+                assert_eq!(batch.stopped_at(), Some(20));
+                assert_eq!(batch.results().len(), 21);
+                assert_eq!(batch.diagnostics().count(), 1);
+            }
+        }),
+        ("read_batch (sequential fallback)", {
+            let mut inputs = vec!["ok"; 50];
+            inputs[20] = "bad )";
+            inputs[30] = "also bad )";
+            let sources = sources(&inputs);
+
+            for options in &[
+                BatchOptions::default(),
+                BatchOptions::default().stop_on_error(),
+            ] {
+                assert_eq!(
+                    read_batch_sequentially::<DefaultDelegate, _>(&sources, *options),
+                    read_batch::<DefaultDelegate, _>(&sources, options.threads(4))
+                );
+            }
+        })
+    )
+);
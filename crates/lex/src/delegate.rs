@@ -1,5 +1,5 @@
 use logos::{Lexer, Logos};
-use wyst_core::{new, wyst_copy};
+use wyst_core::{new, wyst_copy, wyst_data};
 use wyst_core_traits::WystCopy;
 use wyst_source::{AddSpan, Offset, Span, Spanned};
 
@@ -31,11 +31,66 @@ pub trait StandardDelegate<'source>: WystCopy {
         (None, lexer)
     }
 
+    /// Lex a token whose meaning depends on the tokens before it (such as a JavaScript regex
+    /// literal, which looks like a division operator). This hook runs before every token (after
+    /// `comment`), and a delegate that recognizes a contextual token at the start of the remaining
+    /// input returns it, along with a lexer that has been advanced past it.
+    fn contextual(
+        _context: &LexContext<'source>,
+        lexer: Lexer<'source, FlatToken>,
+    ) -> (Option<Spanned<FlatToken>>, Lexer<'source, FlatToken>) {
+        (None, lexer)
+    }
+
     quote_impl!('source, double_quote, DefaultStringWord);
     quote_impl!('source, single_quote, DefaultStringWord);
     quote_impl!('source, backtick, DefaultStringWord);
 }
 
+/// A hint from a parser that is driving the lexer about what the next significant token may be
+/// (see [crate::LexTop::hint]). The lexer doesn't interpret hints itself: it passes them on to its
+/// delegate's `contextual` hook.
+#[wyst_copy]
+#[derive(new)]
+pub struct LexHint {
+    name: &'static str,
+}
+
+impl LexHint {
+    pub fn name(self) -> &'static str {
+        self.name
+    }
+}
+
+/// What the lexer knows about the tokens before the current position.
+#[wyst_data]
+pub struct LexContext<'source> {
+    pub(crate) previous: Option<(Spanned<FlatToken>, &'source str)>,
+    pub(crate) hint: Option<LexHint>,
+}
+
+impl<'source> LexContext<'source> {
+    /// The previous significant (non-trivia) token.
+    pub fn previous(&self) -> Option<&Spanned<FlatToken>> {
+        self.previous.as_ref().map(|(token, _)| token)
+    }
+
+    /// The source text of the previous significant token.
+    pub fn previous_text(&self) -> Option<&'source str> {
+        self.previous.as_ref().map(|(_, text)| *text)
+    }
+
+    /// The hint for the next significant token, if the parser provided one.
+    pub fn hint(&self) -> Option<LexHint> {
+        self.hint
+    }
+
+    /// True if the parser hinted that the next significant token may be `name`.
+    pub fn is_hinted(&self, name: &str) -> bool {
+        self.hint.map(|hint| hint.name == name).unwrap_or(false)
+    }
+}
+
 #[wyst_copy]
 #[derive(Logos)]
 pub enum DefaultStringWord {
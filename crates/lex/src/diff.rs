@@ -0,0 +1,857 @@
+//! A structural diff between two token trees, in the style of GumTree.
+//!
+//! Whitespace and newlines are ignored, so reformatting a file produces an empty diff. The diff
+//! matches nodes in three phases:
+//!
+//! 1. top-down: identical subtrees (of height two or more, or leaves that are unique on both
+//!    sides) are matched, largest first
+//! 2. bottom-up: a delimited node is matched with a node with the same delimiter whose
+//!    descendants were mostly matched to its own descendants
+//! 3. recovery: the remaining children of matched nodes are matched if they have the same text,
+//!    and leaves between the same matched siblings are matched as updates
+//!
+//! The changes are then read off the matching: unmatched nodes were deleted or inserted, matched
+//! leaves with different text were updated, and matched nodes that ended up with a different
+//! parent (or out of order with their siblings) were moved.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    error::Error,
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_source::{Source, Span, Spanned};
+use wyst_style::{emit_styled, PlainStyle, PortableColor, PortableStyle, Print, Style};
+
+use crate::{
+    standard::Delimiter,
+    tree::{Leaf, Token},
+};
+
+/// Two delimited nodes are matched bottom-up if at least this fraction of their descendants were
+/// matched with each other.
+const MIN_DICE: f64 = 0.5;
+
+/// A single change between the old and the new tree.
+#[wyst_copy]
+pub enum Change {
+    Insert { new: Span },
+    Delete { old: Span },
+    Update { old: Span, new: Span },
+    Move { old: Span, new: Span },
+}
+
+impl Change {
+    pub fn old_span(self) -> Option<Span> {
+        match self {
+            Change::Insert { .. } => None,
+            Change::Delete { old } | Change::Update { old, .. } | Change::Move { old, .. } => {
+                Some(old)
+            }
+        }
+    }
+
+    pub fn new_span(self) -> Option<Span> {
+        match self {
+            Change::Delete { .. } => None,
+            Change::Insert { new } | Change::Update { new, .. } | Change::Move { new, .. } => {
+                Some(new)
+            }
+        }
+    }
+}
+
+/// Which side of a diff to render.
+#[wyst_copy]
+pub enum Side {
+    Old,
+    New,
+}
+
+/// The changes between two token trees. Deletions come first (in the order of the old tree),
+/// followed by insertions, updates and moves (in the order of the new tree).
+#[wyst_data]
+pub struct TreeDiff {
+    changes: Vec<Change>,
+}
+
+impl TreeDiff {
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Print one side of the diff, highlighting the tokens that changed on that side.
+    pub fn render<P>(
+        &self,
+        side: Side,
+        source: &Source,
+        print: &mut P,
+    ) -> Result<(), Box<dyn Error>>
+    where
+        P: Print,
+        P::Style: DiffStyle,
+    {
+        let text = source.contents();
+        let mut highlights: Vec<(Span, P::Style)> = self
+            .changes
+            .iter()
+            .filter_map(|change| {
+                let span = match side {
+                    Side::Old => change.old_span(),
+                    Side::New => change.new_span(),
+                }?;
+
+                let style = match change {
+                    Change::Insert { .. } => P::Style::inserted(),
+                    Change::Delete { .. } => P::Style::deleted(),
+                    Change::Update { .. } => P::Style::updated(),
+                    Change::Move { .. } => P::Style::moved(),
+                };
+
+                Some((span, style))
+            })
+            .collect();
+
+        // A change can contain other changes (an update inside of a moved subtree, say), so the
+        // larger changes are painted first and the smaller ones on top.
+        highlights.sort_by_key(|(span, _)| {
+            let (start, end): (usize, usize) = (span.start().into(), span.end().into());
+            std::cmp::Reverse(end - start)
+        });
+
+        let mut styles = vec![P::Style::normal(); text.len()];
+
+        for (span, style) in highlights {
+            let (start, end): (usize, usize) = (span.start().into(), span.end().into());
+
+            for slot in &mut styles[start..end] {
+                *slot = style;
+            }
+        }
+
+        emit_styled(print, text, &styles)
+    }
+}
+
+/// The styles that [TreeDiff::render] uses for each kind of change.
+pub trait DiffStyle: Style {
+    fn inserted() -> Self;
+    fn deleted() -> Self;
+    fn updated() -> Self;
+    fn moved() -> Self;
+}
+
+impl DiffStyle for PortableStyle {
+    fn inserted() -> Self {
+        PortableStyle::fg(PortableColor::LightGreen)
+    }
+
+    fn deleted() -> Self {
+        PortableStyle::fg(PortableColor::LightRed)
+    }
+
+    fn updated() -> Self {
+        PortableStyle::fg(PortableColor::LightYellow)
+    }
+
+    fn moved() -> Self {
+        PortableStyle::fg(PortableColor::LightCyan)
+    }
+}
+
+impl DiffStyle for PlainStyle {
+    fn inserted() -> Self {
+        PlainStyle
+    }
+
+    fn deleted() -> Self {
+        PlainStyle
+    }
+
+    fn updated() -> Self {
+        PlainStyle
+    }
+
+    fn moved() -> Self {
+        PlainStyle
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Label<'a> {
+    Root,
+    Leaf(&'a str),
+    Delimited(Delimiter),
+}
+
+#[derive(Debug)]
+struct Node<'a> {
+    label: Label<'a>,
+    span: Span,
+    parent: Option<usize>,
+    /// The node's position in its parent's children.
+    index: usize,
+    children: Vec<usize>,
+    height: usize,
+    hash: u64,
+    /// The node's descendants are the nodes after it, up to (but not including) `end`.
+    end: usize,
+}
+
+/// A token tree without whitespace, flattened into a list of nodes. The root is the first node,
+/// and every node comes right before its descendants, so the descendants of a node are a range
+/// of ids.
+struct Tree<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Tree<'a> {
+    fn new(tokens: &[Spanned<Token>], source: &'a Source) -> Tree<'a> {
+        let mut tree = Tree { nodes: vec![] };
+        let root = tree.push(Label::Root, Span::new(0, source.contents().len()), None);
+
+        tree.push_children(root, tokens, source);
+        tree.finish(root);
+        tree
+    }
+
+    fn push(&mut self, label: Label<'a>, span: Span, parent: Option<usize>) -> usize {
+        let id = self.nodes.len();
+        let index = parent.map(|parent| self.nodes[parent].children.len());
+
+        self.nodes.push(Node {
+            label,
+            span,
+            parent,
+            index: index.unwrap_or(0),
+            children: vec![],
+            height: 1,
+            hash: 0,
+            end: id + 1,
+        });
+
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+
+        id
+    }
+
+    fn push_children(&mut self, parent: usize, tokens: &[Spanned<Token>], source: &'a Source) {
+        for token in tokens {
+            match token.item() {
+                Token::Leaf(Leaf::Whitespace)
+                | Token::Leaf(Leaf::Newline)
+                | Token::Leaf(Leaf::EOF) => {}
+                Token::Leaf(_) => {
+                    self.push(
+                        Label::Leaf(source.slice(token.span())),
+                        token.span(),
+                        Some(parent),
+                    );
+                }
+                Token::Delimited(delimited) => {
+                    let id = self.push(
+                        Label::Delimited(delimited.delimiter()),
+                        token.span(),
+                        Some(parent),
+                    );
+                    self.push_children(id, delimited.children(), source);
+                }
+            }
+        }
+    }
+
+    /// Compute the height, hash and end of `id` and its descendants.
+    fn finish(&mut self, id: usize) {
+        let mut hasher = DefaultHasher::new();
+        self.nodes[id].label.hash(&mut hasher);
+
+        let mut height = 0;
+
+        for child in self.nodes[id].children.clone() {
+            self.finish(child);
+            height = height.max(self.nodes[child].height);
+            self.nodes[child].hash.hash(&mut hasher);
+        }
+
+        self.nodes[id].height = height + 1;
+        self.nodes[id].hash = hasher.finish();
+        self.nodes[id].end = match self.nodes[id].children.last() {
+            Some(last) => self.nodes[*last].end,
+            None => id + 1,
+        };
+    }
+
+    fn descendants(&self, id: usize) -> Range<usize> {
+        id + 1..self.nodes[id].end
+    }
+
+    /// Is `id` the node `ancestor` or one of its descendants?
+    fn is_inside(&self, id: usize, ancestor: usize) -> bool {
+        ancestor <= id && id < self.nodes[ancestor].end
+    }
+
+    /// The position of `id` in the children of `parent`, if it's one of them.
+    fn index_in(&self, id: usize, parent: usize) -> Option<usize> {
+        match self.nodes[id].parent {
+            Some(actual) if actual == parent => Some(self.nodes[id].index),
+            _ => None,
+        }
+    }
+
+    fn is_leaf(&self, id: usize) -> bool {
+        matches!(self.nodes[id].label, Label::Leaf(_))
+    }
+}
+
+fn isomorphic(old: &Tree, o: usize, new: &Tree, n: usize) -> bool {
+    let (on, nn) = (&old.nodes[o], &new.nodes[n]);
+
+    on.hash == nn.hash
+        && on.label == nn.label
+        && on.children.len() == nn.children.len()
+        && on
+            .children
+            .iter()
+            .zip(&nn.children)
+            .all(|(o, n)| isomorphic(old, *o, new, *n))
+}
+
+struct Matcher<'t, 'a> {
+    old: &'t Tree<'a>,
+    new: &'t Tree<'a>,
+    old_to_new: Vec<Option<usize>>,
+    new_to_old: Vec<Option<usize>>,
+}
+
+impl<'t, 'a> Matcher<'t, 'a> {
+    fn new(old: &'t Tree<'a>, new: &'t Tree<'a>) -> Self {
+        let mut matcher = Matcher {
+            old,
+            new,
+            old_to_new: vec![None; old.nodes.len()],
+            new_to_old: vec![None; new.nodes.len()],
+        };
+
+        matcher.link(0, 0);
+        matcher
+    }
+
+    fn link(&mut self, o: usize, n: usize) {
+        self.old_to_new[o] = Some(n);
+        self.new_to_old[n] = Some(o);
+    }
+
+    /// Match two isomorphic subtrees.
+    fn link_subtree(&mut self, o: usize, n: usize) {
+        self.link(o, n);
+
+        let children = self.old.nodes[o]
+            .children
+            .iter()
+            .zip(&self.new.nodes[n].children)
+            .map(|(o, n)| (*o, *n))
+            .collect::<Vec<_>>();
+
+        for (o, n) in children {
+            self.link_subtree(o, n);
+        }
+    }
+
+    fn top_down(&mut self) {
+        let max_height = self.old.nodes[0].height.max(self.new.nodes[0].height);
+
+        for height in (1..max_height).rev() {
+            let old = self.candidates(self.old, &self.old_to_new, height);
+            let new: HashMap<u64, Vec<usize>> = self
+                .candidates(self.new, &self.new_to_old, height)
+                .into_iter()
+                .collect();
+
+            for (hash, olds) in old {
+                let news = match new.get(&hash) {
+                    Some(news) => news,
+                    None => continue,
+                };
+
+                // Leaves are only matched here if they are unambiguous. Ambiguous leaves (such as
+                // punctuation) are matched later, in the context of their matched parents.
+                if height == 1 && (olds.len() > 1 || news.len() > 1) {
+                    continue;
+                }
+
+                let mut news = news.iter().copied();
+
+                for o in olds {
+                    if let Some(n) = news.find(|n| isomorphic(self.old, o, self.new, *n)) {
+                        self.link_subtree(o, n);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The unmatched (non-root) nodes of `tree` with height `height`, grouped by hash. The groups
+    /// and the nodes in each group are in tree order.
+    fn candidates(
+        &self,
+        tree: &Tree,
+        matched: &[Option<usize>],
+        height: usize,
+    ) -> Vec<(u64, Vec<usize>)> {
+        let mut groups: Vec<(u64, Vec<usize>)> = vec![];
+        let mut index: HashMap<u64, usize> = HashMap::new();
+
+        for (id, node) in tree.nodes.iter().enumerate().skip(1) {
+            if node.height != height || matched[id].is_some() {
+                continue;
+            }
+
+            match index.get(&node.hash) {
+                Some(group) => groups[*group].1.push(id),
+                None => {
+                    index.insert(node.hash, groups.len());
+                    groups.push((node.hash, vec![id]));
+                }
+            }
+        }
+
+        groups
+    }
+
+    fn bottom_up(&mut self) {
+        // Descendants come after their ancestors, so going backwards visits children first.
+        for o in (1..self.old.nodes.len()).rev() {
+            if self.old_to_new[o].is_some() || self.old.is_leaf(o) {
+                continue;
+            }
+
+            let mut best: Option<(usize, f64)> = None;
+
+            for n in self.candidate_containers(o) {
+                let dice = self.dice(o, n);
+
+                if dice >= MIN_DICE && best.map(|(_, best)| dice > best) != Some(false) {
+                    best = Some((n, dice));
+                }
+            }
+
+            if let Some((n, _)) = best {
+                self.link(o, n);
+                self.recover(o, n);
+            }
+        }
+
+        self.recover(0, 0);
+    }
+
+    /// Unmatched nodes in the new tree with the same delimiter as `o` that contain a partner of
+    /// one of `o`'s descendants.
+    fn candidate_containers(&self, o: usize) -> Vec<usize> {
+        let mut candidates = vec![];
+        let mut visited = HashSet::new();
+
+        for d in self.old.descendants(o) {
+            let mut ancestor = self.old_to_new[d].and_then(|n| self.new.nodes[n].parent);
+
+            // Once an ancestor was visited, so were all of its ancestors.
+            while let Some(n) = ancestor.filter(|n| visited.insert(*n)) {
+                if self.new_to_old[n].is_none()
+                    && self.new.nodes[n].label == self.old.nodes[o].label
+                {
+                    candidates.push(n);
+                }
+
+                ancestor = self.new.nodes[n].parent;
+            }
+        }
+
+        candidates
+    }
+
+    /// Were any descendants of `o` or `n` matched to nodes outside of the other one?
+    fn matched_elsewhere(&self, o: usize, n: usize) -> bool {
+        let old = self.old.descendants(o).any(|d| match self.old_to_new[d] {
+            Some(partner) => !self.new.is_inside(partner, n),
+            None => false,
+        });
+
+        old || self.new.descendants(n).any(|d| match self.new_to_old[d] {
+            Some(partner) => !self.old.is_inside(partner, o),
+            None => false,
+        })
+    }
+
+    fn dice(&self, o: usize, n: usize) -> f64 {
+        let (old_descendants, new_descendants) = (self.old.descendants(o), self.new.descendants(n));
+        let common = old_descendants
+            .clone()
+            .filter(|d| match self.old_to_new[*d] {
+                Some(partner) => self.new.is_inside(partner, n) && partner != n,
+                None => false,
+            })
+            .count();
+
+        (2 * common) as f64 / (old_descendants.len() + new_descendants.len()) as f64
+    }
+
+    /// Match the remaining children of a matched pair.
+    fn recover(&mut self, o: usize, n: usize) {
+        let old_children = self.old.nodes[o].children.clone();
+        let new_children = self.new.nodes[n].children.clone();
+
+        // First, children with the same label, in order.
+        let mut next_new = 0;
+
+        for oc in old_children.iter().copied() {
+            if self.old_to_new[oc].is_some() {
+                continue;
+            }
+
+            let found = new_children[next_new..].iter().position(|nc| {
+                self.new_to_old[*nc].is_none()
+                    && self.new.nodes[*nc].label == self.old.nodes[oc].label
+                    && !self.matched_elsewhere(oc, *nc)
+            });
+
+            if let Some(position) = found {
+                let nc = new_children[next_new + position];
+                next_new += position + 1;
+
+                if isomorphic(self.old, oc, self.new, nc) {
+                    self.link_subtree(oc, nc);
+                } else {
+                    self.link(oc, nc);
+                    self.recover(oc, nc);
+                }
+            }
+        }
+
+        // Then, leaves that are between the same matched siblings are updates.
+        let mut old_gap = vec![];
+        let mut new_start = 0;
+
+        for oc in old_children
+            .iter()
+            .copied()
+            .chain(std::iter::once(usize::MAX))
+        {
+            let anchor = if oc == usize::MAX {
+                Some(new_children.len())
+            } else {
+                self.old_to_new[oc]
+                    .and_then(|nc| self.new.index_in(nc, n))
+                    .filter(|position| *position >= new_start)
+            };
+
+            match anchor {
+                Some(position) => {
+                    let new_gap = new_children[new_start..position]
+                        .iter()
+                        .copied()
+                        .filter(|nc| self.new_to_old[*nc].is_none() && self.new.is_leaf(*nc))
+                        .collect::<Vec<_>>();
+
+                    for (oc, nc) in old_gap.drain(..).zip(new_gap) {
+                        self.link(oc, nc);
+                    }
+
+                    new_start = position + 1;
+                }
+                None if self.old_to_new[oc].is_none() && self.old.is_leaf(oc) => old_gap.push(oc),
+                None => {}
+            }
+        }
+    }
+
+    fn changes(&self) -> Vec<Change> {
+        let (old, new) = (self.old, self.new);
+        let mut deletes = vec![];
+        let mut inserts = vec![];
+        let mut updates = vec![];
+        let mut moves = vec![];
+
+        for (o, node) in old.nodes.iter().enumerate().skip(1) {
+            let parent = node.parent.expect("BUG: only the root has no parent");
+
+            if self.old_to_new[o].is_none() && self.old_to_new[parent].is_some() {
+                deletes.push(Change::Delete { old: node.span });
+            }
+        }
+
+        for (n, node) in new.nodes.iter().enumerate().skip(1) {
+            let parent = node.parent.expect("BUG: only the root has no parent");
+
+            match self.new_to_old[n] {
+                None if self.new_to_old[parent].is_some() => {
+                    inserts.push(Change::Insert { new: node.span })
+                }
+                None => {}
+                Some(o) => {
+                    let old_span = old.nodes[o].span;
+
+                    if old.nodes[o].label != node.label {
+                        updates.push(Change::Update {
+                            old: old_span,
+                            new: node.span,
+                        });
+                    }
+
+                    if old.nodes[o].parent.and_then(|p| self.old_to_new[p]) != Some(parent) {
+                        moves.push(Change::Move {
+                            old: old_span,
+                            new: node.span,
+                        });
+                    }
+                }
+            }
+        }
+
+        // Children that stayed in the same parent but changed their order. The longest run of
+        // children that kept their order stays put, and the others moved.
+        for (n, node) in new.nodes.iter().enumerate() {
+            let o = match self.new_to_old[n] {
+                Some(o) => o,
+                None => continue,
+            };
+
+            let positions: Vec<(usize, usize)> = node
+                .children
+                .iter()
+                .filter_map(|nc| {
+                    let oc = self.new_to_old[*nc]?;
+                    let position = old.index_in(oc, o)?;
+                    Some((*nc, position))
+                })
+                .collect();
+
+            let kept: HashSet<usize> = longest_increasing(&positions).into_iter().collect();
+
+            for (i, (nc, _)) in positions.iter().enumerate() {
+                if !kept.contains(&i) {
+                    let oc = self.new_to_old[*nc].expect("BUG: positions are matched");
+
+                    moves.push(Change::Move {
+                        old: old.nodes[oc].span,
+                        new: new.nodes[*nc].span,
+                    });
+                }
+            }
+        }
+
+        let start =
+            |span: Option<Span>| -> usize { span.map(|span| span.start().into()).unwrap_or(0) };
+
+        moves.sort_by_key(|change| start(change.new_span()));
+
+        deletes
+            .into_iter()
+            .chain(inserts)
+            .chain(updates)
+            .chain(moves)
+            .collect()
+    }
+}
+
+/// The indexes of a longest subsequence of `positions` whose second elements are increasing.
+fn longest_increasing(positions: &[(usize, usize)]) -> Vec<usize> {
+    let mut lengths = vec![1; positions.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; positions.len()];
+
+    for i in 0..positions.len() {
+        for j in 0..i {
+            if positions[j].1 < positions[i].1 && lengths[j] + 1 > lengths[i] {
+                lengths[i] = lengths[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+
+    let mut out = vec![];
+    let mut next = (0..positions.len()).max_by_key(|i| (lengths[*i], std::cmp::Reverse(*i)));
+
+    while let Some(i) = next {
+        out.push(i);
+        next = previous[i];
+    }
+
+    out
+}
+
+/// Compare two token trees, ignoring whitespace.
+pub fn diff(
+    old: &[Spanned<Token>],
+    old_source: &Source,
+    new: &[Spanned<Token>],
+    new_source: &Source,
+) -> TreeDiff {
+    let old = Tree::new(old, old_source);
+    let new = Tree::new(new, new_source);
+
+    let mut matcher = Matcher::new(&old, &new);
+    matcher.top_down();
+    matcher.bottom_up();
+
+    TreeDiff {
+        changes: matcher.changes(),
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_style::Indent;
+
+        use crate::{delegate::DefaultDelegate, standard::FlatToken};
+
+        struct Diffed {
+            old: Source,
+            new: Source,
+            diff: TreeDiff,
+        }
+
+        fn diffed(old: &str, new: &str) -> Diffed {
+            let old = Source::new("old", old);
+            let new = Source::new("new", new);
+            let diff = diff(
+                &FlatToken::read_tree::<DefaultDelegate>(&old).into_tokens(),
+                &old,
+                &FlatToken::read_tree::<DefaultDelegate>(&new).into_tokens(),
+                &new,
+            );
+
+            Diffed { old, new, diff }
+        }
+
+        impl Diffed {
+            /// The changes, with their spans replaced by the text they point to.
+            fn changes(&self) -> Vec<String> {
+                self.diff
+                    .changes()
+                    .iter()
+                    .map(|change| match change {
+                        Change::Insert { new } => format!("+{}", self.new.slice(*new)),
+                        Change::Delete { old } => format!("-{}", self.old.slice(*old)),
+                        Change::Update { old, new } => {
+                            format!("{}=>{}", self.old.slice(*old), self.new.slice(*new))
+                        }
+                        Change::Move { old, new } => {
+                            format!("{}~>{}", self.old.slice(*old), self.new.slice(*new))
+                        }
+                    })
+                    .collect()
+            }
+
+            fn render(&self, side: Side) -> String {
+                let source = match side {
+                    Side::Old => &self.old,
+                    Side::New => &self.new,
+                };
+
+                let mut print = Recorder::default();
+                self.diff.render(side, source, &mut print).unwrap();
+                print.out
+            }
+        }
+
+        /// Records the rendered diff, marking inserted text with `{+ +}`, deleted text with
+        /// `{- -}`, updated text with `{= =}` and moved text with `{~ ~}`.
+        #[derive(Debug, Default)]
+        struct Recorder {
+            out: String,
+        }
+
+        impl Print for Recorder {
+            type Style = PortableStyle;
+
+            fn emit_text(&mut self, text: &str, style: Self::Style) -> Result<(), Box<dyn Error>> {
+                let marker = [
+                    (PortableStyle::inserted(), '+'),
+                    (PortableStyle::deleted(), '-'),
+                    (PortableStyle::updated(), '='),
+                    (PortableStyle::moved(), '~'),
+                ]
+                .iter()
+                .find(|(candidate, _)| *candidate == style)
+                .map(|(_, marker)| *marker);
+
+                match marker {
+                    Some(marker) => self
+                        .out
+                        .push_str(&format!("{{{}{}{}}}", marker, text, marker)),
+                    None => self.out.push_str(text),
+                }
+
+                Ok(())
+            }
+
+            fn emit_break(&mut self, _indent: Indent<'_>) -> Result<(), Box<dyn Error>> {
+                self.out.push('\n');
+                Ok(())
+            }
+        }
+    }),
+    tests(
+        ("reformatting", {
+            let result = diffed("f(a, b) { c }", "f(a,\n  b)\n{\n  c\n}\n");
+
+            assert!(result.diff.is_empty());
+        }),
+        ("insertions and deletions", {
+            let result = diffed("a b [c d] e", "a [c d] e (f g)");
+
+            assert_eq!(result.changes(), vec!["-b", "+(f g)"]);
+            assert_eq!(result.render(Side::Old), "a {-b-} [c d] e");
+            assert_eq!(result.render(Side::New), "a [c d] e {+(f g)+}");
+        }),
+        ("updates", {
+            let result = diffed("call(x, [1 2])", "call(y, [1 3])");
+
+            assert_eq!(result.changes(), vec!["x,=>y,", "2=>3"]);
+            assert_eq!(result.render(Side::New), "call({=y,=} [1 {=3=}])");
+        }),
+        ("moves", {
+            let result = diffed(
+                "fn a() { one(1) }\nfn b() { two(2) }",
+                "fn b() { two(2) }\nfn a() { one(1) }",
+            );
+
+            assert_eq!(
+                result.changes(),
+                vec![
+                    "b~>b",
+                    "{ two(2) }~>{ two(2) }",
+                    "a~>a",
+                    "{ one(1) }~>{ one(1) }"
+                ]
+            );
+
+            let result = diffed("f { a b c } g { d }", "f { a c } g { d b }");
+
+            assert_eq!(result.changes(), vec!["b~>b"]);
+            assert_eq!(result.render(Side::Old), "f { a {~b~} c } g { d }");
+            assert_eq!(result.render(Side::New), "f { a c } g { d {~b~} }");
+        }),
+        ("a big reformatted file", {
+            let old: String = (0..2000)
+                .map(|i| format!("item{} {{ x{} [y (z {})] }}\n", i, i, i))
+                .collect();
+            let new: String = (0..2000)
+                .map(|i| match i {
+                    1000 => "item1000 {\n  changed\n  [y (z 1000)]\n}\n".to_string(),
+                    i => format!("item{} {{\n  x{}\n  [y (z {})]\n}}\n", i, i, i),
+                })
+                .collect();
+
+            let result = diffed(&old, &new);
+
+            assert_eq!(result.changes(), vec!["x1000=>changed"]);
+        })
+    )
+);
@@ -0,0 +1,270 @@
+use std::collections::VecDeque;
+
+use wyst_core::{unit_tests, wyst_copy};
+use wyst_source::{AddSpan, Diagnostic, Source, Span, Spanned};
+
+use crate::{
+    kind::{LeafKind, TokenKind},
+    reader::{
+        unclosed_delimiter, unexpected_close, unexpected_input, Closing, ReadTokens, Reader,
+        ReaderNext,
+    },
+    standard::Delimiter,
+    tree::Leaf,
+};
+
+/// A single step through a token tree.
+///
+/// The span of an `Enter` is the span of the opening delimiter, and the span of an `Exit` is the
+/// span of the closing delimiter. A delimiter that was never closed still gets an `Exit`, with an
+/// empty span where it was implicitly closed.
+#[wyst_copy]
+pub enum Event<L = Leaf>
+where
+    L: LeafKind,
+{
+    Enter(Delimiter),
+    Leaf(L),
+    Exit(Delimiter),
+}
+
+/// `EventReader` is a [Reader] that produces a flat stream of [Event]s instead of a tree. It only
+/// remembers the delimiters that are currently open, so it reads a source in memory proportional
+/// to its nesting depth rather than its length. This makes it a good starting point for consumers
+/// that don't need an owned tree (such as highlighters or search indexes).
+///
+/// The events are always balanced: every `Enter` has a matching `Exit`, even if the source
+/// doesn't. Unbalanced delimiters are reported as diagnostics, in the same way as [TokenTree].
+///
+/// [TokenTree]: crate::TokenTree
+pub struct EventReader<L: LeafKind = Leaf> {
+    open: Vec<(Delimiter, Span)>,
+    pending: VecDeque<Spanned<Event<L>>>,
+    diagnostics: Vec<Diagnostic>,
+    done: bool,
+}
+
+impl<L: LeafKind> Default for EventReader<L> {
+    fn default() -> Self {
+        EventReader::new()
+    }
+}
+
+impl<L: LeafKind> EventReader<L> {
+    pub fn new() -> EventReader<L> {
+        EventReader {
+            open: vec![],
+            pending: VecDeque::new(),
+            diagnostics: vec![],
+            done: false,
+        }
+    }
+
+    /// Read a stream of tokens of any [TokenKind] into events. Once the events have been consumed,
+    /// the diagnostics are available through `into_reader().into_diagnostics()`.
+    pub fn read<'source, K, I>(
+        source: &'source Source,
+        tokens: I,
+    ) -> ReadTokens<'source, K, EventReader<L>, I>
+    where
+        K: TokenKind<Leaf = L>,
+        I: Iterator<Item = Spanned<K>>,
+    {
+        ReadTokens::new(EventReader::new(), tokens, source)
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    fn push(&mut self, event: Event<L>, span: Span) {
+        self.pending.push_back(event.spanned(span));
+    }
+
+    /// Report the innermost open delimiter as unclosed, and exit it at `at`.
+    fn exit_unclosed(&mut self, at: Span) {
+        let (delimiter, open) = self
+            .open
+            .pop()
+            .expect("BUG: exit_unclosed called without an open delimiter");
+
+        self.diagnostics.push(unclosed_delimiter(delimiter, open));
+        self.push(Event::Exit(delimiter), at);
+    }
+}
+
+impl<L: LeafKind> Reader<L> for EventReader<L> {
+    type Item = Spanned<Event<L>>;
+
+    fn next(&mut self) -> ReaderNext<Spanned<Event<L>>> {
+        match self.pending.pop_front() {
+            Some(event) => ReaderNext::Token(event),
+            None if self.done => ReaderNext::EOF,
+            None => ReaderNext::Buffer,
+        }
+    }
+
+    fn leaf(&mut self, _source: &Source, span: Span, leaf: L) {
+        self.push(Event::Leaf(leaf), span);
+    }
+
+    fn open(&mut self, _source: &Source, span: Span, delimiter: Delimiter) {
+        self.open.push((delimiter, span));
+        self.push(Event::Enter(delimiter), span);
+    }
+
+    fn close(&mut self, _source: &Source, span: Span, delimiter: Delimiter) {
+        let open = self.open.iter().rev().map(|(delimiter, _)| *delimiter);
+
+        match Closing::find(open, delimiter) {
+            Closing::Matches { unclosed } => {
+                for _ in 0..unclosed {
+                    self.exit_unclosed(Span::eof(span.start()));
+                }
+
+                self.open.pop();
+                self.push(Event::Exit(delimiter), span);
+            }
+            Closing::Unexpected => {
+                self.diagnostics.push(unexpected_close(delimiter, span));
+                self.push(Event::Leaf(L::error()), span);
+            }
+        }
+    }
+
+    fn eof(&mut self, _source: &Source, span: Span) {
+        self.done = true;
+
+        while !self.open.is_empty() {
+            self.exit_unclosed(Span::eof(span.start()));
+        }
+
+        self.push(Event::Leaf(L::eof()), span);
+    }
+
+    fn error(&mut self, _source: &Source, span: Span) {
+        self.diagnostics.push(unexpected_input(span));
+        self.push(Event::Leaf(L::error()), span);
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::delegate::DefaultDelegate;
+        use crate::standard::FlatToken;
+        use crate::tree::Token;
+        use std::sync::Arc;
+
+        fn source(string: &str) -> Arc<Source> {
+            Arc::new(Source::new("<test>", string))
+        }
+
+        /// Build owned token trees out of events, to check that the events describe the same tree
+        /// as `TokenTree`.
+        fn build(events: impl Iterator<Item = Spanned<Event>>) -> Vec<Spanned<Token>> {
+            let mut stack: Vec<(Span, Vec<Spanned<Token>>)> = vec![];
+            let mut roots = vec![];
+
+            for event in events {
+                let span = event.span();
+
+                let token = match *event.item() {
+                    Event::Enter(_) => {
+                        stack.push((span, vec![]));
+                        continue;
+                    }
+                    Event::Leaf(leaf) => Token::Leaf(leaf).spanned(span),
+                    Event::Exit(delimiter) => {
+                        let (open, children) = stack.pop().expect("unbalanced events");
+
+                        // An unclosed delimiter exits with an empty span.
+                        if span.start() == span.end() {
+                            Token::unclosed(delimiter, children).spanned(open.until(span))
+                        } else {
+                            Token::delimited(delimiter, children).spanned(open.until(span))
+                        }
+                    }
+                };
+
+                match stack.last_mut() {
+                    Some((_, children)) => children.push(token),
+                    None => roots.push(token),
+                }
+            }
+
+            assert!(stack.is_empty(), "unbalanced events");
+            roots
+        }
+
+        fn assert_same_as_tree(string: &str) {
+            let s = source(string);
+            let tree = FlatToken::read_tree::<DefaultDelegate>(&s);
+            let mut read =
+                EventReader::read(&s, FlatToken::lex_source::<DefaultDelegate>(s.contents()));
+
+            assert_eq!(build(read.by_ref()), tree.tokens());
+            assert_eq!(read.into_reader().into_diagnostics(), tree.diagnostics());
+        }
+    }),
+    tests(
+        ("events", {
+            let s = source("a (b [c])");
+            let events: Vec<_> = FlatToken::read_events::<DefaultDelegate>(&s)
+                .map(|event| (*event.item(), s.slice(event.span())))
+                .collect();
+
+            assert_eq!(
+                events,
+                &[
+                    (Event::Leaf(Leaf::Word), "a"),
+                    (Event::Leaf(Leaf::Whitespace), " "),
+                    (Event::Enter(Delimiter::Paren), "("),
+                    (Event::Leaf(Leaf::Word), "b"),
+                    (Event::Leaf(Leaf::Whitespace), " "),
+                    (Event::Enter(Delimiter::Bracket), "["),
+                    (Event::Leaf(Leaf::Word), "c"),
+                    (Event::Exit(Delimiter::Bracket), "]"),
+                    (Event::Exit(Delimiter::Paren), ")"),
+                    (Event::Leaf(Leaf::EOF), ""),
+                ]
+            );
+        }),
+        ("events (unbalanced)", {
+            let s = source("{ ( } )");
+            let mut read =
+                EventReader::read(&s, FlatToken::lex_source::<DefaultDelegate>(s.contents()));
+            let events: Vec<_> = read.by_ref().map(|event| *event.item()).collect();
+
+            assert_eq!(
+                events,
+                &[
+                    Event::Enter(Delimiter::Brace),
+                    Event::Leaf(Leaf::Whitespace),
+                    Event::Enter(Delimiter::Paren),
+                    Event::Leaf(Leaf::Whitespace),
+                    Event::Exit(Delimiter::Paren),
+                    Event::Exit(Delimiter::Brace),
+                    Event::Leaf(Leaf::Whitespace),
+                    Event::Leaf(Leaf::Error),
+                    Event::Leaf(Leaf::EOF),
+                ]
+            );
+            assert_eq!(
+                read.into_reader().into_diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(2, 3), "unclosed `(`"),
+                    Diagnostic::error(Span::new(6, 7), "unexpected `)`"),
+                ]
+            );
+        }),
+        ("same as TokenTree", {
+            assert_same_as_tree("");
+            assert_same_as_tree("   hello { world() }   ");
+            assert_same_as_tree(r#"   hello( "world" )   "#);
+            assert_same_as_tree("a [b (c {d} e) f] g\n{ [ ( ) ] }");
+            assert_same_as_tree("a ) b");
+            assert_same_as_tree("( a");
+            assert_same_as_tree("{ ( }");
+        })
+    )
+);
@@ -0,0 +1,1148 @@
+//! A small `macro_rules!`-style expander that works directly on token trees.
+//!
+//! A macro is a list of rules of the form `(pattern) => { template }`, separated by `;`. Patterns
+//! and templates use the same syntax:
+//!
+//! - `$name` matches (and substitutes) a single token tree
+//! - `$( ... ) sep op` matches (and substitutes) a repetition, where `op` is one of `*`, `+` or
+//!   `?` and the separator `sep` is optional
+//! - anything else matches itself
+//!
+//! Matching ignores trivia, and words are compared in pieces: a run of identifier characters or
+//! a single punctuation character. This means that `$a,$b` and `$a , $b` are the same pattern.
+//!
+//! Every expanded token records its [Provenance]: tokens that came from an argument keep the
+//! provenance they had at the call site, and tokens that came from the macro definition point
+//! into the definition and record the expansion that produced them. A macro can be defined in a
+//! different source than the one it's called from, so a provenance also records which source its
+//! span points into, and reading the text of an expanded token takes all of the sources that it
+//! could come from. This is also what makes expansions hygienic: see [Expanded::same_name].
+
+use std::collections::HashMap;
+
+use wyst_core::{unit_tests, wyst_data};
+use wyst_source::{
+    AddSpan, Diagnostic, Expansion, ExpansionId, Provenance, Source, SourceId, Span, Spanned,
+};
+
+use crate::{
+    kind::LeafKind,
+    standard::Delimiter,
+    tree::{Leaf, Token},
+};
+
+/// Expanding a macro that expands to itself should report an error, not overflow the stack.
+const RECURSION_LIMIT: usize = 64;
+
+/// A token produced by expansion (or read from the source), along with its provenance.
+#[wyst_data]
+pub struct Expanded {
+    token: ExpandedToken,
+    provenance: Provenance,
+}
+
+#[wyst_data]
+pub enum ExpandedToken {
+    Leaf(Leaf),
+    Delimited(Delimiter, Vec<Expanded>),
+}
+
+impl Expanded {
+    /// Convert token trees into pieces that can be passed to a macro. Trivia is dropped, and
+    /// words are split into identifier runs and single punctuation characters.
+    pub fn from_tokens(tokens: &[Spanned<Token>], source: &Source) -> Vec<Expanded> {
+        let mut out = vec![];
+
+        for token in tokens {
+            Expanded::push_pieces(token, source, &mut out);
+        }
+
+        out
+    }
+
+    fn push_pieces(token: &Spanned<Token>, source: &Source, out: &mut Vec<Expanded>) {
+        match token.item() {
+            Token::Leaf(Leaf::EOF) => {}
+            Token::Leaf(leaf) if leaf.is_trivia() => {}
+            Token::Leaf(Leaf::Word) => split_word(token.span(), source, out),
+            Token::Leaf(leaf) => out.push(Expanded::source(
+                ExpandedToken::Leaf(*leaf),
+                source.id(),
+                token.span(),
+            )),
+            Token::Delimited(delimited) => {
+                let children = Expanded::from_tokens(delimited.children(), source);
+
+                out.push(Expanded::source(
+                    ExpandedToken::Delimited(delimited.delimiter(), children),
+                    source.id(),
+                    token.span(),
+                ));
+            }
+        }
+    }
+
+    fn source(token: ExpandedToken, source: SourceId, span: Span) -> Expanded {
+        Expanded {
+            token,
+            provenance: Provenance::source(source, span),
+        }
+    }
+
+    pub fn token(&self) -> &ExpandedToken {
+        &self.token
+    }
+
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    /// The span where the token was written, either at the call site or in a macro definition.
+    pub fn span(&self) -> Span {
+        self.provenance.span()
+    }
+
+    /// The text of the token, from whichever of `sources` its span points into, or `None` if its
+    /// source isn't one of `sources`.
+    pub fn text<'source>(&self, sources: &[&'source Source]) -> Option<&'source str> {
+        let id = self.provenance.source_id();
+
+        sources
+            .iter()
+            .find(|source| source.has_id(id))
+            .map(|source| source.slice(self.span()))
+    }
+
+    /// The text of a token that the expander produced, which always points into one of the
+    /// expander's sources.
+    fn known_text<'source>(&self, sources: &[&'source Source]) -> &'source str {
+        self.text(sources)
+            .expect("BUG: expanded tokens point into one of the expander's sources")
+    }
+
+    /// The expansion that introduced this token from a macro definition, if any. Tokens that were
+    /// passed to a macro as arguments keep the context that they had at the call site.
+    pub fn context(&self) -> Option<ExpansionId> {
+        self.provenance.expansion().map(Expansion::id)
+    }
+
+    /// Two tokens refer to the same name if they have the same text and were introduced by the
+    /// same expansion. A name that a macro definition introduces therefore never captures (or is
+    /// captured by) a name with the same text that was passed to the macro.
+    ///
+    /// Tokens whose sources aren't in `sources` are never the same name.
+    pub fn same_name(&self, other: &Expanded, sources: &[&Source]) -> bool {
+        self.context() == other.context()
+            && match (self.text(sources), other.text(sources)) {
+                (Some(left), Some(right)) => left == right,
+                _ => false,
+            }
+    }
+
+    /// Convert the expanded token back into a token tree. The spans are the spans where each
+    /// token was written.
+    pub fn to_token(&self) -> Spanned<Token> {
+        match &self.token {
+            ExpandedToken::Leaf(leaf) => Token::Leaf(*leaf).spanned(self.span()),
+            ExpandedToken::Delimited(delimiter, children) => {
+                Token::delimited(*delimiter, children.iter().map(Expanded::to_token))
+                    .spanned(self.span())
+            }
+        }
+    }
+
+    pub(crate) fn children(&self) -> Option<(Delimiter, &[Expanded])> {
+        match &self.token {
+            ExpandedToken::Leaf(_) => None,
+            ExpandedToken::Delimited(delimiter, children) => Some((*delimiter, children)),
+        }
+    }
+
+    pub(crate) fn is_text(&self, text: &str, sources: &[&Source]) -> bool {
+        matches!(self.token, ExpandedToken::Leaf(_)) && self.known_text(sources) == text
+    }
+
+    pub(crate) fn matches(&self, other: &Expanded, sources: &[&Source]) -> bool {
+        match (&self.token, &other.token) {
+            (ExpandedToken::Leaf(_), ExpandedToken::Leaf(_)) => {
+                self.known_text(sources) == other.known_text(sources)
+            }
+            (
+                ExpandedToken::Delimited(left, left_children),
+                ExpandedToken::Delimited(right, right_children),
+            ) => {
+                left == right
+                    && left_children.len() == right_children.len()
+                    && left_children
+                        .iter()
+                        .zip(right_children)
+                        .all(|(left, right)| left.matches(right, sources))
+            }
+            _ => false,
+        }
+    }
+
+    /// A copy of a token from a macro definition, produced by `expansion`.
+    fn expanded(&self, expansion: &Expansion) -> Expanded {
+        let token = match &self.token {
+            ExpandedToken::Leaf(leaf) => ExpandedToken::Leaf(*leaf),
+            ExpandedToken::Delimited(delimiter, children) => ExpandedToken::Delimited(
+                *delimiter,
+                children
+                    .iter()
+                    .map(|child| child.expanded(expansion))
+                    .collect(),
+            ),
+        };
+
+        Expanded {
+            token,
+            provenance: Provenance::expanded(
+                self.provenance.source_id().clone(),
+                self.span(),
+                expansion.clone(),
+            ),
+        }
+    }
+}
+
+/// Render expanded tokens as text, separating the pieces with spaces. `sources` are the sources
+/// that the tokens' spans point into (see [Expanded::text]), and if one of the tokens' sources
+/// is missing, there's no text.
+pub fn expanded_text(tokens: &[Expanded], sources: &[&Source]) -> Option<String> {
+    let texts = tokens
+        .iter()
+        .map(|token| match token.children() {
+            None => token.text(sources).map(str::to_string),
+            Some((delimiter, children)) => Some(format!(
+                "{}{}{}",
+                delimiter.open_char(),
+                expanded_text(children, sources)?,
+                delimiter.close_char()
+            )),
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(texts.join(" "))
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+pub(crate) fn is_ident(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(is_ident_char)
+}
+
+fn split_word(span: Span, source: &Source, out: &mut Vec<Expanded>) {
+    for piece in word_pieces(span, source) {
+        out.push(Expanded::source(
+            ExpandedToken::Leaf(Leaf::Word),
+            source.id(),
+            piece,
+        ));
+    }
+}
+
+/// Split a word into pieces: runs of identifier characters and single punctuation characters.
+pub(crate) fn word_pieces(span: Span, source: &Source) -> Vec<Span> {
+    let text = source.slice(span);
+    let start: usize = span.start().into();
+    let mut chars = text.char_indices().peekable();
+    let mut pieces = vec![];
+
+    while let Some((i, c)) = chars.next() {
+        let mut end = i + c.len_utf8();
+
+        if is_ident_char(c) {
+            while let Some(&(j, next)) = chars.peek() {
+                if !is_ident_char(next) {
+                    break;
+                }
+
+                end = j + next.len_utf8();
+                chars.next();
+            }
+        }
+
+        pieces.push(Span::new(start + i, start + end));
+    }
+
+    pieces
+}
+
+#[wyst_data]
+enum RepeatOp {
+    ZeroOrMore,
+    OneOrMore,
+    ZeroOrOne,
+}
+
+/// A piece of a pattern or a template.
+#[wyst_data]
+enum Fragment {
+    Token(Expanded),
+    Var(String, Span),
+    Group(Delimiter, Span, Vec<Fragment>),
+    Repeat(Repeat),
+}
+
+#[wyst_data]
+struct Repeat {
+    body: Vec<Fragment>,
+    separator: Option<Expanded>,
+    op: RepeatOp,
+    span: Span,
+}
+
+impl Fragment {
+    fn parse_all(tokens: &[Expanded], sources: &[&Source]) -> Result<Vec<Fragment>, Diagnostic> {
+        let mut fragments = vec![];
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let token = &tokens[i];
+
+            if let Some((delimiter, children)) = token.children() {
+                fragments.push(Fragment::Group(
+                    delimiter,
+                    token.span(),
+                    Fragment::parse_all(children, sources)?,
+                ));
+                i += 1;
+                continue;
+            }
+
+            if !token.is_text("$", sources) {
+                fragments.push(Fragment::Token(token.clone()));
+                i += 1;
+                continue;
+            }
+
+            match tokens.get(i + 1) {
+                Some(name)
+                    if is_ident(name.known_text(sources))
+                        && name.span().start() == token.span().end() =>
+                {
+                    fragments.push(Fragment::Var(
+                        name.known_text(sources).to_string(),
+                        token.span().until(name.span()),
+                    ));
+                    i += 2;
+                }
+                Some(group) if matches!(group.children(), Some((Delimiter::Paren, _))) => {
+                    let (_, children) = group.children().expect("BUG: checked above");
+                    let body = Fragment::parse_all(children, sources)?;
+                    let (separator, op, consumed) = Fragment::repeat_op(&tokens[i + 2..], sources)
+                        .ok_or_else(|| {
+                            Diagnostic::error(
+                                group.span(),
+                                "expected `*`, `+` or `?` after a repetition",
+                            )
+                        })?;
+
+                    fragments.push(Fragment::Repeat(Repeat {
+                        body,
+                        separator,
+                        op,
+                        span: token.span().until(tokens[i + 1 + consumed].span()),
+                    }));
+                    i += 2 + consumed;
+                }
+                _ => {
+                    return Err(Diagnostic::error(
+                        token.span(),
+                        "expected a variable name or `(` after `$`",
+                    ))
+                }
+            }
+        }
+
+        Ok(fragments)
+    }
+
+    /// Parse the (optional) separator and the operator after a repetition. Returns the number of
+    /// tokens consumed.
+    fn repeat_op(
+        tokens: &[Expanded],
+        sources: &[&Source],
+    ) -> Option<(Option<Expanded>, RepeatOp, usize)> {
+        let op = |token: &Expanded| match token.children() {
+            Some(_) => None,
+            None => match token.known_text(sources) {
+                "*" => Some(RepeatOp::ZeroOrMore),
+                "+" => Some(RepeatOp::OneOrMore),
+                "?" => Some(RepeatOp::ZeroOrOne),
+                _ => None,
+            },
+        };
+
+        let first = tokens.first()?;
+
+        if let Some(op) = op(first) {
+            return Some((None, op, 1));
+        }
+
+        if first.children().is_some() {
+            return None;
+        }
+
+        let op = op(tokens.get(1)?)?;
+        Some((Some(first.clone()), op, 2))
+    }
+
+    fn collect_vars<'a>(fragments: &'a [Fragment], out: &mut Vec<&'a str>) {
+        for fragment in fragments {
+            match fragment {
+                Fragment::Token(_) => {}
+                Fragment::Var(name, _) => out.push(name),
+                Fragment::Group(_, _, children) => Fragment::collect_vars(children, out),
+                Fragment::Repeat(repeat) => Fragment::collect_vars(&repeat.body, out),
+            }
+        }
+    }
+}
+
+#[wyst_data]
+enum Binding {
+    One(Expanded),
+    Many(Vec<Binding>),
+}
+
+type Bindings = HashMap<String, Binding>;
+
+/// What to do with the tokens that are left after a fragment matched.
+type Continue<'a> = &'a dyn Fn(&[Expanded], Bindings) -> Option<Bindings>;
+
+/// Matches a pattern against a macro's arguments. Matching backtracks, so a repetition can be
+/// followed by tokens that could also have matched its body.
+struct Matcher<'a> {
+    sources: &'a [&'a Source],
+}
+
+impl<'a> Matcher<'a> {
+    fn match_all(&self, pattern: &[Fragment], tokens: &[Expanded]) -> Option<Bindings> {
+        self.match_fragments(pattern, tokens, Bindings::new(), &|rest, bindings| {
+            if rest.is_empty() {
+                Some(bindings)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn match_fragments(
+        &self,
+        fragments: &[Fragment],
+        tokens: &[Expanded],
+        mut bindings: Bindings,
+        then: Continue<'_>,
+    ) -> Option<Bindings> {
+        let (fragment, rest) = match fragments.split_first() {
+            Some(split) => split,
+            None => return then(tokens, bindings),
+        };
+
+        match fragment {
+            Fragment::Token(expected) => {
+                let (token, tokens) = tokens.split_first()?;
+
+                if expected.matches(token, self.sources) {
+                    self.match_fragments(rest, tokens, bindings, then)
+                } else {
+                    None
+                }
+            }
+            Fragment::Var(name, _) => {
+                let (token, tokens) = tokens.split_first()?;
+                bindings.insert(name.clone(), Binding::One(token.clone()));
+                self.match_fragments(rest, tokens, bindings, then)
+            }
+            Fragment::Group(delimiter, _, body) => {
+                let (token, tokens) = tokens.split_first()?;
+
+                match token.children() {
+                    Some((actual, children)) if actual == *delimiter => {
+                        let bindings =
+                            self.match_fragments(body, children, bindings, &|rest, b| {
+                                if rest.is_empty() {
+                                    Some(b)
+                                } else {
+                                    None
+                                }
+                            })?;
+
+                        self.match_fragments(rest, tokens, bindings, then)
+                    }
+                    _ => None,
+                }
+            }
+            Fragment::Repeat(repeat) => {
+                self.match_repeat(repeat, tokens, bindings, vec![], &|tokens, bindings| {
+                    self.match_fragments(rest, tokens, bindings, then)
+                })
+            }
+        }
+    }
+
+    /// Match another iteration of `repeat` if possible (greedily), and otherwise finish the
+    /// repetition and continue with the rest of the pattern.
+    fn match_repeat(
+        &self,
+        repeat: &Repeat,
+        tokens: &[Expanded],
+        bindings: Bindings,
+        iterations: Vec<Bindings>,
+        then: Continue<'_>,
+    ) -> Option<Bindings> {
+        let more = !(repeat.op == RepeatOp::ZeroOrOne && iterations.len() == 1);
+
+        if more {
+            let start = match (&repeat.separator, iterations.is_empty()) {
+                (Some(separator), false) => match tokens.split_first() {
+                    Some((first, rest)) if separator.matches(first, self.sources) => Some(rest),
+                    _ => None,
+                },
+                _ => Some(tokens),
+            };
+
+            if let Some(start) = start {
+                let matched = self.match_fragments(
+                    &repeat.body,
+                    start,
+                    Bindings::new(),
+                    &|rest, iteration| {
+                        // An iteration that doesn't consume anything would repeat forever.
+                        if rest.len() == tokens.len() {
+                            return None;
+                        }
+
+                        let mut iterations = iterations.clone();
+                        iterations.push(iteration);
+                        self.match_repeat(repeat, rest, bindings.clone(), iterations, then)
+                    },
+                );
+
+                if matched.is_some() {
+                    return matched;
+                }
+            }
+        }
+
+        if repeat.op == RepeatOp::OneOrMore && iterations.is_empty() {
+            return None;
+        }
+
+        let mut vars = vec![];
+        Fragment::collect_vars(&repeat.body, &mut vars);
+
+        let mut bindings = bindings;
+
+        for var in vars {
+            let values = iterations
+                .iter()
+                .filter_map(|iteration| iteration.get(var).cloned())
+                .collect();
+            bindings.insert(var.to_string(), Binding::Many(values));
+        }
+
+        then(tokens, bindings)
+    }
+}
+
+/// Substitutes the bindings from a match into a template.
+struct Transcriber<'a> {
+    expansion: &'a Expansion,
+}
+
+impl<'a> Transcriber<'a> {
+    fn transcribe(
+        &self,
+        fragments: &[Fragment],
+        bindings: &Bindings,
+        out: &mut Vec<Expanded>,
+    ) -> Result<(), Diagnostic> {
+        for fragment in fragments {
+            match fragment {
+                Fragment::Token(token) => out.push(token.expanded(self.expansion)),
+                Fragment::Var(name, span) => match bindings.get(name) {
+                    Some(Binding::One(token)) => out.push(token.clone()),
+                    Some(Binding::Many(_)) => {
+                        return Err(Diagnostic::error(
+                            *span,
+                            format!(
+                                "`${}` repeats, so it must be used inside of `$( ... )`",
+                                name
+                            ),
+                        ))
+                    }
+                    None => {
+                        return Err(Diagnostic::error(
+                            *span,
+                            format!("`${}` isn't bound by the pattern", name),
+                        ))
+                    }
+                },
+                Fragment::Group(delimiter, span, body) => {
+                    let mut children = vec![];
+                    self.transcribe(body, bindings, &mut children)?;
+
+                    out.push(Expanded {
+                        token: ExpandedToken::Delimited(*delimiter, children),
+                        provenance: Provenance::expanded(
+                            self.expansion.definition_source().clone(),
+                            *span,
+                            self.expansion.clone(),
+                        ),
+                    });
+                }
+                Fragment::Repeat(repeat) => self.transcribe_repeat(repeat, bindings, out)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn transcribe_repeat(
+        &self,
+        repeat: &Repeat,
+        bindings: &Bindings,
+        out: &mut Vec<Expanded>,
+    ) -> Result<(), Diagnostic> {
+        let mut vars = vec![];
+        Fragment::collect_vars(&repeat.body, &mut vars);
+
+        let mut count: Option<(&str, usize)> = None;
+
+        for var in vars {
+            if let Some(Binding::Many(values)) = bindings.get(var) {
+                match count {
+                    Some((first, len)) if len != values.len() => {
+                        return Err(Diagnostic::error(
+                            repeat.span,
+                            format!(
+                                "`${}` repeats {} times, but `${}` repeats {} times",
+                                first,
+                                len,
+                                var,
+                                values.len()
+                            ),
+                        ))
+                    }
+                    Some(_) => {}
+                    None => count = Some((var, values.len())),
+                }
+            }
+        }
+
+        let len = match count {
+            Some((_, len)) => len,
+            None => {
+                return Err(Diagnostic::error(
+                    repeat.span,
+                    "a repetition must use a variable that repeats",
+                ))
+            }
+        };
+
+        for i in 0..len {
+            if i > 0 {
+                if let Some(separator) = &repeat.separator {
+                    out.push(separator.expanded(self.expansion));
+                }
+            }
+
+            let mut iteration = bindings.clone();
+
+            for (name, binding) in bindings {
+                if let Binding::Many(values) = binding {
+                    if values.len() == len {
+                        iteration.insert(name.clone(), values[i].clone());
+                    }
+                }
+            }
+
+            self.transcribe(&repeat.body, &iteration, out)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[wyst_data]
+struct MacroRule {
+    pattern: Vec<Fragment>,
+    template: Vec<Fragment>,
+}
+
+/// A macro definition: a name and a list of rules, along with the source that the definition
+/// was read from.
+#[wyst_data]
+pub struct MacroDef {
+    name: String,
+    source: Source,
+    span: Span,
+    rules: Vec<MacroRule>,
+}
+
+impl MacroDef {
+    /// Parse the body of a macro definition, which is a list of `(pattern) => { template }` rules
+    /// separated by `;`. `span` is the span of the whole definition.
+    pub fn parse(
+        name: impl Into<String>,
+        span: Span,
+        body: &[Spanned<Token>],
+        source: &Source,
+    ) -> Result<MacroDef, Diagnostic> {
+        let tokens = Expanded::from_tokens(body, source);
+        let sources = &[source];
+        let mut rules = vec![];
+        let mut i = 0;
+
+        while i < tokens.len() {
+            let rule = match &tokens[i..] {
+                [pattern, eq, gt, template, ..]
+                    if eq.is_text("=", sources) && gt.is_text(">", sources) =>
+                {
+                    match (pattern.children(), template.children()) {
+                        (Some((_, pattern)), Some((_, template))) => Some(MacroRule {
+                            pattern: Fragment::parse_all(pattern, sources)?,
+                            template: Fragment::parse_all(template, sources)?,
+                        }),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+
+            match rule {
+                Some(rule) => rules.push(rule),
+                None => {
+                    return Err(Diagnostic::error(
+                        tokens[i].span(),
+                        "expected a macro rule of the form `(pattern) => { template }`",
+                    ))
+                }
+            }
+
+            i += 4;
+
+            if tokens.get(i).map(|t| t.is_text(";", sources)) == Some(true) {
+                i += 1;
+            }
+        }
+
+        Ok(MacroDef {
+            name: name.into(),
+            source: source.clone(),
+            span,
+            rules,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The source that the macro was defined in.
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The result of expanding the macro calls in a list of token trees.
+#[wyst_data]
+pub struct ExpandResult {
+    tokens: Vec<Expanded>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ExpandResult {
+    pub fn tokens(&self) -> &[Expanded] {
+        &self.tokens
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+
+    pub fn into_tokens(self) -> Vec<Expanded> {
+        self.tokens
+    }
+}
+
+/// Expands calls of the form `name!( ... )` (with any delimiter) to the macros that it knows
+/// about. The output of an expansion is expanded again, so macros can call other macros (and
+/// themselves).
+pub struct Expander<'source> {
+    source: &'source Source,
+    macros: HashMap<String, MacroDef>,
+    next_id: usize,
+}
+
+impl<'source> Expander<'source> {
+    pub fn new(source: &'source Source) -> Expander<'source> {
+        Expander {
+            source,
+            macros: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn with_macro(mut self, definition: MacroDef) -> Self {
+        self.macros.insert(definition.name.clone(), definition);
+        self
+    }
+
+    /// The sources that expanded tokens can point into: the source that the macros are called
+    /// from, followed by the sources of the macro definitions.
+    pub fn sources(&self) -> Vec<&Source> {
+        sources(self.source, &self.macros)
+    }
+
+    pub fn expand(&mut self, tokens: &[Spanned<Token>]) -> ExpandResult {
+        let tokens = Expanded::from_tokens(tokens, self.source);
+        let mut diagnostics = vec![];
+
+        let mut expanding = Expanding {
+            sources: sources(self.source, &self.macros),
+            macros: &self.macros,
+            next_id: &mut self.next_id,
+        };
+
+        let tokens = expanding.expand_all(tokens, 0, &mut diagnostics);
+
+        ExpandResult {
+            tokens,
+            diagnostics,
+        }
+    }
+}
+
+fn sources<'a>(source: &'a Source, macros: &'a HashMap<String, MacroDef>) -> Vec<&'a Source> {
+    std::iter::once(source)
+        .chain(macros.values().map(MacroDef::source))
+        .collect()
+}
+
+/// The state of a single [Expander::expand].
+struct Expanding<'a> {
+    sources: Vec<&'a Source>,
+    macros: &'a HashMap<String, MacroDef>,
+    next_id: &'a mut usize,
+}
+
+impl<'a> Expanding<'a> {
+    fn expand_all(
+        &mut self,
+        tokens: Vec<Expanded>,
+        depth: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<Expanded> {
+        let mut out = vec![];
+        let mut i = 0;
+
+        while i < tokens.len() {
+            if let Some(name) = self.call_at(&tokens[i..]) {
+                let call = &tokens[i..i + 3];
+
+                match self.expand_call(&name, call, depth) {
+                    Ok(expanded) => out.extend(self.expand_all(expanded, depth + 1, diagnostics)),
+                    Err(error) => {
+                        diagnostics.push(error);
+                        diagnostics.extend(call[0].provenance.notes());
+                        out.extend(call.iter().cloned());
+                    }
+                }
+
+                i += 3;
+                continue;
+            }
+
+            let token = tokens[i].clone();
+
+            match token.token {
+                ExpandedToken::Delimited(delimiter, children) => out.push(Expanded {
+                    token: ExpandedToken::Delimited(
+                        delimiter,
+                        self.expand_all(children, depth, diagnostics),
+                    ),
+                    provenance: token.provenance,
+                }),
+                ExpandedToken::Leaf(_) => out.push(token),
+            }
+
+            i += 1;
+        }
+
+        out
+    }
+
+    /// If `tokens` starts with a call to a known macro, return the macro's name.
+    fn call_at(&self, tokens: &[Expanded]) -> Option<String> {
+        match tokens {
+            [name, bang, args, ..]
+                if bang.is_text("!", &self.sources)
+                    && args.children().is_some()
+                    && name.span().end() == bang.span().start() =>
+            {
+                let name = name.known_text(&self.sources);
+
+                if self.macros.contains_key(name) {
+                    Some(name.to_string())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn expand_call(
+        &mut self,
+        name: &str,
+        call: &[Expanded],
+        depth: usize,
+    ) -> Result<Vec<Expanded>, Diagnostic> {
+        let call_site = call[0].span().until(call[2].span());
+
+        if depth >= RECURSION_LIMIT {
+            return Err(Diagnostic::error(
+                call_site,
+                format!("recursion limit reached while expanding `{}!`", name),
+            ));
+        }
+
+        let id = *self.next_id;
+        *self.next_id += 1;
+
+        let definition = &self.macros[name];
+        let expansion = Expansion::new(
+            id,
+            name,
+            (definition.source.id(), definition.span),
+            (call[0].provenance.source_id().clone(), call_site),
+        )
+        .with_parent(call[0].provenance.expansion().cloned());
+
+        let (_, args) = call[2].children().expect("BUG: checked in call_at");
+        let matcher = Matcher {
+            sources: &self.sources,
+        };
+
+        for rule in &definition.rules {
+            if let Some(bindings) = matcher.match_all(&rule.pattern, args) {
+                let mut out = vec![];
+                Transcriber {
+                    expansion: &expansion,
+                }
+                .transcribe(&rule.template, &bindings, &mut out)?;
+
+                return Ok(out);
+            }
+        }
+
+        Err(Diagnostic::error(
+            call_site,
+            format!("no rules of `{}!` matched this call", name),
+        ))
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::{delegate::DefaultDelegate, standard::FlatToken};
+
+        /// The source is a macro definition (a braced list of rules) followed by the program.
+        struct Test {
+            source: Source,
+        }
+
+        impl Test {
+            fn new(name: &str, source: &str) -> (Test, MacroDef) {
+                let source = Source::new("<test>", source);
+                let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+                let definition = match tokens[0].item() {
+                    Token::Delimited(delimited) => {
+                        MacroDef::parse(name, tokens[0].span(), delimited.children(), &source)
+                            .unwrap()
+                    }
+                    other => panic!("expected a macro definition, got {:?}", other),
+                };
+
+                (Test { source }, definition)
+            }
+
+            fn expand(&self, definition: MacroDef) -> ExpandResult {
+                let tokens = FlatToken::read_tree::<DefaultDelegate>(&self.source).into_tokens();
+                Expander::new(&self.source)
+                    .with_macro(definition)
+                    .expand(&tokens[1..])
+            }
+
+            fn text(&self, result: &ExpandResult) -> String {
+                expanded_text(result.tokens(), &[&self.source]).unwrap()
+            }
+        }
+    }),
+    tests(
+        ("substitution", {
+            let (test, swap) = Test::new("swap", "{ ($a,$b) => { ($b $a) } } swap!(x, [y z]) w");
+            let result = test.expand(swap);
+
+            assert_eq!(result.diagnostics(), &[]);
+            assert_eq!(test.text(&result), "([y z] x) w");
+        }),
+        ("repetition", {
+            let (test, list) = Test::new(
+                "list",
+                "{ ($($x),*) => { [$($x);*] } ; ($x $($y)+) => { $($y $x)+ } } list!(a, b,c) list!() list!(f g h)",
+            );
+            let result = test.expand(list);
+
+            assert_eq!(result.diagnostics(), &[]);
+            assert_eq!(test.text(&result), "[a ; b ; c] [] g f h f");
+        }),
+        ("recursion", {
+            let (test, rev) = Test::new(
+                "rev",
+                "{ () => { } ; ($x $($rest)*) => { rev!($($rest)*) $x } } rev!(a b c)",
+            );
+            let result = test.expand(rev);
+
+            assert_eq!(result.diagnostics(), &[]);
+            assert_eq!(test.text(&result), "c b a");
+        }),
+        ("provenance", {
+            let (test, swap) = Test::new("swap", "{ ($a $b) => { ($b $a) } } swap!(x y)");
+            let result = test.expand(swap);
+            let group = &result.tokens()[0];
+            let expansion = group.provenance().expansion().unwrap();
+
+            assert_eq!(expansion.name(), "swap");
+            assert_eq!(
+                test.source.slice(expansion.definition()),
+                "{ ($a $b) => { ($b $a) } }"
+            );
+            assert_eq!(test.source.slice(expansion.call_site()), "swap!(x y)");
+            assert_eq!(test.source.slice(group.span()), "($b $a)");
+
+            let y = match group.token() {
+                ExpandedToken::Delimited(_, children) => &children[0],
+                other => panic!("expected a group, got {:?}", other),
+            };
+
+            assert_eq!(y.text(&[&test.source]), Some("y"));
+            assert_eq!(y.provenance().expansion(), None);
+        }),
+        ("nested provenance", {
+            let (test, twice) = Test::new(
+                "twice",
+                "{ (once $x) => { [$x] } ; ($x) => { twice!(once $x) twice!(once $x) } } twice!(a)",
+            );
+            let result = test.expand(twice);
+            let inner = &result.tokens()[0];
+
+            assert_eq!(test.text(&result), "[a] [a]");
+            assert_eq!(
+                inner
+                    .provenance()
+                    .expansions()
+                    .map(|e| test.source.slice(e.call_site()))
+                    .collect::<Vec<_>>(),
+                vec!["twice!(once $x)", "twice!(a)"]
+            );
+        }),
+        ("definition in another source", {
+            let defs = Source::new("defs.wyst", "{ ($a) => { [$a here] } }");
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&defs).into_tokens();
+            let wrap = match tokens[0].item() {
+                Token::Delimited(delimited) => {
+                    MacroDef::parse("wrap", tokens[0].span(), delimited.children(), &defs).unwrap()
+                }
+                other => panic!("expected a macro definition, got {:?}", other),
+            };
+
+            let main = Source::new("main.wyst", "wrap!(x) tail");
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&main).into_tokens();
+            let mut expander = Expander::new(&main).with_macro(wrap);
+            let result = expander.expand(&tokens);
+            let sources = expander.sources();
+
+            assert_eq!(result.diagnostics(), &[]);
+            assert_eq!(
+                expanded_text(result.tokens(), &sources),
+                Some("[x here] tail".to_string())
+            );
+
+            let group = &result.tokens()[0];
+            let expansion = group.provenance().expansion().unwrap();
+
+            assert_eq!(group.provenance().source_id(), &defs.id());
+            assert_eq!(expansion.definition_source(), &defs.id());
+            assert_eq!(expansion.call_site_source(), &main.id());
+            assert_eq!(main.slice(expansion.call_site()), "wrap!(x)");
+
+            let x = match group.token() {
+                ExpandedToken::Delimited(_, children) => &children[0],
+                other => panic!("expected a group, got {:?}", other),
+            };
+
+            assert_eq!(x.provenance().source_id(), &main.id());
+
+            // Another version of defs.wyst is a different source, and a missing source has no
+            // text.
+            let edited = Source::new("defs.wyst", "{ ($a) => { [$a] } }");
+
+            assert_eq!(group.text(&[&main, &edited]), None);
+            assert_eq!(expanded_text(result.tokens(), &[&main, &edited]), None);
+            assert_eq!(
+                expanded_text(result.tokens(), &[&edited, &main, &defs]),
+                Some("[x here] tail".to_string())
+            );
+        }),
+        ("hygiene", {
+            let (test, bind) = Test::new("bind", "{ ($x) => { let tmp = $x ; tmp } } bind!(tmp)");
+            let result = test.expand(bind);
+            let tokens = result.tokens();
+
+            assert_eq!(test.text(&result), "let tmp = tmp ; tmp");
+            assert!(tokens[1].same_name(&tokens[5], &[&test.source]));
+            assert!(!tokens[1].same_name(&tokens[3], &[&test.source]));
+        }),
+        ("errors", {
+            let (test, pair) = Test::new("pair", "{ ($a $b) => { $a $b } } pair!(x) pair!(x y)");
+            let result = test.expand(pair);
+
+            assert_eq!(
+                result.diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(25, 33),
+                    "no rules of `pair!` matched this call"
+                )]
+            );
+            assert_eq!(test.text(&result), "pair ! (x) x y");
+
+            let (test, forever) = Test::new("forever", "{ () => { forever!() } } forever!()");
+            let result = test.expand(forever);
+
+            assert!(result.has_errors());
+            assert_eq!(
+                result.diagnostics()[0].message(),
+                "recursion limit reached while expanding `forever!`"
+            );
+            assert_eq!(
+                result.diagnostics()[1].message(),
+                "in this expansion of `forever!`"
+            );
+        })
+    )
+);
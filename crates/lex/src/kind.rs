@@ -0,0 +1,241 @@
+use logos::Logos;
+use wyst_core::{unit_tests, wyst_copy, WystCopy};
+use wyst_source::{Span, Spanned};
+
+use crate::{
+    delegate::QuoteResult,
+    standard::{Delimiter, FlatToken},
+    tree::Leaf,
+};
+
+/// How the reader should treat a token.
+#[wyst_copy]
+pub enum TokenClass<L>
+where
+    L: LeafKind,
+{
+    /// An atomic token, which becomes a leaf in the token tree.
+    Leaf(L),
+    Open(Delimiter),
+    Close(Delimiter),
+    EOF,
+    Error,
+}
+
+/// `TokenKind` describes a flat token that can be read into a token tree. It classifies each token
+/// as a leaf, an opening or closing delimiter, the end of the input, or an error.
+///
+/// `FlatToken` implements `TokenKind`, but a language that needs its own atomic tokens can use its
+/// own `Logos` enum instead (see [lex_logos]) and still get bracket-aware token trees.
+pub trait TokenKind: WystCopy {
+    /// The type of the leaves in the token tree.
+    type Leaf: LeafKind;
+
+    fn classify(self) -> TokenClass<Self::Leaf>;
+}
+
+/// `LeafKind` describes the leaves of a token tree.
+pub trait LeafKind: WystCopy {
+    /// The leaf that the reader creates for the end of the input.
+    fn eof() -> Self;
+
+    /// The leaf that the reader creates for input it can't make sense of (such as a closing
+    /// delimiter that doesn't close anything).
+    fn error() -> Self;
+
+    /// Trivia (such as whitespace and comments) doesn't affect the meaning of the source.
+    fn is_trivia(self) -> bool;
+}
+
+impl TokenKind for FlatToken {
+    type Leaf = Leaf;
+
+    fn classify(self) -> TokenClass<Leaf> {
+        match self {
+            FlatToken::Error => TokenClass::Error,
+            FlatToken::Word => TokenClass::Leaf(Leaf::Word),
+            FlatToken::Whitespace => TokenClass::Leaf(Leaf::Whitespace),
+            FlatToken::Newline => TokenClass::Leaf(Leaf::Newline),
+            FlatToken::Quoted((quote, inner)) => {
+                TokenClass::Leaf(Leaf::Quoted(QuoteResult::new(quote, inner)))
+            }
+            FlatToken::Open(delimiter) => TokenClass::Open(delimiter),
+            FlatToken::Close(delimiter) => TokenClass::Close(delimiter),
+            FlatToken::Comment(body) => TokenClass::Leaf(Leaf::Comment(body)),
+            FlatToken::Shebang => TokenClass::Leaf(Leaf::Shebang),
+            FlatToken::FrontMatter(front_matter) => {
+                TokenClass::Leaf(Leaf::FrontMatter(front_matter))
+            }
+            FlatToken::EOF => TokenClass::EOF,
+        }
+    }
+}
+
+impl LeafKind for Leaf {
+    fn eof() -> Self {
+        Leaf::EOF
+    }
+
+    fn error() -> Self {
+        Leaf::Error
+    }
+
+    fn is_trivia(self) -> bool {
+        matches!(self, Leaf::Whitespace | Leaf::Newline | Leaf::Comment(_))
+    }
+}
+
+/// Lex a source with a `Logos` token enum. Unlike `FlatToken::lex_source`, the tokens don't end
+/// with an explicit EOF token, which is fine because the reader adds one when the input runs out.
+pub fn lex_logos<'source, K>(source: &'source str) -> impl Iterator<Item = Spanned<K>> + 'source
+where
+    K: TokenKind + Logos<'source, Source = str> + 'source,
+    K::Extras: Default,
+{
+    K::lexer(source)
+        .spanned()
+        .map(|(token, span)| Spanned::new(Span::from(span), token))
+}
+
+unit_tests!(
+    all({
+        use crate::{
+            arena::TokenArena,
+            tree::{Token, TokenTree},
+        };
+        use wyst_source::{AddSpan, Diagnostic, Source};
+
+        /// A small expression language with numbers and regex literals, which `FlatToken` would
+        /// have lexed as words.
+        #[wyst_copy]
+        #[derive(Logos)]
+        enum Calc {
+            #[error]
+            Error,
+
+            #[regex(r"[0-9]+")]
+            Number,
+
+            #[regex(r"[a-z]+")]
+            Ident,
+
+            #[regex(r"/[^/\n]*/")]
+            Regex,
+
+            #[regex(r"[+*=-]")]
+            Operator,
+
+            #[regex(r"[ \t\n]+")]
+            Whitespace,
+
+            #[token("(", |_| Delimiter::Paren)]
+            #[token("[", |_| Delimiter::Bracket)]
+            Open(Delimiter),
+
+            #[token(")", |_| Delimiter::Paren)]
+            #[token("]", |_| Delimiter::Bracket)]
+            Close(Delimiter),
+
+            EOF,
+        }
+
+        impl TokenKind for Calc {
+            type Leaf = Calc;
+
+            fn classify(self) -> TokenClass<Calc> {
+                match self {
+                    Calc::Error => TokenClass::Error,
+                    Calc::Open(delimiter) => TokenClass::Open(delimiter),
+                    Calc::Close(delimiter) => TokenClass::Close(delimiter),
+                    Calc::EOF => TokenClass::EOF,
+                    other => TokenClass::Leaf(other),
+                }
+            }
+        }
+
+        impl LeafKind for Calc {
+            fn eof() -> Self {
+                Calc::EOF
+            }
+
+            fn error() -> Self {
+                Calc::Error
+            }
+
+            fn is_trivia(self) -> bool {
+                self == Calc::Whitespace
+            }
+        }
+
+        fn leaf(leaf: Calc, span: (usize, usize)) -> Spanned<Token<Calc>> {
+            Token::Leaf(leaf).spanned(Span::new(span.0, span.1))
+        }
+    }),
+    tests(
+        ("custom token kinds (TokenTree)", {
+            let source = Source::new("<test>", "x = (1 + [/a(b/])");
+            let result = TokenTree::read(&source, lex_logos::<Calc>(source.contents()));
+
+            assert_eq!(
+                result.tokens(),
+                &[
+                    leaf(Calc::Ident, (0, 1)),
+                    leaf(Calc::Whitespace, (1, 2)),
+                    leaf(Calc::Operator, (2, 3)),
+                    leaf(Calc::Whitespace, (3, 4)),
+                    Token::delimited(
+                        Delimiter::Paren,
+                        vec![
+                            leaf(Calc::Number, (5, 6)),
+                            leaf(Calc::Whitespace, (6, 7)),
+                            leaf(Calc::Operator, (7, 8)),
+                            leaf(Calc::Whitespace, (8, 9)),
+                            Token::delimited(Delimiter::Bracket, vec![leaf(Calc::Regex, (10, 15))])
+                                .spanned(Span::new(9, 16)),
+                        ]
+                    )
+                    .spanned(Span::new(4, 17)),
+                    Token::Leaf(Calc::EOF).spanned(Span::eof(17)),
+                ]
+            );
+            assert_eq!(result.diagnostics(), &[]);
+        }),
+        ("custom token kinds (TokenArena)", {
+            let source = Source::new("<test>", "x = (1 + [/a(b/])");
+            let tree = TokenTree::read(&source, lex_logos::<Calc>(source.contents()));
+            let arena = TokenArena::read(&source, lex_logos::<Calc>(source.contents()));
+
+            assert_eq!(arena.to_tokens(), tree.tokens());
+        }),
+        ("custom token kinds (errors)", {
+            let source = Source::new("<test>", "1 ] ?");
+            let result = TokenTree::read(&source, lex_logos::<Calc>(source.contents()));
+
+            assert_eq!(
+                result.tokens(),
+                &[
+                    leaf(Calc::Number, (0, 1)),
+                    leaf(Calc::Whitespace, (1, 2)),
+                    leaf(Calc::Error, (2, 3)),
+                    leaf(Calc::Whitespace, (3, 4)),
+                    leaf(Calc::Error, (4, 5)),
+                    Token::Leaf(Calc::EOF).spanned(Span::eof(5)),
+                ]
+            );
+            assert_eq!(
+                result.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(2, 3), "unexpected `]`"),
+                    Diagnostic::error(Span::new(4, 5), "unexpected input"),
+                ]
+            );
+        }),
+        ("FlatToken trivia", {
+            assert!(Leaf::Whitespace.is_trivia());
+            assert!(Leaf::Newline.is_trivia());
+            assert!(Leaf::Comment(Span::new(0, 0)).is_trivia());
+            assert!(!Leaf::Word.is_trivia());
+            assert!(!Leaf::EOF.is_trivia());
+        })
+    )
+);
@@ -1,17 +1,49 @@
+// Lets the `tokens!` macro refer to `::wyst_lex` from inside of this crate.
+extern crate self as wyst_lex;
+
 #[macro_use]
 pub(crate) mod macros;
 
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
+mod arena;
+mod batch;
 mod delegate;
+mod diff;
+mod events;
+mod expand;
+mod kind;
 mod reader;
+mod rewrite;
+mod search;
+mod split;
 mod standard;
+mod structure;
+#[cfg(test)]
+mod testing;
 mod token_builder;
 mod top_builder;
 mod tree;
+mod trivia;
 
-pub use delegate::QuoteResult;
-pub use standard::FlatToken;
-pub use standard::Quote;
+pub use arena::{Children, DelimitedRef, NodeRef, TokenArena, TokenId, TokenRef};
+pub use batch::{read_batch, BatchOptions, BatchResult};
+pub use delegate::{DefaultDelegate, LexContext, LexHint, QuoteResult, StandardDelegate};
+pub use diff::{diff, Change, DiffStyle, Side, TreeDiff};
+pub use events::{Event, EventReader};
+pub use expand::{expanded_text, ExpandResult, Expanded, ExpandedToken, Expander, MacroDef};
+pub use kind::{lex_logos, LeafKind, TokenClass, TokenKind};
+pub use reader::{ReadTokens, Reader, ReaderNext};
+pub use rewrite::{Edit, FixpointResult, RewriteResult, RewriteRule, Rewriter};
+pub use search::{Capture, Match, Pattern};
+pub use split::{split_list, ListItem, SeparatedList, Separator};
+pub use standard::{Delimiter, Fence, Quote};
+pub use standard::{FlatToken, LexTop};
+pub use structure::{BracketPair, FoldKind, FoldingRange, Structure};
 pub use token_builder::TokenBuilder;
 pub use top_builder::TopBuilder;
+pub use tree::{Delimited, Leaf, ReadResult, Token, TokenTree};
+pub use trivia::{attach_trivia, Attached, AttachedDelimited, AttachedToken, TriviaList};
 
+pub use wyst_core::tokens;
 pub use wyst_source;
@@ -1,10 +1,9 @@
 use wyst_core::WystData;
-use wyst_source::{Source, Span, Spanned};
+use wyst_source::{Diagnostic, Source, Span, Spanned};
 
 use crate::{
-    delegate::QuoteResult,
-    standard::{Delimiter, FlatToken},
-    tree::Token,
+    kind::{LeafKind, TokenClass, TokenKind},
+    standard::Delimiter,
 };
 
 pub enum ReaderNext<T: WystData> {
@@ -13,67 +12,111 @@ pub enum ReaderNext<T: WystData> {
     EOF,
 }
 
-/// Reads a sequence of tokens into a structure
-pub trait Reader {
-    fn next(&mut self) -> ReaderNext<Spanned<Token>>;
-    fn word(&mut self, source: &Source, span: Span);
-    fn whitespace(&mut self, source: &Source, span: Span);
-    fn newline(&mut self, source: &Source, span: Span);
-    fn comment(&mut self, source: &Source, span: Span, body: Span);
-    fn quoted(&mut self, source: &Source, span: Span, quote: QuoteResult);
+/// Reads a sequence of tokens into a structure. The tokens have already been classified by their
+/// [TokenKind], so a `Reader` works the same way for every kind of token.
+pub trait Reader<L: LeafKind> {
+    type Item: WystData;
+
+    fn next(&mut self) -> ReaderNext<Self::Item>;
+    fn leaf(&mut self, source: &Source, span: Span, leaf: L);
     fn open(&mut self, source: &Source, span: Span, delimiter: Delimiter);
     fn close(&mut self, source: &Source, span: Span, delimiter: Delimiter);
     fn eof(&mut self, source: &Source, span: Span);
+    fn error(&mut self, source: &Source, span: Span);
+}
+
+/// What a [Reader] should do with a closing delimiter.
+pub(crate) enum Closing {
+    /// The closing delimiter matches one of the open delimiters. The `unclosed` innermost open
+    /// delimiters were never closed, and should be closed (and reported) before it.
+    Matches { unclosed: usize },
+    /// The closing delimiter doesn't match any open delimiter.
+    Unexpected,
+}
+
+impl Closing {
+    /// Find the open delimiter that `close` closes. The `open` delimiters are ordered from the
+    /// innermost to the outermost.
+    pub(crate) fn find(open: impl Iterator<Item = Delimiter>, close: Delimiter) -> Closing {
+        for (unclosed, delimiter) in open.enumerate() {
+            if delimiter == close {
+                return Closing::Matches { unclosed };
+            }
+        }
+
+        Closing::Unexpected
+    }
+}
+
+pub(crate) fn unclosed_delimiter(delimiter: Delimiter, open: Span) -> Diagnostic {
+    Diagnostic::error(open, format!("unclosed `{}`", delimiter.open_char()))
 }
 
-pub struct ReadTokens<'source, T: Reader, I: Iterator<Item = Spanned<FlatToken>>> {
+pub(crate) fn unexpected_close(delimiter: Delimiter, close: Span) -> Diagnostic {
+    Diagnostic::error(close, format!("unexpected `{}`", delimiter.close_char()))
+}
+
+pub(crate) fn unexpected_input(span: Span) -> Diagnostic {
+    Diagnostic::error(span, "unexpected input")
+}
+
+pub struct ReadTokens<'source, K, T, I>
+where
+    K: TokenKind,
+    T: Reader<K::Leaf>,
+    I: Iterator<Item = Spanned<K>>,
+{
     reader: T,
     source: &'source Source,
     input: I,
+    saw_eof: bool,
 }
 
-impl<'source, T, I> ReadTokens<'source, T, I>
+impl<'source, K, T, I> ReadTokens<'source, K, T, I>
 where
-    T: Reader,
-    I: Iterator<Item = Spanned<FlatToken>>,
+    K: TokenKind,
+    T: Reader<K::Leaf>,
+    I: Iterator<Item = Spanned<K>>,
 {
-    pub fn new(reader: T, tokens: I, source: &'source Source) -> ReadTokens<'source, T, I> {
+    pub fn new(reader: T, tokens: I, source: &'source Source) -> ReadTokens<'source, K, T, I> {
         Self {
             reader,
             input: tokens,
             source,
+            saw_eof: false,
         }
     }
 
-    fn process_token(&mut self, token: Spanned<FlatToken>) {
+    pub fn into_reader(self) -> T {
+        self.reader
+    }
+
+    fn process_token(&mut self, token: Spanned<K>) {
         let span = token.span();
-        let item = token.item();
 
         let reader = &mut self.reader;
         let source = &self.source;
 
-        match item {
-            FlatToken::Error => todo!("error token (can we make this impossible?)"),
-            FlatToken::Word => reader.word(source, span),
-            FlatToken::Whitespace => reader.whitespace(source, span),
-            FlatToken::Newline => reader.newline(source, span),
-            FlatToken::Comment(body) => reader.comment(source, span, *body),
-            FlatToken::Quoted((quote, inner)) => {
-                reader.quoted(source, span, QuoteResult::new(*quote, *inner))
+        match token.item().classify() {
+            TokenClass::Leaf(leaf) => reader.leaf(source, span, leaf),
+            TokenClass::Open(d) => reader.open(source, span, d),
+            TokenClass::Close(d) => reader.close(source, span, d),
+            TokenClass::EOF => {
+                self.saw_eof = true;
+                reader.eof(source, span)
             }
-            FlatToken::Open(d) => reader.open(source, span, *d),
-            FlatToken::Close(d) => reader.close(source, span, *d),
-            FlatToken::EOF => reader.eof(source, span),
+            TokenClass::Error => reader.error(source, span),
         }
     }
 }
 
-impl<'source, T, I> Iterator for ReadTokens<'source, T, I>
+impl<'source, K, T, I> Iterator for ReadTokens<'source, K, T, I>
 where
-    T: Reader,
-    I: Iterator<Item = Spanned<FlatToken>>,
+    K: TokenKind,
+    T: Reader<K::Leaf>,
+    I: Iterator<Item = Spanned<K>>,
 {
-    type Item = Spanned<Token>;
+    type Item = T::Item;
 
     fn next(&mut self) -> Option<Self::Item> {
         loop {
@@ -83,9 +126,17 @@ where
                 ReaderNext::EOF => return None,
             }
 
-            let next = self.input.next()?;
-
-            self.process_token(next);
+            match self.input.next() {
+                Some(next) => self.process_token(next),
+                // Lexers that don't produce an EOF token of their own get one at the end of the
+                // source.
+                None if !self.saw_eof => {
+                    self.saw_eof = true;
+                    let eof = Span::eof(self.source.contents().len());
+                    self.reader.eof(self.source, eof);
+                }
+                None => return None,
+            }
         }
     }
 }
@@ -0,0 +1,432 @@
+//! Structural rewriting (codemods) on top of [crate::Pattern] search.
+//!
+//! A [RewriteRule] is a pattern plus a template. The template is plain text in which `$name` and
+//! `$$$name` are replaced with the source text that the metavariable captured (including any
+//! trivia inside of the capture). Everything outside of a match is left alone, and each
+//! replacement is trimmed down to the text that actually changed.
+
+use std::collections::HashSet;
+
+use wyst_core::{unit_tests, wyst_data};
+use wyst_source::{Diagnostic, Source, Span};
+
+use crate::{
+    delegate::StandardDelegate,
+    expand::is_ident,
+    search::{Match, Pattern},
+    standard::FlatToken,
+};
+
+#[wyst_data]
+enum TemplatePiece {
+    Text(String),
+    Var(String),
+}
+
+/// A pattern and the template that replaces its matches.
+#[wyst_data]
+pub struct RewriteRule {
+    pattern: Pattern,
+    template: Vec<TemplatePiece>,
+}
+
+impl RewriteRule {
+    /// Parse a rule. Every metavariable in the template has to be captured by the pattern.
+    pub fn parse<S>(pattern: &str, template: &str) -> Result<RewriteRule, Diagnostic>
+    where
+        S: for<'source> StandardDelegate<'source> + 'static,
+    {
+        let pattern = Pattern::parse::<S>(pattern)?;
+        let names = pattern.names();
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+
+            let sigil = if rest[dollar..].starts_with("$$$") {
+                3
+            } else {
+                1
+            };
+            let after = &rest[dollar + sigil..];
+            let len = after
+                .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            let name = &after[..len];
+
+            if !is_ident(name) {
+                text.push('$');
+                rest = &rest[dollar + 1..];
+                continue;
+            }
+
+            if !names.contains(&name) {
+                let start = template.len() - rest.len() + dollar;
+
+                return Err(Diagnostic::error(
+                    Span::new(start, start + sigil + len),
+                    format!("`{}` isn't captured by the pattern", name),
+                ));
+            }
+
+            if !text.is_empty() {
+                pieces.push(TemplatePiece::Text(std::mem::take(&mut text)));
+            }
+
+            pieces.push(TemplatePiece::Var(name.to_string()));
+            rest = &after[len..];
+        }
+
+        text.push_str(rest);
+
+        if !text.is_empty() {
+            pieces.push(TemplatePiece::Text(text));
+        }
+
+        Ok(RewriteRule {
+            pattern,
+            template: pieces,
+        })
+    }
+
+    fn render(&self, found: &Match, source: &Source) -> String {
+        self.template
+            .iter()
+            .map(|piece| match piece {
+                TemplatePiece::Text(text) => text.as_str(),
+                TemplatePiece::Var(name) => match found.get(name).and_then(|c| c.span()) {
+                    Some(span) => source.slice(span),
+                    None => "",
+                },
+            })
+            .collect()
+    }
+}
+
+/// A single replacement made by a rewrite.
+#[wyst_data]
+pub struct Edit {
+    matched: Span,
+    span: Span,
+    replacement: String,
+    output: Span,
+}
+
+impl Edit {
+    /// The region of the input that the rule matched.
+    pub fn matched(&self) -> Span {
+        self.matched
+    }
+
+    /// The (minimal) region of the input that was replaced.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    /// The region of the output that the matched region was rewritten to.
+    pub fn output(&self) -> Span {
+        self.output
+    }
+}
+
+/// The result of a single rewriting pass.
+#[wyst_data]
+pub struct RewriteResult {
+    output: Source,
+    edits: Vec<Edit>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl RewriteResult {
+    pub fn output(&self) -> &Source {
+        &self.output
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    /// Diagnostics about the input, such as matches that weren't rewritten because they overlap
+    /// with another match.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn changed(&self) -> bool {
+        !self.edits.is_empty()
+    }
+}
+
+/// The result of rewriting until nothing changes.
+#[wyst_data]
+pub struct FixpointResult {
+    passes: Vec<RewriteResult>,
+    converged: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl FixpointResult {
+    /// Every pass, in order. The spans in each pass refer to the output of the previous pass.
+    pub fn passes(&self) -> &[RewriteResult] {
+        &self.passes
+    }
+
+    pub fn output(&self) -> &Source {
+        self.passes
+            .last()
+            .expect("BUG: a fixpoint always has at least one pass")
+            .output()
+    }
+
+    pub fn converged(&self) -> bool {
+        self.converged
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+}
+
+/// Applies a list of rules to a source. When matches overlap, the one that starts first wins
+/// (and if they start at the same place, the earlier rule wins). The other matches are reported,
+/// and can be rewritten by another pass.
+#[derive(Default)]
+pub struct Rewriter {
+    rules: Vec<RewriteRule>,
+}
+
+impl Rewriter {
+    pub fn new() -> Rewriter {
+        Rewriter::default()
+    }
+
+    pub fn rule(mut self, rule: RewriteRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Rewrite every (non-overlapping) match in `source` once.
+    pub fn rewrite<S>(&self, source: &Source) -> RewriteResult
+    where
+        S: for<'source> StandardDelegate<'source> + 'static,
+    {
+        let tokens = FlatToken::read_tree::<S>(source).into_tokens();
+        let mut candidates = vec![];
+
+        for (priority, rule) in self.rules.iter().enumerate() {
+            for found in rule.pattern.search(&tokens, source) {
+                candidates.push((found.span(), priority, rule.render(&found, source)));
+            }
+        }
+
+        candidates.sort_by_key(|(span, priority, _)| {
+            let start: usize = span.start().into();
+            (start, *priority)
+        });
+
+        let mut edits = vec![];
+        let mut diagnostics = vec![];
+        // Several rules can match the same span, but it's only reported once.
+        let mut skipped = HashSet::new();
+        let mut output = String::new();
+        let mut copied: usize = 0;
+
+        for (matched, _, replacement) in candidates {
+            let start: usize = matched.start().into();
+            let end: usize = matched.end().into();
+
+            if start < copied {
+                if skipped.insert(matched) {
+                    diagnostics.push(Diagnostic::warning(
+                        matched,
+                        "this match overlaps with another match, so it wasn't rewritten",
+                    ));
+                }
+
+                continue;
+            }
+
+            let original = source.slice(matched);
+            let (prefix, suffix) = common_affixes(original, &replacement);
+
+            output.push_str(&source.contents()[copied..start]);
+            let output_start = output.len();
+            output.push_str(&replacement);
+            copied = end;
+
+            if prefix == original.len() && prefix == replacement.len() {
+                continue;
+            }
+
+            edits.push(Edit {
+                matched,
+                span: Span::new(start + prefix, end - suffix),
+                replacement: replacement[prefix..replacement.len() - suffix].to_string(),
+                output: Span::new(output_start, output.len()),
+            });
+        }
+
+        output.push_str(&source.contents()[copied..]);
+
+        RewriteResult {
+            output: Source::new(source.filename(), output),
+            edits,
+            diagnostics,
+        }
+    }
+
+    /// Rewrite `source` repeatedly, until a pass doesn't change anything or `limit` passes have
+    /// run.
+    pub fn rewrite_until_fixpoint<S>(&self, source: &Source, limit: usize) -> FixpointResult
+    where
+        S: for<'source> StandardDelegate<'source> + 'static,
+    {
+        let mut passes: Vec<RewriteResult> = vec![];
+
+        while passes.len() < limit.max(1) {
+            let input = passes.last().map(RewriteResult::output).unwrap_or(source);
+            let pass = self.rewrite::<S>(input);
+            let changed = pass.changed();
+
+            passes.push(pass);
+
+            if !changed {
+                return FixpointResult {
+                    passes,
+                    converged: true,
+                    diagnostics: vec![],
+                };
+            }
+        }
+
+        let diagnostics = vec![Diagnostic::error(
+            Span::eof(0),
+            format!("rewriting didn't converge after {} passes", passes.len()),
+        )];
+
+        FixpointResult {
+            passes,
+            converged: false,
+            diagnostics,
+        }
+    }
+}
+
+/// The lengths (in bytes) of the longest common prefix and suffix of `left` and `right`. The
+/// prefix and suffix never overlap, and always end on character boundaries.
+fn common_affixes(left: &str, right: &str) -> (usize, usize) {
+    let prefix: usize = left
+        .chars()
+        .zip(right.chars())
+        .take_while(|(l, r)| l == r)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    let suffix: usize = left[prefix..]
+        .chars()
+        .rev()
+        .zip(right[prefix..].chars().rev())
+        .take_while(|(l, r)| l == r)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+
+    (prefix, suffix)
+}
+
+unit_tests!(
+    all({
+        use crate::delegate::DefaultDelegate;
+
+        fn rule(pattern: &str, template: &str) -> RewriteRule {
+            RewriteRule::parse::<DefaultDelegate>(pattern, template).unwrap()
+        }
+
+        fn rewrite(rewriter: &Rewriter, source: &str) -> RewriteResult {
+            rewriter.rewrite::<DefaultDelegate>(&Source::new("<test>", source))
+        }
+    }),
+    tests(
+        ("rewrite", {
+            let rewriter = Rewriter::new().rule(rule("assert_eq($a, $b)", "assert($a == $b)"));
+            let source = Source::new("<test>", "before\n  assert_eq(foo, [1,  2])\nafter");
+            let result = rewriter.rewrite::<DefaultDelegate>(&source);
+            let output = result.output();
+
+            assert_eq!(output.contents(), "before\n  assert(foo == [1,  2])\nafter");
+            assert_eq!(result.diagnostics(), &[]);
+            assert_eq!(result.edits().len(), 1);
+
+            let edit = &result.edits()[0];
+
+            assert_eq!(source.slice(edit.matched()), "assert_eq(foo, [1,  2])");
+            assert_eq!(output.slice(edit.output()), "assert(foo == [1,  2])");
+            assert_eq!(source.slice(edit.span()), "_eq(foo,");
+            assert_eq!(edit.replacement(), "(foo ==");
+        }),
+        ("unchanged matches", {
+            let rewriter = Rewriter::new().rule(rule("f($x)", "f($x)"));
+            let result = rewrite(&rewriter, "f(a) f(b)");
+
+            assert!(!result.changed());
+            assert_eq!(result.output().contents(), "f(a) f(b)");
+        }),
+        ("overlapping matches", {
+            let rewriter = Rewriter::new()
+                .rule(rule("f($$$x)", "g($$$x)"))
+                .rule(rule("f(a)", "h()"));
+            let result = rewrite(&rewriter, "f(f(a)) f(a)");
+
+            assert_eq!(result.output().contents(), "g(f(a)) g(a)");
+            assert_eq!(
+                result.diagnostics(),
+                &[
+                    Diagnostic::warning(
+                        Span::new(2, 6),
+                        "this match overlaps with another match, so it wasn't rewritten"
+                    ),
+                    Diagnostic::warning(
+                        Span::new(8, 12),
+                        "this match overlaps with another match, so it wasn't rewritten"
+                    )
+                ]
+            );
+        }),
+        ("fixpoint", {
+            let rewriter = Rewriter::new().rule(rule("f($$$x)", "g($$$x)"));
+            let source = Source::new("<test>", "f(f(f(a)))");
+            let result = rewriter.rewrite_until_fixpoint::<DefaultDelegate>(&source, 10);
+
+            assert!(result.converged());
+            assert_eq!(result.output().contents(), "g(g(g(a)))");
+            assert_eq!(result.passes().len(), 4);
+
+            let grow = Rewriter::new().rule(rule("a", "a a"));
+            let result = grow.rewrite_until_fixpoint::<DefaultDelegate>(&source, 3);
+
+            assert!(!result.converged());
+            assert_eq!(result.passes().len(), 3);
+            assert_eq!(
+                result.diagnostics()[0].message(),
+                "rewriting didn't converge after 3 passes"
+            );
+        }),
+        ("template variables", {
+            assert_eq!(
+                RewriteRule::parse::<DefaultDelegate>("f($x)", "g($y)"),
+                Err(Diagnostic::error(
+                    Span::new(2, 4),
+                    "`y` isn't captured by the pattern"
+                ))
+            );
+
+            let rewriter = Rewriter::new().rule(rule("f($x)", "$ $x$$$"));
+            assert_eq!(rewrite(&rewriter, "f(a)").output().contents(), "$ a$$$");
+        })
+    )
+);
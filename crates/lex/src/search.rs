@@ -0,0 +1,713 @@
+//! Structural search over token trees.
+//!
+//! A [Pattern] is lexed with the same rules as the source that it searches, and matches a run of
+//! sibling token trees anywhere in the tree. Inside of a pattern:
+//!
+//! - `$name` matches a single token tree (a word, a quoted string or a delimited group)
+//! - `$$$name` matches a (possibly empty) sequence of token trees
+//! - anything else matches itself
+//!
+//! Search ignores trivia, and like the macro expander, it compares literal text in pieces (a run
+//! of identifier characters or a single punctuation character), so the pattern
+//! `call($x, { $$$ })` matches `call(a,{ b })` as well as `call(a, {})`. A metavariable matches
+//! whole words, so `f($x)` matches `f(a.b)` and `f(-1)`, but it stops early when the pattern has
+//! literal text right after it in the same word: `$x,` matches `a,` with `a` for `$x`. A
+//! metavariable that appears more than once has to match the same tokens every time. `$_` and
+//! `$$$` match without capturing anything.
+
+use std::ptr;
+
+use wyst_core::{unit_tests, wyst_data};
+use wyst_source::{Diagnostic, Source, Span, Spanned};
+
+use crate::{
+    delegate::StandardDelegate,
+    expand::{is_ident, word_pieces},
+    kind::LeafKind,
+    standard::{Delimiter, FlatToken},
+    tree::{Leaf, Token},
+};
+
+/// A token tree without its trivia.
+#[wyst_data]
+enum Node {
+    /// A word, split into pieces, or another leaf (such as a quoted string), which is a single
+    /// piece.
+    Leaf(Vec<Span>),
+    Group(Delimiter, Span, Vec<Node>),
+}
+
+impl Node {
+    fn read(tokens: &[Spanned<Token>], source: &Source) -> Vec<Node> {
+        tokens
+            .iter()
+            .filter_map(|token| match token.item() {
+                Token::Leaf(Leaf::EOF) => None,
+                Token::Leaf(leaf) if leaf.is_trivia() => None,
+                Token::Leaf(Leaf::Word) => Some(Node::Leaf(word_pieces(token.span(), source))),
+                Token::Leaf(_) => Some(Node::Leaf(vec![token.span()])),
+                Token::Delimited(delimited) => Some(Node::Group(
+                    delimited.delimiter(),
+                    token.span(),
+                    Node::read(delimited.children(), source),
+                )),
+            })
+            .collect()
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            Node::Leaf(pieces) => pieces[0].until(pieces[pieces.len() - 1]),
+            Node::Group(_, span, _) => *span,
+        }
+    }
+}
+
+#[wyst_data]
+enum Fragment {
+    Text(String),
+    Group(Delimiter, Vec<Fragment>),
+    Tree(Option<String>),
+    Sequence(Option<String>),
+}
+
+impl Fragment {
+    fn parse_all(nodes: &[Node], source: &Source) -> Vec<Fragment> {
+        let mut fragments = vec![];
+
+        for node in nodes {
+            match node {
+                Node::Group(delimiter, _, children) => fragments.push(Fragment::Group(
+                    *delimiter,
+                    Fragment::parse_all(children, source),
+                )),
+                Node::Leaf(pieces) => Fragment::parse_leaf(pieces, source, &mut fragments),
+            }
+        }
+
+        fragments
+    }
+
+    /// The pieces of a leaf are adjacent, so a metavariable (`$`, `$`, `$` and the name) is
+    /// always inside of a single leaf.
+    fn parse_leaf(pieces: &[Span], source: &Source, fragments: &mut Vec<Fragment>) {
+        let texts: Vec<&str> = pieces.iter().map(|piece| source.slice(*piece)).collect();
+        let is_dollar = |at: usize| texts.get(at) == Some(&"$");
+        let name_at = |at: usize| {
+            texts
+                .get(at)
+                .filter(|text| is_ident(text))
+                .map(|name| match *name {
+                    "_" => None,
+                    name => Some(name.to_string()),
+                })
+        };
+
+        let mut i = 0;
+
+        while i < texts.len() {
+            if is_dollar(i) && is_dollar(i + 1) && is_dollar(i + 2) {
+                match name_at(i + 3) {
+                    Some(name) => {
+                        fragments.push(Fragment::Sequence(name));
+                        i += 4;
+                    }
+                    None => {
+                        fragments.push(Fragment::Sequence(None));
+                        i += 3;
+                    }
+                }
+            } else if let (true, Some(name)) = (is_dollar(i), name_at(i + 1)) {
+                fragments.push(Fragment::Tree(name));
+                i += 2;
+            } else {
+                fragments.push(Fragment::Text(texts[i].to_string()));
+                i += 1;
+            }
+        }
+    }
+
+    fn collect_names<'a>(fragments: &'a [Fragment], out: &mut Vec<&'a str>) {
+        for fragment in fragments {
+            match fragment {
+                Fragment::Tree(Some(name)) | Fragment::Sequence(Some(name)) => {
+                    if !out.contains(&name.as_str()) {
+                        out.push(name)
+                    }
+                }
+                Fragment::Group(_, children) => Fragment::collect_names(children, out),
+                Fragment::Text(_) | Fragment::Tree(None) | Fragment::Sequence(None) => {}
+            }
+        }
+    }
+}
+
+/// What a metavariable captured.
+#[wyst_data]
+pub enum Capture {
+    Tree(Span),
+    /// The spans of the captured token trees. A word that was only partly captured is a single
+    /// span.
+    Sequence(Vec<Span>),
+}
+
+impl Capture {
+    /// The span of everything that was captured, or `None` for an empty sequence.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Capture::Tree(span) => Some(*span),
+            Capture::Sequence(spans) => match (spans.first(), spans.last()) {
+                (Some(first), Some(last)) => Some(first.until(*last)),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// A single match of a [Pattern].
+#[wyst_data]
+pub struct Match {
+    span: Span,
+    captures: Vec<(String, Capture)>,
+}
+
+impl Match {
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The captured metavariables, in the order that they first appear in the pattern.
+    pub fn captures(&self) -> &[(String, Capture)] {
+        &self.captures
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Capture> {
+        self.captures
+            .iter()
+            .find(|(capture, _)| capture == name)
+            .map(|(_, capture)| capture)
+    }
+}
+
+/// Something that a metavariable matched: some of the pieces of a leaf, or a delimited group.
+#[derive(Debug, Clone, Copy)]
+enum Step<'a> {
+    Pieces {
+        leaf: &'a [Span],
+        start: usize,
+        end: usize,
+    },
+    Group(&'a Node),
+}
+
+impl<'a> Step<'a> {
+    fn span(self) -> Span {
+        match self {
+            Step::Pieces { leaf, start, end } => leaf[start].until(leaf[end - 1]),
+            Step::Group(node) => node.span(),
+        }
+    }
+
+    /// Join a step to the step before it, if they're pieces of the same leaf.
+    fn join(self, next: Step<'a>) -> Option<Step<'a>> {
+        match (self, next) {
+            (
+                Step::Pieces { leaf, start, end },
+                Step::Pieces {
+                    leaf: next_leaf,
+                    start: next_start,
+                    end: next_end,
+                },
+            ) if ptr::eq(leaf, next_leaf) && end == next_start => Some(Step::Pieces {
+                leaf,
+                start,
+                end: next_end,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Where matching is in a list of siblings: the nodes that are left, and the first piece of the
+/// first node that hasn't been matched yet.
+#[derive(Debug, Clone, Copy)]
+struct Cursor<'a> {
+    nodes: &'a [Node],
+    piece: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(nodes: &'a [Node], piece: usize) -> Cursor<'a> {
+        Cursor { nodes, piece }
+    }
+
+    fn is_end(self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The index of the current node in `siblings` and the current piece.
+    fn position(self, siblings: &[Node]) -> (usize, usize) {
+        (siblings.len() - self.nodes.len(), self.piece)
+    }
+
+    /// The offset where everything before the cursor ends.
+    fn end_of_previous(self, siblings: &[Node]) -> usize {
+        match self.nodes.first() {
+            Some(Node::Leaf(pieces)) if self.piece > 0 => pieces[self.piece - 1].end().into(),
+            _ => siblings[siblings.len() - self.nodes.len() - 1]
+                .span()
+                .end()
+                .into(),
+        }
+    }
+
+    /// The cursor after taking `count` more pieces of the current leaf.
+    fn skip_pieces(self, leaf: &[Span], count: usize) -> Cursor<'a> {
+        match self.piece + count {
+            end if end == leaf.len() => Cursor::new(&self.nodes[1..], 0),
+            end => Cursor::new(self.nodes, end),
+        }
+    }
+
+    /// The single token trees that can be matched here, longest first. In a leaf, that's the
+    /// rest of the leaf or any part of it.
+    fn trees(self) -> Vec<(Step<'a>, Cursor<'a>)> {
+        match self.nodes.first() {
+            None => vec![],
+            Some(node @ Node::Group(..)) => {
+                vec![(Step::Group(node), Cursor::new(&self.nodes[1..], 0))]
+            }
+            Some(Node::Leaf(leaf)) => (self.piece + 1..=leaf.len())
+                .rev()
+                .map(|end| {
+                    let step = Step::Pieces {
+                        leaf,
+                        start: self.piece,
+                        end,
+                    };
+
+                    (step, self.skip_pieces(leaf, end - self.piece))
+                })
+                .collect(),
+        }
+    }
+
+    /// The next piece or group.
+    fn step(self) -> Option<(Step<'a>, Cursor<'a>)> {
+        match self.nodes.first()? {
+            node @ Node::Group(..) => Some((Step::Group(node), Cursor::new(&self.nodes[1..], 0))),
+            Node::Leaf(leaf) => Some((
+                Step::Pieces {
+                    leaf,
+                    start: self.piece,
+                    end: self.piece + 1,
+                },
+                self.skip_pieces(leaf, 1),
+            )),
+        }
+    }
+}
+
+/// A piece of text or a group, for comparing what metavariables matched.
+enum Unit<'a> {
+    Text(&'a str),
+    Group(Delimiter, &'a [Node]),
+}
+
+impl<'a> Unit<'a> {
+    fn of_steps(steps: &[Step<'a>], source: &'a Source) -> Vec<Unit<'a>> {
+        let mut units = vec![];
+
+        for step in steps {
+            match *step {
+                Step::Pieces { leaf, start, end } => units.extend(
+                    leaf[start..end]
+                        .iter()
+                        .map(|piece| Unit::Text(source.slice(*piece))),
+                ),
+                Step::Group(node) => Unit::push_node(node, source, &mut units),
+            }
+        }
+
+        units
+    }
+
+    fn of_nodes(nodes: &'a [Node], source: &'a Source) -> Vec<Unit<'a>> {
+        let mut units = vec![];
+
+        for node in nodes {
+            Unit::push_node(node, source, &mut units);
+        }
+
+        units
+    }
+
+    fn push_node(node: &'a Node, source: &'a Source, units: &mut Vec<Unit<'a>>) {
+        match node {
+            Node::Leaf(pieces) => {
+                units.extend(pieces.iter().map(|piece| Unit::Text(source.slice(*piece))))
+            }
+            Node::Group(delimiter, _, children) => units.push(Unit::Group(*delimiter, children)),
+        }
+    }
+
+    fn same(left: &[Unit<'a>], right: &[Unit<'a>], source: &'a Source) -> bool {
+        left.len() == right.len()
+            && left.iter().zip(right).all(|pair| match pair {
+                (Unit::Text(left), Unit::Text(right)) => left == right,
+                (Unit::Group(left, left_children), Unit::Group(right, right_children)) => {
+                    left == right
+                        && Unit::same(
+                            &Unit::of_nodes(left_children, source),
+                            &Unit::of_nodes(right_children, source),
+                            source,
+                        )
+                }
+                _ => false,
+            })
+    }
+}
+
+/// The steps that a metavariable matched, while matching is still in progress.
+type Bindings<'a> = Vec<(&'a str, Vec<Step<'a>>)>;
+
+/// What to do with the rest of the siblings after a fragment matched.
+type Continue<'c, 'a> = &'c dyn Fn(Cursor<'a>, Bindings<'a>) -> Option<(Cursor<'a>, Bindings<'a>)>;
+
+/// A structural search pattern.
+#[wyst_data]
+pub struct Pattern {
+    fragments: Vec<Fragment>,
+}
+
+impl Pattern {
+    /// Lex and read a pattern with the rules of `S`.
+    pub fn parse<S>(pattern: &str) -> Result<Pattern, Diagnostic>
+    where
+        S: for<'source> StandardDelegate<'source> + 'static,
+    {
+        let source = Source::new("<pattern>", pattern);
+        let result = FlatToken::read_tree::<S>(&source);
+
+        if let Some(error) = result.diagnostics().iter().find(|d| d.is_error()) {
+            return Err(error.clone());
+        }
+
+        let nodes = Node::read(result.tokens(), &source);
+
+        if nodes.is_empty() {
+            return Err(Diagnostic::error(Span::eof(0), "a pattern can't be empty"));
+        }
+
+        Ok(Pattern {
+            fragments: Fragment::parse_all(&nodes, &source),
+        })
+    }
+
+    /// The names of the metavariables that the pattern captures.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = vec![];
+        Fragment::collect_names(&self.fragments, &mut names);
+        names
+    }
+
+    /// Find every match of the pattern in `tokens`, in source order. Matches don't overlap with
+    /// other matches in the same list of siblings, but a match can contain other matches.
+    pub fn search(&self, tokens: &[Spanned<Token>], source: &Source) -> Vec<Match> {
+        let nodes = Node::read(tokens, source);
+        let mut matches = vec![];
+
+        Search {
+            pattern: self,
+            source,
+        }
+        .search_siblings(&nodes, &mut matches);
+
+        matches
+    }
+}
+
+struct Search<'a> {
+    pattern: &'a Pattern,
+    source: &'a Source,
+}
+
+impl<'a> Search<'a> {
+    fn search_siblings(&self, nodes: &'a [Node], matches: &mut Vec<Match>) {
+        let mut next_match = (0, 0);
+
+        for (i, node) in nodes.iter().enumerate() {
+            let starts = match node {
+                Node::Leaf(pieces) => pieces.len(),
+                Node::Group(..) => 1,
+            };
+
+            for piece in 0..starts {
+                if (i, piece) >= next_match {
+                    if let Some((end, found)) =
+                        self.match_at(nodes, Cursor::new(&nodes[i..], piece))
+                    {
+                        next_match = end.position(nodes);
+                        matches.push(found);
+                    }
+                }
+            }
+
+            if let Node::Group(_, _, children) = node {
+                self.search_siblings(children, matches);
+            }
+        }
+    }
+
+    /// Match the pattern at `start`, returning the cursor after the match and the match.
+    fn match_at(&self, siblings: &'a [Node], start: Cursor<'a>) -> Option<(Cursor<'a>, Match)> {
+        let (end, bindings) =
+            self.match_fragments(&self.pattern.fragments, start, vec![], &|rest, bindings| {
+                if rest.position(siblings) == start.position(siblings) {
+                    None
+                } else {
+                    Some((rest, bindings))
+                }
+            })?;
+
+        let (first, _) = start.step().expect("BUG: a match is never empty");
+        let span = Span::new(first.span().start(), end.end_of_previous(siblings));
+        let mut captures: Vec<(String, Capture)> = vec![];
+
+        for (name, steps) in bindings {
+            if captures.iter().any(|(captured, _)| captured == name) {
+                continue;
+            }
+
+            let capture = match self.is_sequence(&self.pattern.fragments, name) {
+                true => Capture::Sequence(joined(&steps).into_iter().map(Step::span).collect()),
+                false => Capture::Tree(steps[0].span()),
+            };
+
+            captures.push((name.to_string(), capture));
+        }
+
+        Some((end, Match { span, captures }))
+    }
+
+    fn is_sequence(&self, fragments: &[Fragment], name: &str) -> bool {
+        fragments.iter().any(|fragment| match fragment {
+            Fragment::Sequence(Some(sequence)) => sequence == name,
+            Fragment::Group(_, children) => self.is_sequence(children, name),
+            _ => false,
+        })
+    }
+
+    /// Bind `name` to `steps`, unless it's already bound to different tokens.
+    fn bind(
+        &self,
+        name: &'a Option<String>,
+        steps: Vec<Step<'a>>,
+        mut bindings: Bindings<'a>,
+    ) -> Option<Bindings<'a>> {
+        let name = match name {
+            Some(name) => name,
+            None => return Some(bindings),
+        };
+
+        if let Some((_, bound)) = bindings.iter().find(|(bound, _)| *bound == name) {
+            let same = Unit::same(
+                &Unit::of_steps(bound, self.source),
+                &Unit::of_steps(&steps, self.source),
+                self.source,
+            );
+
+            if !same {
+                return None;
+            }
+        }
+
+        bindings.push((name, steps));
+        Some(bindings)
+    }
+
+    fn match_fragments(
+        &self,
+        fragments: &'a [Fragment],
+        cursor: Cursor<'a>,
+        bindings: Bindings<'a>,
+        then: Continue<'_, 'a>,
+    ) -> Option<(Cursor<'a>, Bindings<'a>)> {
+        let (fragment, rest) = match fragments.split_first() {
+            Some(split) => split,
+            None => return then(cursor, bindings),
+        };
+
+        match fragment {
+            Fragment::Text(text) => match cursor.step()? {
+                (Step::Pieces { leaf, start, .. }, next)
+                    if self.source.slice(leaf[start]) == text =>
+                {
+                    self.match_fragments(rest, next, bindings, then)
+                }
+                _ => None,
+            },
+            Fragment::Group(delimiter, children) => match cursor.step()? {
+                (Step::Group(Node::Group(actual, _, inner)), next) if actual == delimiter => {
+                    let (_, bindings) = self.match_fragments(
+                        children,
+                        Cursor::new(inner, 0),
+                        bindings,
+                        &|rest, bindings| {
+                            if rest.is_end() {
+                                Some((rest, bindings))
+                            } else {
+                                None
+                            }
+                        },
+                    )?;
+
+                    self.match_fragments(rest, next, bindings, then)
+                }
+                _ => None,
+            },
+            Fragment::Tree(name) => cursor.trees().into_iter().find_map(|(step, next)| {
+                let bindings = self.bind(name, vec![step], bindings.clone())?;
+                self.match_fragments(rest, next, bindings, then)
+            }),
+            Fragment::Sequence(name) => {
+                // Sequences are lazy: they match as few pieces as possible.
+                let mut steps = vec![];
+                let mut cursor = cursor;
+
+                loop {
+                    let matched = self
+                        .bind(name, steps.clone(), bindings.clone())
+                        .and_then(|bindings| self.match_fragments(rest, cursor, bindings, then));
+
+                    if matched.is_some() {
+                        return matched;
+                    }
+
+                    let (step, next) = cursor.step()?;
+                    steps.push(step);
+                    cursor = next;
+                }
+            }
+        }
+    }
+}
+
+/// Join the consecutive pieces of the same leaf.
+fn joined<'a>(steps: &[Step<'a>]) -> Vec<Step<'a>> {
+    let mut out: Vec<Step<'a>> = vec![];
+
+    for step in steps {
+        match out.last().and_then(|last| last.join(*step)) {
+            Some(joined) => *out.last_mut().expect("BUG: just joined") = joined,
+            None => out.push(*step),
+        }
+    }
+
+    out
+}
+
+unit_tests!(
+    all({
+        use crate::delegate::DefaultDelegate;
+
+        fn search(pattern: &str, source: &str) -> (Source, Vec<Match>) {
+            let pattern = Pattern::parse::<DefaultDelegate>(pattern).unwrap();
+            let source = Source::new("<test>", source);
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+            let matches = pattern.search(&tokens, &source);
+
+            (source, matches)
+        }
+
+        fn texts<'a>(source: &'a Source, matches: &[Match]) -> Vec<&'a str> {
+            matches.iter().map(|m| source.slice(m.span())).collect()
+        }
+
+        fn capture<'a>(source: &'a Source, found: &Match, name: &str) -> &'a str {
+            match found.get(name).and_then(Capture::span) {
+                Some(span) => source.slice(span),
+                None => "",
+            }
+        }
+    }),
+    tests(
+        ("metavariables", {
+            let (source, matches) = search(
+                "call($x, { $$$ })",
+                "call(a, {b; c}) call(a, b)\nnested(call([1],{ }))",
+            );
+
+            assert_eq!(
+                texts(&source, &matches),
+                vec!["call(a, {b; c})", "call([1],{ })"]
+            );
+            assert_eq!(capture(&source, &matches[0], "x"), "a");
+            assert_eq!(capture(&source, &matches[1], "x"), "[1]");
+            assert_eq!(matches[0].captures().len(), 1);
+        }),
+        ("sequences", {
+            let (source, matches) = search("f($$$args)", "f(a, b c) f() g(f(x))");
+
+            assert_eq!(texts(&source, &matches), vec!["f(a, b c)", "f()", "f(x)"]);
+            assert_eq!(capture(&source, &matches[0], "args"), "a, b c");
+            assert_eq!(matches[1].get("args"), Some(&Capture::Sequence(vec![])));
+            assert_eq!(capture(&source, &matches[2], "args"), "x");
+
+            let (source, matches) = search("[$$$before, $last]", "[a, b, c] [d]");
+
+            assert_eq!(texts(&source, &matches), vec!["[a, b, c]"]);
+            assert_eq!(capture(&source, &matches[0], "before"), "a, b");
+            assert_eq!(capture(&source, &matches[0], "last"), "c");
+        }),
+        ("repeated metavariables", {
+            let (source, matches) = search("$x + $x", "a + a b + c (d e) + (d  e)");
+
+            assert_eq!(texts(&source, &matches), vec!["a + a", "(d e) + (d  e)"]);
+        }),
+        ("nested matches", {
+            let (source, matches) = search("f($$$)", "f(f(a))");
+
+            assert_eq!(texts(&source, &matches), vec!["f(f(a))", "f(a)"]);
+            assert_eq!(matches[0].captures(), &[]);
+
+            // `f(a)` is two token trees, so it doesn't match `$_`.
+            let (source, matches) = search("f($_)", "f(f(a)) f([a b])");
+
+            assert_eq!(texts(&source, &matches), vec!["f(a)", "f([a b])"]);
+        }),
+        ("metavariables match whole words", {
+            let (source, matches) = search("f($x)", "f(a) f(a.b) f(-1) f(foo-bar) f(a b)");
+
+            assert_eq!(
+                texts(&source, &matches),
+                vec!["f(a)", "f(a.b)", "f(-1)", "f(foo-bar)"]
+            );
+            assert_eq!(capture(&source, &matches[1], "x"), "a.b");
+            assert_eq!(capture(&source, &matches[2], "x"), "-1");
+            assert_eq!(capture(&source, &matches[3], "x"), "foo-bar");
+
+            // Literal text right after a metavariable ends it early.
+            let (source, matches) = search("$x.len", "items.len a.b.len");
+
+            assert_eq!(texts(&source, &matches), vec!["items.len", "a.b.len"]);
+            assert_eq!(capture(&source, &matches[1], "x"), "a.b");
+
+            let (source, matches) = search("g($$$args)", "g(-1, a.b c)");
+
+            assert_eq!(
+                matches[0].get("args"),
+                Some(&Capture::Sequence(vec![
+                    Span::new(2, 5),
+                    Span::new(6, 9),
+                    Span::new(10, 11)
+                ]))
+            );
+            assert_eq!(capture(&source, &matches[0], "args"), "-1, a.b c");
+        }),
+        ("invalid patterns", {
+            assert!(Pattern::parse::<DefaultDelegate>("   ").is_err());
+            assert!(Pattern::parse::<DefaultDelegate>("call($x").is_err());
+        })
+    )
+);
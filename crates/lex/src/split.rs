@@ -0,0 +1,487 @@
+//! Splitting a list of sibling tokens into separated items, such as the arguments in `(a, b, c)`
+//! or the statements in `{ a; b }`.
+//!
+//! The standard lexer doesn't treat `,` or `;` specially, so a separator is usually the end of a
+//! word (`a,`) and can even be in the middle of one (`a,b`). The splitter looks for separators
+//! inside of words (but not inside of quotes or nested delimiters), so an item's span can start
+//! or end in the middle of a token.
+
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_source::{Diagnostic, LineIndex, Offset, Source, Span, Spanned};
+
+use crate::tree::{Delimited, Leaf, Token};
+
+/// What separates the items of a list.
+#[wyst_copy]
+pub enum Separator {
+    /// A character, such as `,` in an argument list. A line break doesn't end an item, unless the
+    /// next line starts at the same column as the item, in which case the separator is reported
+    /// as missing.
+    Char(char),
+    /// Line breaks. Blank lines don't produce empty items.
+    Newline,
+    /// A character or a line break, such as `;` in a language with optional semicolons.
+    CharOrNewline(char),
+}
+
+impl Separator {
+    fn char(self) -> Option<char> {
+        match self {
+            Separator::Char(c) | Separator::CharOrNewline(c) => Some(c),
+            Separator::Newline => None,
+        }
+    }
+
+    fn on_newline(self) -> bool {
+        match self {
+            Separator::Char(_) => false,
+            Separator::Newline | Separator::CharOrNewline(_) => true,
+        }
+    }
+}
+
+/// An item in a [SeparatedList].
+#[wyst_data]
+pub struct ListItem<'a> {
+    tokens: &'a [Spanned<Token>],
+    span: Span,
+    separator: Option<Span>,
+    leading_comments: Vec<Span>,
+    inner_comments: Vec<Span>,
+    trailing_comments: Vec<Span>,
+}
+
+impl<'a> ListItem<'a> {
+    /// The tokens that make up the item, from the first significant token to the last one. The
+    /// first and last tokens can be words that are shared with the neighbouring items (as in
+    /// `a,b`); [ListItem::span] is precise.
+    pub fn tokens(&self) -> &'a [Spanned<Token>] {
+        self.tokens
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The separator after the item. A line break is a separator in the [Separator::Newline] and
+    /// [Separator::CharOrNewline] modes.
+    pub fn separator(&self) -> Option<Span> {
+        self.separator
+    }
+
+    /// Comments on their own lines before the item.
+    pub fn leading_comments(&self) -> &[Span] {
+        &self.leading_comments
+    }
+
+    /// Comments between the item's tokens, in an item that continues onto later lines.
+    pub fn inner_comments(&self) -> &[Span] {
+        &self.inner_comments
+    }
+
+    /// Comments after the item (or its separator) on the same line.
+    pub fn trailing_comments(&self) -> &[Span] {
+        &self.trailing_comments
+    }
+}
+
+/// The result of splitting a list of tokens.
+#[wyst_data]
+pub struct SeparatedList<'a> {
+    items: Vec<ListItem<'a>>,
+    trailing_separator: Option<Span>,
+    dangling_comments: Vec<Span>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> SeparatedList<'a> {
+    pub fn items(&self) -> &[ListItem<'a>] {
+        &self.items
+    }
+
+    /// The separator after the last item, if there is one.
+    pub fn trailing_separator(&self) -> Option<Span> {
+        self.trailing_separator
+    }
+
+    /// Comments after the last item that aren't on the same line as it (or every comment, if the
+    /// list is empty).
+    pub fn dangling_comments(&self) -> &[Span] {
+        &self.dangling_comments
+    }
+
+    /// Missing and extra separators.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+impl Delimited {
+    /// Split the children of this delimited token. See [split_list].
+    pub fn split<'a>(&'a self, separator: Separator, source: &Source) -> SeparatedList<'a> {
+        split_list(self.children(), separator, source)
+    }
+}
+
+/// Split a list of sibling tokens into items.
+pub fn split_list<'a>(
+    tokens: &'a [Spanned<Token>],
+    separator: Separator,
+    source: &Source,
+) -> SeparatedList<'a> {
+    let mut splitter = Splitter {
+        tokens,
+        separator,
+        lines: source.line_index(),
+        list: SeparatedList {
+            items: vec![],
+            trailing_separator: None,
+            dangling_comments: vec![],
+            diagnostics: vec![],
+        },
+        current: None,
+        comments: vec![],
+        same_line: false,
+        line_break: false,
+    };
+
+    for (index, token) in tokens.iter().enumerate() {
+        let span = token.span();
+
+        match token.item() {
+            Token::Leaf(Leaf::EOF) | Token::Leaf(Leaf::Whitespace) => {}
+            Token::Leaf(Leaf::Newline) => splitter.newline(span),
+            Token::Leaf(Leaf::Comment(_)) => splitter.comment(span),
+            Token::Leaf(Leaf::Word) => match separator.char() {
+                Some(c) => splitter.word(index, span, source.slice(span), c),
+                None => splitter.content(index, span),
+            },
+            _ => splitter.content(index, span),
+        }
+    }
+
+    splitter.finish()
+}
+
+/// An item that hasn't seen its separator yet.
+struct Current {
+    first: usize,
+    last: usize,
+    span: Span,
+    leading_comments: Vec<Span>,
+    inner_comments: Vec<Span>,
+    /// Comments after the last significant token so far.
+    comments: Vec<Span>,
+}
+
+struct Splitter<'a> {
+    tokens: &'a [Spanned<Token>],
+    separator: Separator,
+    lines: LineIndex,
+    list: SeparatedList<'a>,
+    current: Option<Current>,
+    /// Comments since the last item ended, which belong to the next item.
+    comments: Vec<Span>,
+    /// True until the first line break after the last item ended.
+    same_line: bool,
+    /// True if there was a line break since the last significant token.
+    line_break: bool,
+}
+
+impl<'a> Splitter<'a> {
+    fn word(&mut self, index: usize, span: Span, text: &str, separator: char) {
+        let start: usize = span.start().into();
+        let mut from = 0;
+
+        for (at, c) in text.char_indices() {
+            if c == separator {
+                if at > from {
+                    self.content(index, Span::new(start + from, start + at));
+                }
+
+                self.separator(Span::new(start + at, start + at + c.len_utf8()));
+                from = at + c.len_utf8();
+            }
+        }
+
+        if from < text.len() {
+            self.content(index, Span::new(start + from, start + text.len()));
+        }
+    }
+
+    fn content(&mut self, index: usize, span: Span) {
+        if self.line_break {
+            if let Some(current) = &self.current {
+                if self.separator.on_newline() {
+                    self.end_item(None);
+                } else if self.column(span.start()) == self.column(current.span.start()) {
+                    let c = self
+                        .separator
+                        .char()
+                        .expect("BUG: Char separators have a char");
+
+                    self.list.diagnostics.push(Diagnostic::error(
+                        Span::eof(current.span.end()),
+                        format!("expected `{}`", c),
+                    ));
+                    self.end_item(None);
+                }
+            }
+        }
+
+        self.line_break = false;
+
+        match &mut self.current {
+            Some(current) => {
+                current.last = index;
+                current.span = current.span.until(span);
+                // The comments since the last token turned out to be inside of the item.
+                current.inner_comments.append(&mut current.comments);
+                current.inner_comments.append(&mut self.comments);
+            }
+            None => {
+                self.current = Some(Current {
+                    first: index,
+                    last: index,
+                    span,
+                    leading_comments: std::mem::take(&mut self.comments),
+                    inner_comments: vec![],
+                    comments: vec![],
+                })
+            }
+        }
+    }
+
+    fn separator(&mut self, span: Span) {
+        if self.current.is_some() {
+            self.end_item(Some(span));
+        } else {
+            self.extra(span);
+        }
+
+        self.same_line = true;
+        self.line_break = false;
+    }
+
+    fn extra(&mut self, span: Span) {
+        let c = self
+            .separator
+            .char()
+            .expect("BUG: only char separators can be extra");
+
+        self.list
+            .diagnostics
+            .push(Diagnostic::error(span, format!("unexpected `{}`", c)));
+    }
+
+    fn newline(&mut self, span: Span) {
+        if self.separator.on_newline() && self.current.is_some() {
+            self.end_item(Some(span));
+        }
+
+        self.line_break = true;
+        self.same_line = false;
+    }
+
+    fn comment(&mut self, span: Span) {
+        match &mut self.current {
+            // A comment on a later line belongs to the next item, unless the current item
+            // continues after it.
+            Some(_) if self.line_break => self.comments.push(span),
+            Some(current) => current.comments.push(span),
+            None if self.same_line => match self.list.items.last_mut() {
+                Some(item) => item.trailing_comments.push(span),
+                None => self.comments.push(span),
+            },
+            None => self.comments.push(span),
+        }
+    }
+
+    fn end_item(&mut self, separator: Option<Span>) {
+        let current = self
+            .current
+            .take()
+            .expect("BUG: can only end an item that was started");
+
+        self.list.items.push(ListItem {
+            tokens: &self.tokens[current.first..=current.last],
+            span: current.span,
+            separator,
+            leading_comments: current.leading_comments,
+            inner_comments: current.inner_comments,
+            trailing_comments: current.comments,
+        });
+
+        self.same_line = true;
+    }
+
+    fn column(&self, offset: Offset) -> usize {
+        let line_start = self
+            .lines
+            .line_start(self.lines.line(offset))
+            .expect("BUG: every offset is on a line");
+
+        let offset: usize = offset.into();
+        let line_start: usize = line_start.into();
+        offset - line_start
+    }
+
+    fn finish(mut self) -> SeparatedList<'a> {
+        if self.current.is_some() {
+            self.end_item(None);
+        }
+
+        self.list.trailing_separator = self.list.items.last().and_then(ListItem::separator);
+        self.list.dangling_comments = self.comments;
+        self.list
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::{testing::read, token_builder::TokenBuilder};
+
+        fn texts<'a>(source: &'a Source, list: &SeparatedList) -> Vec<&'a str> {
+            list.items()
+                .iter()
+                .map(|item| source.slice(item.span()))
+                .collect()
+        }
+
+        fn delimited(tokens: &[Spanned<Token>]) -> &Delimited {
+            tokens
+                .iter()
+                .find_map(|token| match token.item() {
+                    Token::Delimited(delimited) => Some(delimited),
+                    _ => None,
+                })
+                .expect("expected a delimited token")
+        }
+    }),
+    tests(
+        ("commas", {
+            let source = Source::new("<test>", "f(a, g(b, c) h,d,)");
+            let tokens = read(&source);
+            let list = delimited(&tokens).split(Separator::Char(','), &source);
+
+            assert_eq!(texts(&source, &list), &["a", "g(b, c) h", "d"]);
+            assert_eq!(list.trailing_separator(), Some(Span::new(16, 17)));
+            assert_eq!(list.diagnostics(), &[]);
+
+            let items = list.items();
+            assert_eq!(items[0].tokens().len(), 1);
+            assert_eq!(items[1].tokens().len(), 4);
+            assert_eq!(items[1].separator(), Some(Span::new(14, 15)));
+
+            // `d` and both of the separators around it are part of the word `h,d,`.
+            assert_eq!(source.slice(items[2].tokens()[0].span()), "h,d,");
+        }),
+        ("extra and missing separators", {
+            let source = Source::new("<test>", "(, a,,\n b\n   x\n c)");
+            let tokens = read(&source);
+            let list = delimited(&tokens).split(Separator::Char(','), &source);
+
+            assert_eq!(texts(&source, &list), &["a", "b\n   x", "c"]);
+            assert_eq!(list.trailing_separator(), None);
+            assert_eq!(
+                list.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(1, 2), "unexpected `,`"),
+                    Diagnostic::error(Span::new(5, 6), "unexpected `,`"),
+                    Diagnostic::error(Span::eof(14), "expected `,`"),
+                ]
+            );
+            assert!(list.has_errors());
+        }),
+        ("statements", {
+            let source = Source::new("<test>", "{\n  a b;\n\n  c\n  d; e;\n}");
+            let tokens = read(&source);
+            let list = delimited(&tokens).split(Separator::CharOrNewline(';'), &source);
+
+            assert_eq!(texts(&source, &list), &["a b", "c", "d", "e"]);
+            assert_eq!(list.items()[1].separator(), Some(Span::new(13, 14)));
+            assert_eq!(list.trailing_separator(), Some(Span::new(20, 21)));
+            assert_eq!(list.diagnostics(), &[]);
+
+            let list = split_list(&tokens, Separator::Newline, &source);
+            assert_eq!(list.items().len(), 1);
+        }),
+        ("comments", {
+            let source = Source::new("<test>", "a, # one\n# two\nb # three\n# four\n");
+            let mut b = TokenBuilder::new();
+            let tokens = vec![
+                b.word("a,"),
+                b.ws(" "),
+                b.comment(" one", ("#", "")),
+                b.newline(),
+                b.comment(" two", ("#", "")),
+                b.newline(),
+                b.word("b"),
+                b.ws(" "),
+                b.comment(" three", ("#", "")),
+                b.newline(),
+                b.comment(" four", ("#", "")),
+                b.newline(),
+            ];
+
+            let list = split_list(&tokens, Separator::Char(','), &source);
+            let comments = |spans: &[Span]| -> Vec<&str> {
+                spans.iter().map(|span| source.slice(*span)).collect()
+            };
+
+            assert_eq!(texts(&source, &list), &["a", "b"]);
+            assert_eq!(comments(list.items()[0].trailing_comments()), &["# one"]);
+            assert_eq!(comments(list.items()[1].leading_comments()), &["# two"]);
+            assert_eq!(comments(list.items()[1].trailing_comments()), &["# three"]);
+            assert_eq!(comments(list.dangling_comments()), &["# four"]);
+        }),
+        ("comments inside of an item", {
+            let source = Source::new(
+                "<test>",
+                "a + # one
+    # two
+    b, c",
+            );
+            let mut b = TokenBuilder::new();
+            let tokens = vec![
+                b.word("a"),
+                b.ws(" "),
+                b.word("+"),
+                b.ws(" "),
+                b.comment(" one", ("#", "")),
+                b.newline(),
+                b.ws("    "),
+                b.comment(" two", ("#", "")),
+                b.newline(),
+                b.ws("    "),
+                b.word("b,"),
+                b.ws(" "),
+                b.word("c"),
+            ];
+
+            let list = split_list(&tokens, Separator::Char(','), &source);
+            let comments = |spans: &[Span]| -> Vec<&str> {
+                spans.iter().map(|span| source.slice(*span)).collect()
+            };
+
+            assert_eq!(texts(&source, &list), &["a + # one\n    # two\n    b", "c"]);
+            assert_eq!(list.diagnostics(), &[]);
+            assert_eq!(
+                comments(list.items()[0].inner_comments()),
+                &["# one", "# two"]
+            );
+            assert_eq!(
+                comments(list.items()[0].trailing_comments()),
+                Vec::<&str>::new()
+            );
+            assert_eq!(
+                comments(list.items()[1].leading_comments()),
+                Vec::<&str>::new()
+            );
+        })
+    )
+);
@@ -2,12 +2,15 @@ use std::marker::PhantomData;
 
 use logos::{Lexer, Logos};
 use wyst_core::{new, unit_tests, wyst_copy};
-use wyst_source::{Source, Span, Spanned};
+use wyst_source::{AddSpan, Source, Span, Spanned};
 
 use crate::{
-    delegate::StandardDelegate,
+    arena::TokenArena,
+    delegate::{LexContext, LexHint, StandardDelegate},
+    events::{Event, EventReader},
+    kind::{LeafKind, TokenClass, TokenKind},
     reader::ReadTokens,
-    tree::{Token, TokenTree},
+    tree::{ReadResult, Token, TokenTree},
 };
 
 /// `Quoted` is the representation of a `FlatToken` that begins with a quote (either double-quote,
@@ -30,6 +33,7 @@ pub struct Quoted {
 /// - Open: an opening delimiter (`[`, `{` or `(`)
 /// - Close: a closing delimiter (`]`, `}` or `)`)
 /// - Comment
+/// - Shebang and FrontMatter: only produced by the optional preamble pass
 ///
 /// Quotation rules are pluggable by passing an implementation of `StandardDelegate` to
 /// `read_source` or `lex_source`.
@@ -45,7 +49,10 @@ pub enum FlatToken {
     #[error]
     Error,
 
-    #[regex(r#"[^"'`\(\[\{\)\]\}\p{White_Space}][^"'`\p{White_Space}\(\[\{]*"#)]
+    /// A run of anything other than whitespace, quotes and delimiters. A word stops at a closing
+    /// delimiter as well as an opening one: otherwise the last word in `f(a, b)` would be `b)`,
+    /// and the `)` would never close the parentheses.
+    #[regex(r#"[^"'`\(\[\{\)\]\}\p{White_Space}][^"'`\p{White_Space}\(\[\{\)\]\}]*"#)]
     Word,
 
     #[regex(r"[^\S\n]+")]
@@ -71,16 +78,36 @@ pub enum FlatToken {
 
     Comment(Span),
 
+    /// A `#!` line at the very start of a source (see [LexTop::with_preamble]).
+    Shebang,
+
+    /// A front matter block at the start of a source, fenced by `---` or `+++` lines. The span is
+    /// the span of the contents between the fences (see [LexTop::with_preamble]).
+    FrontMatter((Fence, Span)),
+
     EOF,
 }
 
 impl FlatToken {
+    /// Trivia (whitespace, newlines and comments) doesn't affect the meaning of the source.
+    pub fn is_trivia(self) -> bool {
+        match self.classify() {
+            TokenClass::Leaf(leaf) => leaf.is_trivia(),
+            _ => false,
+        }
+    }
+
     pub fn lex_source<'source, S>(source: &'source str) -> LexTop<'source, S>
     where
         S: StandardDelegate<'source>,
     {
         LexTop {
             lexer: Some(FlatToken::lexer(source)),
+            context: LexContext {
+                previous: None,
+                hint: None,
+            },
+            preamble: vec![].into_iter(),
             done: false,
             source: PhantomData,
         }
@@ -95,6 +122,35 @@ impl FlatToken {
         let lexed = FlatToken::lex_source::<S>(source.contents());
         lexed.read(source)
     }
+
+    /// Read a source into a token tree, collecting the diagnostics that were reported while
+    /// reading it.
+    pub fn read_tree<'source, S>(source: &'source Source) -> ReadResult
+    where
+        S: StandardDelegate<'source> + 'source,
+    {
+        let lexed = FlatToken::lex_source::<S>(source.contents());
+        lexed.read_tree(source)
+    }
+
+    pub fn read_arena<'source, S>(source: &'source Source) -> TokenArena
+    where
+        S: StandardDelegate<'source> + 'source,
+    {
+        let lexed = FlatToken::lex_source::<S>(source.contents());
+        lexed.read_arena(source)
+    }
+
+    /// Read a source into a flat stream of [Event]s (see [EventReader]).
+    pub fn read_events<'source, S>(
+        source: &'source Source,
+    ) -> impl Iterator<Item = Spanned<Event>> + 'source
+    where
+        S: StandardDelegate<'source> + 'source,
+    {
+        let lexed = FlatToken::lex_source::<S>(source.contents());
+        lexed.read_events(source)
+    }
 }
 
 pub struct LexTop<'source, S>
@@ -102,6 +158,8 @@ where
     S: StandardDelegate<'source>,
 {
     lexer: Option<Lexer<'source, FlatToken>>,
+    context: LexContext<'source>,
+    preamble: std::vec::IntoIter<Spanned<FlatToken>>,
     done: bool,
     source: PhantomData<S>,
 }
@@ -110,9 +168,55 @@ impl<'source, S> LexTop<'source, S>
 where
     S: StandardDelegate<'source> + 'source,
 {
+    /// Recognize a shebang line and a front matter block at the start of the source, which would
+    /// otherwise be lexed as words (and could unbalance the delimiters). They become `Shebang` and
+    /// `FrontMatter` tokens, and normal lexing starts after them. The front matter token has the
+    /// span of its contents, so that another lexer can be run on them.
+    ///
+    /// This must be called before the first token is lexed.
+    pub fn with_preamble(mut self) -> Self {
+        let lexer = self.lexer.as_mut().expect("iterating top is not reentrant");
+
+        assert!(
+            lexer.span().end == 0,
+            "with_preamble must be called before lexing"
+        );
+
+        let (tokens, end) = preamble(lexer.source());
+        lexer.bump(end);
+        self.preamble = tokens.into_iter();
+        self
+    }
+
+    /// Tell the delegate what the next significant token may be. The hint stays in place (across
+    /// any trivia) until the next significant token has been lexed.
+    pub fn hint(&mut self, hint: LexHint) {
+        self.context.hint = Some(hint);
+    }
+
+    /// The previous significant (non-trivia) token.
+    pub fn previous(&self) -> Option<&Spanned<FlatToken>> {
+        self.context.previous()
+    }
+
     pub fn read(self, source: &'source Source) -> impl Iterator<Item = Spanned<Token>> + 'source {
         ReadTokens::new(TokenTree::new(), self, source)
     }
+
+    pub fn read_tree(self, source: &'source Source) -> ReadResult {
+        TokenTree::read(source, self)
+    }
+
+    pub fn read_arena(self, source: &'source Source) -> TokenArena {
+        TokenArena::read(source, self)
+    }
+
+    pub fn read_events(
+        self,
+        source: &'source Source,
+    ) -> impl Iterator<Item = Spanned<Event>> + 'source {
+        EventReader::read(source, self)
+    }
 }
 
 impl<'source, S> Iterator for LexTop<'source, S>
@@ -122,10 +226,34 @@ where
     type Item = Spanned<FlatToken>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        let token = self.next_token()?;
+
+        if !token.item().is_trivia() {
+            let source = self.lexer.as_ref().map(|l| l.source()).unwrap();
+            let span = token.span();
+            let text = &source[span.start().into()..span.end().into()];
+
+            self.context.previous = Some((token.clone(), text));
+            self.context.hint = None;
+        }
+
+        Some(token)
+    }
+}
+
+impl<'source, S> LexTop<'source, S>
+where
+    S: StandardDelegate<'source>,
+{
+    fn next_token(&mut self) -> Option<Spanned<FlatToken>> {
         if self.done {
             return None;
         }
 
+        if let Some(token) = self.preamble.next() {
+            return Some(token);
+        }
+
         let (comment, lexer) =
             S::comment(self.lexer.take().expect("iterating top is not reentrant"));
 
@@ -135,6 +263,17 @@ where
             return Some(comment);
         }
 
+        let (contextual, lexer) = S::contextual(
+            &self.context,
+            self.lexer.take().expect("iterating top is not reentrant"),
+        );
+
+        self.lexer = Some(lexer);
+
+        if let Some(contextual) = contextual {
+            return Some(contextual);
+        }
+
         let token = self.lexer.as_mut().map(|l| l.next()).unwrap();
         let span = self.lexer.as_ref().map(|l| l.span()).unwrap();
 
@@ -160,6 +299,78 @@ where
     }
 }
 
+/// Lex the shebang line and front matter block at the start of `source`, returning their tokens
+/// and the offset where normal lexing should start.
+fn preamble(source: &str) -> (Vec<Spanned<FlatToken>>, usize) {
+    let mut tokens = vec![];
+    let mut start = 0;
+
+    if source.starts_with("#!") {
+        let end = source.find('\n').unwrap_or(source.len());
+        tokens.push(FlatToken::Shebang.spanned(Span::new(0, end)));
+        start = end;
+
+        if start < source.len() {
+            tokens.push(FlatToken::Newline.spanned(Span::new(start, start + 1)));
+            start += 1;
+        }
+    }
+
+    if let Some((token, end)) = front_matter(source, start) {
+        tokens.push(token);
+        start = end;
+    }
+
+    (tokens, start)
+}
+
+/// A front matter block starts with a line containing only a fence (`---` or `+++`), and ends with
+/// the next line containing only the same fence. A block without a closing fence isn't front
+/// matter.
+fn front_matter(source: &str, start: usize) -> Option<(Spanned<FlatToken>, usize)> {
+    let rest = &source[start..];
+
+    let fence = if rest.starts_with(Fence::Yaml.as_str()) {
+        Fence::Yaml
+    } else if rest.starts_with(Fence::Toml.as_str()) {
+        Fence::Toml
+    } else {
+        return None;
+    };
+
+    let is_fence = |line: &str| line.trim_end() == fence.as_str();
+
+    let open_end = rest.find('\n')?;
+
+    if !is_fence(&rest[..open_end]) {
+        return None;
+    }
+
+    let inner_start = open_end + 1;
+    let mut line_start = inner_start;
+
+    loop {
+        let line_end = rest[line_start..]
+            .find('\n')
+            .map(|end| line_start + end)
+            .unwrap_or(rest.len());
+
+        if is_fence(&rest[line_start..line_end]) {
+            let inner = Span::new(start + inner_start, start + line_start);
+            let token =
+                FlatToken::FrontMatter((fence, inner)).spanned(Span::new(start, start + line_end));
+
+            return Some((token, start + line_end));
+        }
+
+        if line_end == rest.len() {
+            return None;
+        }
+
+        line_start = line_end + 1;
+    }
+}
+
 #[wyst_copy]
 pub enum Quote {
     // "
@@ -181,6 +392,24 @@ impl From<char> for Quote {
     }
 }
 
+/// The fence around a front matter block.
+#[wyst_copy]
+pub enum Fence {
+    /// `---`, which conventionally surrounds YAML.
+    Yaml,
+    /// `+++`, which conventionally surrounds TOML.
+    Toml,
+}
+
+impl Fence {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Fence::Yaml => "---",
+            Fence::Toml => "+++",
+        }
+    }
+}
+
 impl Quote {
     pub fn char(self) -> char {
         match self {
@@ -224,6 +453,59 @@ unit_tests!(
         use crate::delegate::{DefaultDelegate, StandardDelegate};
         use crate::top_builder::TopBuilder;
         use wyst_source::{AddSpan, Offset};
+
+        /// A delegate that lexes JavaScript-style regex literals as words, but only where a regex
+        /// can appear (so `/` is still division after an operand).
+        #[wyst_copy]
+        struct Js;
+
+        impl<'a> StandardDelegate<'a> for Js {
+            fn contextual(
+                context: &LexContext<'a>,
+                mut lexer: Lexer<'a, FlatToken>,
+            ) -> (Option<Spanned<FlatToken>>, Lexer<'a, FlatToken>) {
+                let regex_allowed = context.is_hinted("regex")
+                    || match context.previous().map(|token| *token.item()) {
+                        None | Some(FlatToken::Open(_)) => true,
+                        Some(FlatToken::Word) => {
+                            matches!(context.previous_text(), Some("=") | Some("return"))
+                        }
+                        Some(_) => false,
+                    };
+
+                let remainder = lexer.remainder();
+
+                if !regex_allowed || !remainder.starts_with('/') {
+                    return (None, lexer);
+                }
+
+                match remainder[1..].find('/') {
+                    Some(end) => {
+                        let start = lexer.span().end;
+                        let len = end + 2;
+                        lexer.bump(len);
+
+                        (
+                            Some(FlatToken::Word.spanned(Span::new(start, start + len))),
+                            lexer,
+                        )
+                    }
+                    None => (None, lexer),
+                }
+            }
+        }
+
+        /// The source text of the significant tokens.
+        fn texts(source: &str, tokens: impl Iterator<Item = Spanned<FlatToken>>) -> Vec<&str> {
+            tokens
+                .filter(|token| !token.item().is_trivia())
+                .map(|token| {
+                    let start: usize = token.span().start().into();
+                    let end: usize = token.span().end().into();
+                    &source[start..end]
+                })
+                .collect()
+        }
     }),
     tests(
         ("whitespace", {
@@ -262,6 +544,30 @@ unit_tests!(
                 ]
             );
         }),
+        ("delimiters (adjacent to words)", {
+            let tokens: Vec<_> =
+                FlatToken::lex_source::<DefaultDelegate>("call(a, [b]){c}").collect();
+
+            let mut b = TopBuilder::new();
+
+            assert_eq!(
+                tokens,
+                &[
+                    b.word("call"),
+                    b.open("("),
+                    b.word("a,"),
+                    b.ws(" "),
+                    b.open("["),
+                    b.word("b"),
+                    b.close("]"),
+                    b.close(")"),
+                    b.open("{"),
+                    b.word("c"),
+                    b.close("}"),
+                    b.eof()
+                ]
+            );
+        }),
         ("quotes (by default)", {
             let tokens: Vec<_> =
                 FlatToken::lex_source::<DefaultDelegate>(r#"   hello( "world" )   "#).collect();
@@ -457,6 +763,93 @@ unit_tests!(
                     }
                 }
             }
+        }),
+        ("contextual tokens (previous token)", {
+            let source = "x = /a)b/g\ny = x / 2 / (/c d/)";
+            let tokens = FlatToken::lex_source::<Js>(source);
+
+            assert_eq!(
+                texts(source, tokens),
+                &["x", "=", "/a)b/", "g", "y", "=", "x", "/", "2", "/", "(", "/c d/", ")", ""]
+            );
+
+            let tokens = FlatToken::lex_source::<DefaultDelegate>(source);
+
+            assert_eq!(
+                texts(source, tokens),
+                &[
+                    "x", "=", "/a", ")", "b/g", "y", "=", "x", "/", "2", "/", "(", "/c", "d/", ")",
+                    ""
+                ]
+            );
+        }),
+        ("contextual tokens (hints)", {
+            let source = "x /a b/ / c";
+            let mut tokens = FlatToken::lex_source::<Js>(source);
+
+            assert_eq!(texts(source, tokens.by_ref().take(1)), &["x"]);
+
+            tokens.hint(LexHint::new("regex"));
+
+            assert_eq!(texts(source, tokens.by_ref().take(2)), &["/a b/"]);
+            assert_eq!(
+                tokens.previous().map(|token| token.span()),
+                Some(Span::new(2, 7))
+            );
+
+            // The hint only applies to the next significant token.
+            assert_eq!(texts(source, tokens), &["/", "c", ""]);
+        }),
+        ("preamble (shebang and front matter)", {
+            let source = Source::new(
+                "<test>",
+                "#!/usr/bin/env wyst\n---\ntitle: (draft\n---\nbody ( )",
+            );
+            let tokens: Vec<_> = FlatToken::lex_source::<DefaultDelegate>(source.contents())
+                .with_preamble()
+                .collect();
+
+            let front_matter = match tokens[2].item() {
+                FlatToken::FrontMatter((Fence::Yaml, inner)) => *inner,
+                other => panic!("expected front matter, got {:?}", other),
+            };
+
+            assert_eq!(source.slice(tokens[0].span()), "#!/usr/bin/env wyst");
+            assert_eq!(*tokens[0].item(), FlatToken::Shebang);
+            assert_eq!(*tokens[1].item(), FlatToken::Newline);
+            assert_eq!(source.slice(tokens[2].span()), "---\ntitle: (draft\n---");
+            assert_eq!(source.slice(front_matter), "title: (draft\n");
+            assert_eq!(
+                texts(source.contents(), tokens.into_iter()),
+                &[
+                    "#!/usr/bin/env wyst",
+                    "---\ntitle: (draft\n---",
+                    "body",
+                    "(",
+                    ")",
+                    ""
+                ]
+            );
+
+            let result = FlatToken::lex_source::<DefaultDelegate>(source.contents())
+                .with_preamble()
+                .read_tree(&source);
+            assert!(!result.has_errors());
+
+            let result = FlatToken::read_tree::<DefaultDelegate>(&source);
+            assert!(result.has_errors());
+        }),
+        ("preamble (toml front matter without a shebang)", {
+            let source = "+++\na = [1]\n+++\n";
+            let tokens = FlatToken::lex_source::<DefaultDelegate>(source).with_preamble();
+
+            assert_eq!(texts(source, tokens), &["+++\na = [1]\n+++", ""]);
+        }),
+        ("preamble (unclosed front matter)", {
+            let source = "---\na: b\n";
+            let tokens = FlatToken::lex_source::<DefaultDelegate>(source).with_preamble();
+
+            assert_eq!(texts(source, tokens), &["---", "a:", "b", ""]);
         })
     )
 );
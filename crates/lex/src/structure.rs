@@ -0,0 +1,406 @@
+//! Editor features that only need the structure of the token tree: folding ranges, matching
+//! brackets and expand-selection.
+
+use wyst_core::{unit_tests, wyst_copy};
+use wyst_source::{LineIndex, Offset, Source, Span, Spanned};
+
+use crate::{
+    kind::LeafKind,
+    tree::{Delimited, Leaf, Token},
+};
+
+#[wyst_copy]
+pub enum FoldKind {
+    /// A delimited region that spans multiple lines.
+    Region,
+    /// A run of comments (or a single comment) that spans multiple lines. A comment after code on
+    /// the same line is never part of a run.
+    Comment,
+    FrontMatter,
+}
+
+/// A range of lines that an editor can fold. The lines are zero-based and inclusive.
+#[wyst_copy]
+pub struct FoldingRange {
+    kind: FoldKind,
+    span: Span,
+    start_line: usize,
+    end_line: usize,
+}
+
+impl FoldingRange {
+    pub fn kind(&self) -> FoldKind {
+        self.kind
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn start_line(&self) -> usize {
+        self.start_line
+    }
+
+    pub fn end_line(&self) -> usize {
+        self.end_line
+    }
+}
+
+/// The spans of an opening delimiter and its matching closing delimiter.
+#[wyst_copy]
+pub struct BracketPair {
+    open: Span,
+    close: Span,
+}
+
+impl BracketPair {
+    pub fn open(&self) -> Span {
+        self.open
+    }
+
+    pub fn close(&self) -> Span {
+        self.close
+    }
+}
+
+/// Structural queries over a source's token tree.
+pub struct Structure<'a> {
+    tokens: &'a [Spanned<Token>],
+    source: &'a Source,
+    lines: LineIndex,
+}
+
+impl<'a> Structure<'a> {
+    pub fn new(tokens: &'a [Spanned<Token>], source: &'a Source) -> Structure<'a> {
+        Structure {
+            tokens,
+            source,
+            lines: source.line_index(),
+        }
+    }
+
+    /// The folding ranges in the source, ordered by where they start.
+    pub fn folding_ranges(&self) -> Vec<FoldingRange> {
+        let mut ranges = vec![];
+        self.fold_siblings(self.tokens, &mut ranges);
+        ranges.sort_by_key(|range| -> usize { range.span.start().into() });
+        ranges
+    }
+
+    fn fold_siblings(&self, tokens: &[Spanned<Token>], ranges: &mut Vec<FoldingRange>) {
+        let mut comments: Option<Span> = None;
+        let mut newlines = 0;
+        // Whether the current line has a significant token before the current token.
+        let mut after_code = false;
+
+        for token in tokens {
+            match token.item() {
+                // A comment after code on the same line isn't part of a run of comments.
+                Token::Leaf(Leaf::Comment(_)) if after_code => {
+                    self.fold(comments.take(), FoldKind::Comment, ranges);
+                }
+                Token::Leaf(Leaf::Comment(_)) => {
+                    // A blank line ends a run of comments.
+                    if newlines > 1 {
+                        self.fold(comments.take(), FoldKind::Comment, ranges);
+                    }
+
+                    comments = Some(match comments {
+                        Some(run) => run.until(token.span()),
+                        None => token.span(),
+                    });
+                    newlines = 0;
+                }
+                Token::Leaf(Leaf::Newline) => {
+                    newlines += 1;
+                    after_code = false;
+                }
+                Token::Leaf(Leaf::Whitespace) => {}
+                Token::Leaf(leaf) => {
+                    after_code = true;
+                    self.fold(comments.take(), FoldKind::Comment, ranges);
+
+                    if let Leaf::FrontMatter(_) = leaf {
+                        self.fold(Some(token.span()), FoldKind::FrontMatter, ranges);
+                    }
+                }
+                Token::Delimited(delimited) => {
+                    after_code = true;
+                    self.fold(comments.take(), FoldKind::Comment, ranges);
+                    self.fold(Some(token.span()), FoldKind::Region, ranges);
+                    self.fold_siblings(delimited.children(), ranges);
+                }
+            }
+        }
+
+        self.fold(comments, FoldKind::Comment, ranges);
+    }
+
+    /// Add a folding range for `span`, if it spans multiple lines.
+    fn fold(&self, span: Option<Span>, kind: FoldKind, ranges: &mut Vec<FoldingRange>) {
+        let span = match span {
+            Some(span) => span,
+            None => return,
+        };
+
+        let start_line = self.lines.line(span.start());
+        let end_line = self.lines.line(self.last_char(span));
+
+        if end_line > start_line {
+            ranges.push(FoldingRange {
+                kind,
+                span,
+                start_line,
+                end_line,
+            });
+        }
+    }
+
+    /// The offset of the last character in `span` (so that a span that ends with a newline
+    /// doesn't end on the next line).
+    fn last_char(&self, span: Span) -> Offset {
+        match self.source.slice(span).chars().last() {
+            Some(c) => span.end() - c,
+            None => span.start(),
+        }
+    }
+
+    /// The bracket pair for a delimiter at `offset` (just before or just after it). Unclosed
+    /// delimiters don't have a pair.
+    pub fn matching_bracket(&self, offset: impl Into<Offset>) -> Option<BracketPair> {
+        let offset = offset.into();
+        let mut pairs = vec![];
+        self.bracket_pairs(self.tokens, &mut pairs);
+
+        pairs
+            .iter()
+            .find(|pair| pair.open.start() == offset || pair.close.start() == offset)
+            .or_else(|| {
+                pairs
+                    .iter()
+                    .find(|pair| pair.open.end() == offset || pair.close.end() == offset)
+            })
+            .copied()
+    }
+
+    fn bracket_pairs(&self, tokens: &[Spanned<Token>], pairs: &mut Vec<BracketPair>) {
+        for token in tokens {
+            if let Token::Delimited(delimited) = token.item() {
+                if let Some(pair) = self.bracket_pair(delimited, token.span()) {
+                    pairs.push(pair);
+                }
+
+                self.bracket_pairs(delimited.children(), pairs);
+            }
+        }
+    }
+
+    fn bracket_pair(&self, delimited: &Delimited, span: Span) -> Option<BracketPair> {
+        let open = span.start().char_span(delimited.delimiter().open_char());
+        let close_char = delimited.delimiter().close_char();
+
+        if !delimited.is_closed() {
+            return None;
+        }
+
+        Some(BracketPair {
+            open,
+            close: (span.end() - close_char).char_span(close_char),
+        })
+    }
+
+    /// The spans that an expand-selection command steps through from `offset`, from the
+    /// innermost to the whole source. Each delimited region contributes the span of its
+    /// contents, followed by its own span.
+    pub fn selection_ranges(&self, offset: impl Into<Offset>) -> Vec<Span> {
+        let offset: usize = offset.into().into();
+        let mut chain = vec![];
+        let mut tokens = self.tokens;
+        let mut enclosing = vec![];
+
+        loop {
+            let found = tokens.iter().find(|token| {
+                let (start, end): (usize, usize) =
+                    (token.span().start().into(), token.span().end().into());
+                let significant = match token.item() {
+                    Token::Leaf(leaf) => !leaf.is_trivia() && *leaf != Leaf::EOF,
+                    Token::Delimited(_) => true,
+                };
+
+                significant && start <= offset && offset <= end && start != end
+            });
+
+            match found.map(|token| (token, token.item())) {
+                Some((token, Token::Delimited(delimited)))
+                    if offset > token.span().start().into()
+                        && offset < token.span().end().into() =>
+                {
+                    enclosing.push((token.span(), contents(delimited.children())));
+                    tokens = delimited.children();
+                }
+                Some((token, _)) => {
+                    chain.push(token.span());
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        for (span, contents) in enclosing.into_iter().rev() {
+            chain.extend(contents);
+            chain.push(span);
+        }
+
+        chain.extend(contents(self.tokens));
+        chain.push(Span::new(0, self.source.contents().len()));
+        chain.dedup();
+        chain
+    }
+}
+
+/// The span from the first to the last significant token in `tokens`.
+fn contents(tokens: &[Spanned<Token>]) -> Option<Span> {
+    let significant = |token: &&Spanned<Token>| match token.item() {
+        Token::Leaf(leaf) => !leaf.is_trivia() && *leaf != Leaf::EOF,
+        Token::Delimited(_) => true,
+    };
+
+    let first = tokens.iter().find(significant)?;
+    let last = tokens.iter().rev().find(significant)?;
+
+    Some(first.span().until(last.span()))
+}
+
+unit_tests!(
+    all({
+        use crate::{
+            delegate::DefaultDelegate, standard::FlatToken, testing::read,
+            token_builder::TokenBuilder,
+        };
+
+        fn folds(source: &Source, tokens: &[Spanned<Token>]) -> Vec<(FoldKind, usize, usize)> {
+            Structure::new(tokens, source)
+                .folding_ranges()
+                .iter()
+                .map(|range| (range.kind(), range.start_line(), range.end_line()))
+                .collect()
+        }
+    }),
+    tests(
+        ("folding ranges (regions)", {
+            let source = Source::new("<test>", "f {\n  g(a,\n    b)\n  [c]\n}\nh(\n)");
+            let tokens = read(&source);
+
+            assert_eq!(
+                folds(&source, &tokens),
+                vec![
+                    (FoldKind::Region, 0, 4),
+                    (FoldKind::Region, 1, 2),
+                    (FoldKind::Region, 5, 6)
+                ]
+            );
+        }),
+        ("folding ranges (comments)", {
+            let source = Source::new("<test>", "# a\n# b\n\n# c\nx # d\n# e\n# f");
+            let mut b = TokenBuilder::new();
+            let tokens = vec![
+                b.comment(" a", ("#", "")),
+                b.newline(),
+                b.comment(" b", ("#", "")),
+                b.newline(),
+                b.newline(),
+                b.comment(" c", ("#", "")),
+                b.newline(),
+                b.word("x"),
+                b.ws(" "),
+                b.comment(" d", ("#", "")),
+                b.newline(),
+                b.comment(" e", ("#", "")),
+                b.newline(),
+                b.comment(" f", ("#", "")),
+                b.eof(),
+            ];
+
+            // `# d` is a trailing comment, so it doesn't start a run with `# e`.
+            assert_eq!(
+                folds(&source, &tokens),
+                vec![(FoldKind::Comment, 0, 1), (FoldKind::Comment, 5, 6)]
+            );
+        }),
+        ("folding ranges (front matter)", {
+            let source = Source::new("<test>", "---\ntitle: x\n---\nbody");
+            let tokens = FlatToken::lex_source::<DefaultDelegate>(source.contents())
+                .with_preamble()
+                .read_tree(&source)
+                .into_tokens();
+
+            assert_eq!(folds(&source, &tokens), vec![(FoldKind::FrontMatter, 0, 2)]);
+        }),
+        ("matching brackets", {
+            let source = Source::new("<test>", "a(b[c]) (d");
+            let tokens = read(&source);
+            let structure = Structure::new(&tokens, &source);
+            let pair = |open: usize, close: usize| BracketPair {
+                open: Span::new(open, open + 1),
+                close: Span::new(close, close + 1),
+            };
+
+            assert_eq!(structure.matching_bracket(1), Some(pair(1, 6)));
+            assert_eq!(structure.matching_bracket(6), Some(pair(1, 6)));
+            assert_eq!(structure.matching_bracket(7), Some(pair(1, 6)));
+            assert_eq!(structure.matching_bracket(3), Some(pair(3, 5)));
+            assert_eq!(structure.matching_bracket(4), Some(pair(3, 5)));
+            assert_eq!(structure.matching_bracket(0), None);
+            assert_eq!(structure.matching_bracket(8), None);
+        }),
+        ("matching brackets (unclosed outer bracket)", {
+            let source = Source::new("<test>", "( ( )");
+            let tokens = read(&source);
+            let structure = Structure::new(&tokens, &source);
+            let inner = BracketPair {
+                open: Span::new(2, 3),
+                close: Span::new(4, 5),
+            };
+
+            assert_eq!(structure.matching_bracket(0), None);
+            assert_eq!(structure.matching_bracket(2), Some(inner));
+            assert_eq!(structure.matching_bracket(4), Some(inner));
+        }),
+        ("selection ranges", {
+            let source = Source::new("<test>", " f(x, { y  z })\n");
+            let tokens = read(&source);
+            let structure = Structure::new(&tokens, &source);
+            let texts = |offset: usize| -> Vec<&str> {
+                structure
+                    .selection_ranges(offset)
+                    .into_iter()
+                    .map(|span| source.slice(span))
+                    .collect()
+            };
+
+            assert_eq!(
+                texts(9),
+                vec![
+                    "y",
+                    "y  z",
+                    "{ y  z }",
+                    "x, { y  z }",
+                    "(x, { y  z })",
+                    "f(x, { y  z })",
+                    " f(x, { y  z })\n"
+                ]
+            );
+            assert_eq!(
+                texts(10),
+                vec![
+                    "y  z",
+                    "{ y  z }",
+                    "x, { y  z }",
+                    "(x, { y  z })",
+                    "f(x, { y  z })",
+                    " f(x, { y  z })\n"
+                ]
+            );
+        })
+    )
+);
@@ -0,0 +1,10 @@
+//! Helpers for the unit tests.
+
+use wyst_source::{Source, Spanned};
+
+use crate::{delegate::DefaultDelegate, standard::FlatToken, tree::Token};
+
+/// Read a source into token trees with the default delegate.
+pub(crate) fn read(source: &Source) -> Vec<Spanned<Token>> {
+    FlatToken::read_tree::<DefaultDelegate>(source).into_tokens()
+}
@@ -1,3 +1,4 @@
+use wyst_core::unit_tests;
 use wyst_source::{AddSpan, HasLen, Span, Spanned};
 
 use crate::{
@@ -41,6 +42,22 @@ impl TokenBuilder {
         Token::Leaf(Leaf::Word).spanned(self.consume(chars))
     }
 
+    pub fn error(&mut self, chars: impl HasLen) -> Spanned<Token> {
+        Token::Leaf(Leaf::Error).spanned(self.consume(chars))
+    }
+
+    pub fn comment(
+        &mut self,
+        chars: impl HasLen,
+        (pre, post): (impl HasLen, impl HasLen),
+    ) -> Spanned<Token> {
+        let start = self.consume(pre);
+        let body = self.consume(chars);
+        let end = self.consume(post);
+
+        Token::Leaf(Leaf::Comment(body)).spanned(start.until(end))
+    }
+
     pub fn quote(&mut self, quote: impl Into<Quote>, chars: impl HasLen) -> Spanned<Token> {
         let quote = quote.into();
         let outer_start = self.consume(quote.char());
@@ -94,3 +111,86 @@ impl TokenBuilder {
         }
     }
 }
+
+unit_tests!(
+    all({
+        use crate::delegate::DefaultDelegate;
+        use crate::tokens;
+        use wyst_source::Source;
+
+        fn read(source: &str) -> Vec<Spanned<Token>> {
+            let source = Source::new("<test>", source);
+            FlatToken::read_source::<DefaultDelegate>(&source).collect()
+        }
+    }),
+    tests(
+        ("tokens! (same as TokenBuilder)", {
+            let mut b = TokenBuilder::new();
+
+            assert_eq!(
+                tokens! { $ws(3) hello { world() } $ws(3) $eof },
+                &[
+                    b.ws("   "),
+                    b.word("hello"),
+                    b.ws(" "),
+                    b.delimited(Delimiter::Brace, |b| {
+                        vec![
+                            b.ws(" "),
+                            b.word("world"),
+                            b.delimited(Delimiter::Paren, |_| vec![]),
+                            b.ws(" "),
+                        ]
+                    }),
+                    b.ws("   "),
+                    b.eof()
+                ]
+            );
+        }),
+        ("tokens! (same as reading the source)", {
+            assert_eq!(
+                tokens! { hello { world() } $eof },
+                read("hello { world() }")
+            );
+            assert_eq!(
+                tokens! { call(a, [b]){c}   x.y->z $eof },
+                read("call(a, [b]){c}   x.y->z")
+            );
+            assert_eq!(
+                tokens! { say "hello" 'c' x $eof },
+                read(r#"say "hello" 'c' x"#)
+            );
+            assert_eq!(
+                tokens! {
+                    a {
+                        b
+                    }
+
+                    c $eof
+                },
+                read("a {\nb\n}\n\nc")
+            );
+        }),
+        ("tokens! (escapes)", {
+            let mut b = TokenBuilder::new();
+
+            assert_eq!(
+                tokens! {
+                    a $ws("\t") $comment("#", " note") $nl
+                    $ws(2) $word("#b") $quote('`', "tick") $error(")") $eof
+                },
+                &[
+                    b.word("a"),
+                    b.ws("\t"),
+                    b.comment(" note", ("#", "")),
+                    b.newline(),
+                    b.newline(),
+                    b.ws("  "),
+                    b.word("#b"),
+                    b.quote(Quote::Backtick, "tick"),
+                    b.error(")"),
+                    b.eof()
+                ]
+            );
+        })
+    )
+);
@@ -1,14 +1,19 @@
 use std::collections::VecDeque;
 
 use wyst_core::{unit_tests, wyst_copy, wyst_data};
-use wyst_source::{AddSpan, Offset, Span, Spanned};
+use wyst_source::{AddSpan, Diagnostic, Offset, Source, Span, Spanned};
 
-use crate::reader::{Reader, ReaderNext};
-use crate::{delegate::QuoteResult, standard::Delimiter};
+use crate::reader::{
+    unclosed_delimiter, unexpected_close, unexpected_input, Closing, ReadTokens, Reader, ReaderNext,
+};
+use crate::{
+    delegate::QuoteResult,
+    kind::{LeafKind, TokenKind},
+    standard::{Delimiter, Fence},
+};
 
 #[wyst_copy]
 pub enum Leaf {
-    #[allow(unused)]
     Error,
 
     EOF,
@@ -17,6 +22,8 @@ pub enum Leaf {
     Comment(Span),
     Whitespace,
     Newline,
+    Shebang,
+    FrontMatter((Fence, Span)),
 }
 
 #[wyst_data]
@@ -25,58 +32,150 @@ pub struct Program {
 }
 
 #[wyst_data]
-pub struct Delimited {
+pub struct Delimited<L: LeafKind = Leaf> {
     delimiter: Delimiter,
-    children: Vec<Spanned<Token>>,
+    children: Vec<Spanned<Token<L>>>,
+    closed: bool,
 }
 
-impl Delimited {
-    fn push(&mut self, token: Spanned<Token>) {
+impl<L: LeafKind> Delimited<L> {
+    pub fn delimiter(&self) -> Delimiter {
+        self.delimiter
+    }
+
+    /// Whether the delimiter was closed. An unclosed delimiter's span ends where the reader gave
+    /// up on it: at the end of the source, or at the close of a delimiter that contains it.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    pub fn children(&self) -> &[Spanned<Token<L>>] {
+        &self.children
+    }
+
+    fn push(&mut self, token: Spanned<Token<L>>) {
         self.children.push(token);
     }
 }
 
+/// A token tree. The leaves are [Leaf]s by default, but a token tree can be read from any
+/// [TokenKind], in which case its leaves are the kind's [TokenKind::Leaf].
 #[wyst_data]
-pub enum Token {
-    Leaf(Leaf),
-    Delimited(Delimited),
+pub enum Token<L: LeafKind = Leaf> {
+    Leaf(L),
+    Delimited(Delimited<L>),
 }
 
-impl Token {
+impl<L: LeafKind> Token<L> {
     pub fn delimited(
         delimiter: impl Into<Delimiter>,
-        tokens: impl IntoIterator<Item = Spanned<Token>>,
-    ) -> Token {
+        tokens: impl IntoIterator<Item = Spanned<Token<L>>>,
+    ) -> Token<L> {
+        Token::Delimited(Delimited {
+            delimiter: delimiter.into(),
+            children: tokens.into_iter().collect(),
+            closed: true,
+        })
+    }
+
+    /// A delimited token whose delimiter was never closed.
+    pub fn unclosed(
+        delimiter: impl Into<Delimiter>,
+        tokens: impl IntoIterator<Item = Spanned<Token<L>>>,
+    ) -> Token<L> {
         Token::Delimited(Delimited {
             delimiter: delimiter.into(),
             children: tokens.into_iter().collect(),
+            closed: false,
         })
     }
 }
 
+/// The result of reading a source into a token tree.
+#[wyst_data]
+pub struct ReadResult<L: LeafKind = Leaf> {
+    tokens: Vec<Spanned<Token<L>>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<L: LeafKind> ReadResult<L> {
+    pub(crate) fn new(
+        tokens: Vec<Spanned<Token<L>>>,
+        diagnostics: Vec<Diagnostic>,
+    ) -> ReadResult<L> {
+        ReadResult {
+            tokens,
+            diagnostics,
+        }
+    }
+
+    pub fn tokens(&self) -> &[Spanned<Token<L>>] {
+        &self.tokens
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(|d| d.is_error())
+    }
+
+    pub fn into_tokens(self) -> Vec<Spanned<Token<L>>> {
+        self.tokens
+    }
+}
+
 /// "{" "(" hello ")" "}"
 ///
 /// push "{"
 /// push "("
 /// push "hello"
-pub struct TokenTree {
-    stack: Vec<(Delimited, Offset)>,
-    current_parent: Option<(Delimited, Offset)>,
-    finished_tokens: VecDeque<Spanned<Token>>,
+///
+/// Unbalanced delimiters don't stop the tree from being built. An unexpected closing delimiter
+/// becomes an error leaf and unclosed delimiters are closed implicitly. Both are reported as
+/// diagnostics.
+pub struct TokenTree<L: LeafKind = Leaf> {
+    stack: Vec<(Delimited<L>, Offset)>,
+    current_parent: Option<(Delimited<L>, Offset)>,
+    finished_tokens: VecDeque<Spanned<Token<L>>>,
+    diagnostics: Vec<Diagnostic>,
     done: bool,
 }
 
-impl TokenTree {
-    pub fn new() -> TokenTree {
+impl<L: LeafKind> Default for TokenTree<L> {
+    fn default() -> Self {
+        TokenTree::new()
+    }
+}
+
+impl<L: LeafKind> TokenTree<L> {
+    pub fn new() -> TokenTree<L> {
         TokenTree {
             stack: vec![],
             current_parent: None,
             finished_tokens: VecDeque::new(),
+            diagnostics: vec![],
             done: false,
         }
     }
 
-    fn push_leaf(&mut self, leaf: Leaf, span: Span) {
+    /// Read a stream of tokens of any [TokenKind] into a token tree.
+    pub fn read<K>(source: &Source, tokens: impl Iterator<Item = Spanned<K>>) -> ReadResult<L>
+    where
+        K: TokenKind<Leaf = L>,
+    {
+        let mut read = ReadTokens::new(TokenTree::new(), tokens, source);
+        let tokens = read.by_ref().collect();
+
+        ReadResult::new(tokens, read.into_reader().into_diagnostics())
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+
+    fn push_leaf(&mut self, leaf: L, span: Span) {
         let token = Token::Leaf(leaf).spanned(span);
 
         match &mut self.current_parent {
@@ -84,10 +183,51 @@ impl TokenTree {
             None => self.finished_tokens.push_back(token),
         }
     }
+
+    fn open_delimiters(&self) -> impl Iterator<Item = Delimiter> + '_ {
+        self.current_parent
+            .iter()
+            .chain(self.stack.iter().rev())
+            .map(|(parent, _)| parent.delimiter)
+    }
+
+    /// Close the current parent at `end`.
+    fn close_parent(&mut self, end: Offset, closed: bool) {
+        let (mut parent, start) = self
+            .current_parent
+            .take()
+            .expect("BUG: close_parent called without an open delimiter");
+
+        parent.closed = closed;
+        let token = Token::Delimited(parent).spanned(Span::new(start, end));
+
+        match self.stack.pop() {
+            Some((mut tail, tail_offset)) => {
+                tail.push(token);
+                self.current_parent = Some((tail, tail_offset));
+            }
+            None => self.finished_tokens.push_back(token),
+        }
+    }
+
+    /// Report the current parent as unclosed, and close it at `end`.
+    fn close_unclosed(&mut self, end: Offset) {
+        if let Some((parent, start)) = &self.current_parent {
+            let delimiter = parent.delimiter;
+            self.diagnostics.push(unclosed_delimiter(
+                delimiter,
+                start.char_span(delimiter.open_char()),
+            ));
+        }
+
+        self.close_parent(end, false);
+    }
 }
 
-impl Reader for TokenTree {
-    fn next(&mut self) -> ReaderNext<Spanned<Token>> {
+impl<L: LeafKind> Reader<L> for TokenTree<L> {
+    type Item = Spanned<Token<L>>;
+
+    fn next(&mut self) -> ReaderNext<Spanned<Token<L>>> {
         match self.finished_tokens.pop_front() {
             Some(token) => ReaderNext::Token(token),
             None => {
@@ -100,27 +240,11 @@ impl Reader for TokenTree {
         }
     }
 
-    fn word(&mut self, _source: &wyst_source::Source, span: Span) {
-        self.push_leaf(Leaf::Word, span);
-    }
-
-    fn whitespace(&mut self, _source: &wyst_source::Source, span: Span) {
-        self.push_leaf(Leaf::Whitespace, span);
-    }
-
-    fn newline(&mut self, _source: &wyst_source::Source, span: Span) {
-        self.push_leaf(Leaf::Newline, span);
+    fn leaf(&mut self, _source: &Source, span: Span, leaf: L) {
+        self.push_leaf(leaf, span);
     }
 
-    fn comment(&mut self, _source: &wyst_source::Source, span: Span, body: Span) {
-        self.push_leaf(Leaf::Comment(body), span)
-    }
-
-    fn quoted(&mut self, _source: &wyst_source::Source, span: Span, quote: QuoteResult) {
-        self.push_leaf(Leaf::Quoted(quote), span)
-    }
-
-    fn open(&mut self, _source: &wyst_source::Source, span: Span, delimiter: Delimiter) {
+    fn open(&mut self, _source: &Source, span: Span, delimiter: Delimiter) {
         if let Some(parent) = self.current_parent.take() {
             self.stack.push(parent);
         }
@@ -129,41 +253,42 @@ impl Reader for TokenTree {
             Delimited {
                 delimiter,
                 children: vec![],
+                closed: false,
             },
             span.start(),
         ));
     }
 
-    fn close(&mut self, _source: &wyst_source::Source, span: Span, _delimiter: Delimiter) {
-        let (parent, start) = self
-            .current_parent
-            .take()
-            .expect("BUG: unbalanced open/close");
-
-        let token = Token::Delimited(parent).spanned(Span::new(start, span.end()));
+    fn close(&mut self, _source: &Source, span: Span, delimiter: Delimiter) {
+        match Closing::find(self.open_delimiters(), delimiter) {
+            Closing::Matches { unclosed } => {
+                for _ in 0..unclosed {
+                    self.close_unclosed(span.start());
+                }
 
-        match self.stack.pop() {
-            Some((mut tail, tail_offset)) => {
-                tail.push(token);
-                self.current_parent = Some((tail, tail_offset));
+                self.close_parent(span.end(), true);
+            }
+            Closing::Unexpected => {
+                self.diagnostics.push(unexpected_close(delimiter, span));
+                self.push_leaf(L::error(), span);
             }
-            None => self.finished_tokens.push_back(token),
         }
     }
 
-    fn eof(&mut self, _source: &wyst_source::Source, span: Span) {
+    fn eof(&mut self, _source: &Source, span: Span) {
         self.done = true;
 
-        if let Some(_) = self.current_parent {
-            panic!("eof() called when there was still an open delimiter")
-        };
-
-        if self.stack.len() > 0 {
-            panic!("eof() called when there was still an open delimiter")
+        while self.current_parent.is_some() {
+            self.close_unclosed(span.start());
         }
 
         self.finished_tokens
-            .push_back(Token::Leaf(Leaf::EOF).spanned(span));
+            .push_back(Token::Leaf(L::eof()).spanned(span));
+    }
+
+    fn error(&mut self, _source: &Source, span: Span) {
+        self.diagnostics.push(unexpected_input(span));
+        self.push_leaf(L::error(), span);
     }
 }
 
@@ -173,7 +298,6 @@ unit_tests!(
         use crate::standard::FlatToken;
         use crate::token_builder::TokenBuilder;
         use std::sync::Arc;
-        use wyst_source::Source;
 
         fn source(string: &str) -> Arc<Source> {
             Arc::new(Source::new("<test>", string))
@@ -239,6 +363,72 @@ unit_tests!(
                     b.eof()
                 ]
             );
+        }),
+        ("unexpected close", {
+            let s = source("a ) b");
+            let result = FlatToken::read_tree::<DefaultDelegate>(&s);
+            let mut b = TokenBuilder::new();
+
+            assert_eq!(
+                result.tokens(),
+                &[
+                    b.word("a"),
+                    b.ws(" "),
+                    b.error(")"),
+                    b.ws(" "),
+                    b.word("b"),
+                    b.eof()
+                ]
+            );
+            assert_eq!(
+                result.diagnostics(),
+                &[Diagnostic::error(Span::new(2, 3), "unexpected `)`")]
+            );
+        }),
+        ("unclosed at eof", {
+            let s = source("( a");
+            let result = FlatToken::read_tree::<DefaultDelegate>(&s);
+            let mut b = TokenBuilder::new();
+            let open = b.consume("(");
+            let children = vec![b.ws(" "), b.word("a")];
+
+            assert_eq!(
+                result.tokens(),
+                &[
+                    Token::unclosed(Delimiter::Paren, children)
+                        .spanned(open.until(Span::new(3, 3))),
+                    b.eof()
+                ]
+            );
+            assert_eq!(
+                result.diagnostics(),
+                &[Diagnostic::error(Span::new(0, 1), "unclosed `(`")]
+            );
+        }),
+        ("mismatched close", {
+            let s = source("{ ( }");
+            let result = FlatToken::read_tree::<DefaultDelegate>(&s);
+            let mut b = TokenBuilder::new();
+            let open_brace = b.consume("{");
+            let ws = b.ws(" ");
+            let open_paren = b.consume("(");
+            let paren = Token::unclosed(Delimiter::Paren, vec![b.ws(" ")])
+                .spanned(open_paren.until(Span::new(4, 4)));
+            let close_brace = b.consume("}");
+
+            assert_eq!(
+                result.tokens(),
+                &[
+                    Token::delimited(Delimiter::Brace, vec![ws, paren])
+                        .spanned(open_brace.until(close_brace)),
+                    b.eof()
+                ]
+            );
+            assert_eq!(
+                result.diagnostics(),
+                &[Diagnostic::error(Span::new(2, 3), "unclosed `(`")]
+            );
+            assert!(result.has_errors());
         })
     )
 );
@@ -0,0 +1,409 @@
+use wyst_core::{unit_tests, wyst_data};
+use wyst_source::{AddSpan, Span, Spanned};
+
+use crate::{
+    kind::LeafKind,
+    standard::Delimiter,
+    tree::{Leaf, Token},
+};
+
+/// A significant (non-trivia) token, together with the trivia around it.
+///
+/// Trivia that follows a token on the same line (such as a trailing comment) is attached to it as
+/// `trailing` trivia. Everything else between two significant tokens, starting with the newline
+/// that ends the previous token's line, is attached to the next token as `leading` trivia.
+#[wyst_data]
+pub struct Attached {
+    token: Spanned<AttachedToken>,
+    leading: Vec<Spanned<Leaf>>,
+    trailing: Vec<Spanned<Leaf>>,
+    blank_lines: usize,
+}
+
+impl Attached {
+    pub fn token(&self) -> &Spanned<AttachedToken> {
+        &self.token
+    }
+
+    pub fn leading(&self) -> &[Spanned<Leaf>] {
+        &self.leading
+    }
+
+    pub fn trailing(&self) -> &[Spanned<Leaf>] {
+        &self.trailing
+    }
+
+    /// The number of blank lines (lines that only contain whitespace) between the previous
+    /// significant token and this one.
+    pub fn blank_lines(&self) -> usize {
+        self.blank_lines
+    }
+
+    /// Comments in the leading trivia.
+    pub fn leading_comments(&self) -> impl Iterator<Item = Span> + '_ {
+        comments(&self.leading)
+    }
+
+    /// Comments in the trailing trivia.
+    pub fn trailing_comments(&self) -> impl Iterator<Item = Span> + '_ {
+        comments(&self.trailing)
+    }
+}
+
+#[wyst_data]
+pub enum AttachedToken {
+    Leaf(Leaf),
+    Delimited(AttachedDelimited),
+}
+
+#[wyst_data]
+pub struct AttachedDelimited {
+    delimiter: Delimiter,
+    after_open: Vec<Spanned<Leaf>>,
+    children: TriviaList,
+    closed: bool,
+}
+
+impl AttachedDelimited {
+    pub fn delimiter(&self) -> Delimiter {
+        self.delimiter
+    }
+
+    /// Trivia on the same line as the opening delimiter (such as a comment after a `{`).
+    pub fn after_open(&self) -> &[Spanned<Leaf>] {
+        &self.after_open
+    }
+
+    pub fn children(&self) -> &TriviaList {
+        &self.children
+    }
+
+    /// See [Delimited::is_closed](crate::Delimited::is_closed).
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// A list of sibling tokens with their trivia attached.
+#[wyst_data]
+pub struct TriviaList {
+    items: Vec<Attached>,
+    dangling: Vec<Spanned<Leaf>>,
+    blank_lines_after: usize,
+}
+
+impl TriviaList {
+    pub fn items(&self) -> &[Attached] {
+        &self.items
+    }
+
+    /// Trivia after the last item that isn't on the same line as it (such as a comment on its own
+    /// line before a closing `}`). If the list has no items, all of its trivia is dangling.
+    pub fn dangling(&self) -> &[Spanned<Leaf>] {
+        &self.dangling
+    }
+
+    /// The number of blank lines after the last item.
+    pub fn blank_lines_after(&self) -> usize {
+        self.blank_lines_after
+    }
+
+    /// Flatten the list back into the tokens that it was built from.
+    pub fn to_tokens(&self) -> Vec<Spanned<Token>> {
+        let mut tokens = vec![];
+        self.flatten_into(&mut tokens);
+        tokens
+    }
+
+    fn flatten_into(&self, tokens: &mut Vec<Spanned<Token>>) {
+        for item in &self.items {
+            push_trivia(tokens, &item.leading);
+
+            let span = item.token.span();
+
+            match item.token.item() {
+                AttachedToken::Leaf(leaf) => tokens.push(Token::Leaf(*leaf).spanned(span)),
+                AttachedToken::Delimited(delimited) => {
+                    let mut children = vec![];
+                    push_trivia(&mut children, &delimited.after_open);
+                    delimited.children.flatten_into(&mut children);
+
+                    let token = if delimited.closed {
+                        Token::delimited(delimited.delimiter, children)
+                    } else {
+                        Token::unclosed(delimited.delimiter, children)
+                    };
+
+                    tokens.push(token.spanned(span));
+                }
+            }
+
+            push_trivia(tokens, &item.trailing);
+        }
+
+        push_trivia(tokens, &self.dangling);
+    }
+}
+
+fn push_trivia(tokens: &mut Vec<Spanned<Token>>, trivia: &[Spanned<Leaf>]) {
+    tokens.extend(
+        trivia
+            .iter()
+            .map(|leaf| Token::Leaf(*leaf.item()).spanned(leaf.span())),
+    );
+}
+
+fn comments(trivia: &[Spanned<Leaf>]) -> impl Iterator<Item = Span> + '_ {
+    trivia.iter().filter_map(|leaf| match leaf.item() {
+        Leaf::Comment(_) => Some(leaf.span()),
+        _ => None,
+    })
+}
+
+/// Attach the trivia in a token tree (whitespace, newlines and comments) to the significant tokens
+/// around it, so that a formatter can ask a token for its comments rather than working them out
+/// from its siblings.
+///
+/// The trivia isn't changed or dropped: [TriviaList::to_tokens] turns the result back into the
+/// original tokens.
+pub fn attach_trivia(tokens: &[Spanned<Token>]) -> TriviaList {
+    attach_list(tokens, Gap::start()).1
+}
+
+/// Attach the trivia in a list of siblings. The trivia at the start of the list that belongs to
+/// whatever came before the list (if `gap` says that there was something) is returned separately.
+fn attach_list(tokens: &[Spanned<Token>], mut gap: Gap) -> (Vec<Spanned<Leaf>>, TriviaList) {
+    let mut before = vec![];
+    let mut items: Vec<Attached> = vec![];
+
+    for token in tokens {
+        let span = token.span();
+
+        let attached = match token.item() {
+            Token::Leaf(leaf) if leaf.is_trivia() => {
+                gap.push((*leaf).spanned(span));
+                continue;
+            }
+            Token::Leaf(leaf) => AttachedToken::Leaf(*leaf),
+            Token::Delimited(delimited) => {
+                let (after_open, children) = attach_list(delimited.children(), Gap::after());
+
+                AttachedToken::Delimited(AttachedDelimited {
+                    delimiter: delimited.delimiter(),
+                    after_open,
+                    children,
+                    closed: delimited.is_closed(),
+                })
+            }
+        };
+
+        let Gap {
+            trailing,
+            leading,
+            blank_lines,
+            ..
+        } = std::mem::replace(&mut gap, Gap::after());
+
+        match items.last_mut() {
+            Some(previous) => previous.trailing = trailing,
+            None => before = trailing,
+        }
+
+        items.push(Attached {
+            token: attached.spanned(span),
+            leading,
+            trailing: vec![],
+            blank_lines,
+        });
+    }
+
+    match items.last_mut() {
+        Some(previous) => previous.trailing = gap.trailing,
+        None => before = gap.trailing,
+    }
+
+    let list = TriviaList {
+        items,
+        dangling: gap.leading,
+        blank_lines_after: gap.blank_lines,
+    };
+
+    (before, list)
+}
+
+/// The trivia between two significant tokens.
+struct Gap {
+    trailing: Vec<Spanned<Leaf>>,
+    leading: Vec<Spanned<Leaf>>,
+    blank_lines: usize,
+    /// True until the first newline after the previous token.
+    same_line: bool,
+    /// True if the current line has anything other than whitespace on it.
+    has_content: bool,
+}
+
+impl Gap {
+    /// The gap at the start of a source, which doesn't follow any token.
+    fn start() -> Gap {
+        Gap {
+            trailing: vec![],
+            leading: vec![],
+            blank_lines: 0,
+            same_line: false,
+            has_content: false,
+        }
+    }
+
+    /// The gap after a token (or an opening delimiter).
+    fn after() -> Gap {
+        Gap {
+            same_line: true,
+            has_content: true,
+            ..Gap::start()
+        }
+    }
+
+    fn push(&mut self, trivia: Spanned<Leaf>) {
+        match trivia.item() {
+            Leaf::Newline => {
+                if !self.has_content {
+                    self.blank_lines += 1;
+                }
+
+                self.same_line = false;
+                self.has_content = false;
+                self.leading.push(trivia);
+            }
+            Leaf::Comment(_) if !self.same_line => {
+                self.has_content = true;
+                self.leading.push(trivia);
+            }
+            _ if self.same_line => self.trailing.push(trivia),
+            _ => self.leading.push(trivia),
+        }
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::token_builder::TokenBuilder;
+
+        use wyst_source::Source;
+
+        fn texts<'a>(source: &'a Source, trivia: &[Spanned<Leaf>]) -> Vec<&'a str> {
+            trivia
+                .iter()
+                .map(|leaf| source.slice(leaf.span()))
+                .collect()
+        }
+    }),
+    tests(
+        ("trailing and leading comments", {
+            let source = Source::new("<test>", "a # one\n\n# two\nb");
+            let mut b = TokenBuilder::new();
+            let tokens = vec![
+                b.word("a"),
+                b.ws(" "),
+                b.comment(" one", ("#", "")),
+                b.newline(),
+                b.newline(),
+                b.comment(" two", ("#", "")),
+                b.newline(),
+                b.word("b"),
+            ];
+
+            let list = attach_trivia(&tokens);
+            let items = list.items();
+
+            assert_eq!(items.len(), 2);
+            assert_eq!(texts(&source, items[0].trailing()), &[" ", "# one"]);
+            assert_eq!(texts(&source, items[0].leading()), Vec::<&str>::new());
+            assert_eq!(
+                texts(&source, items[1].leading()),
+                &["\n", "\n", "# two", "\n"]
+            );
+            assert_eq!(items[1].blank_lines(), 1);
+            assert_eq!(items[1].leading_comments().count(), 1);
+            assert_eq!(items[0].trailing_comments().count(), 1);
+            assert_eq!(list.to_tokens(), tokens);
+        }),
+        ("blank lines", {
+            let mut b = TokenBuilder::new();
+            let tokens = vec![
+                b.newline(),
+                b.word("a"),
+                b.newline(),
+                b.word("b"),
+                b.newline(),
+                b.ws("  "),
+                b.newline(),
+                b.newline(),
+                b.word("c"),
+                b.newline(),
+                b.newline(),
+            ];
+
+            let list = attach_trivia(&tokens);
+            let blank_lines: Vec<_> = list.items().iter().map(|item| item.blank_lines()).collect();
+
+            assert_eq!(blank_lines, &[1, 0, 2]);
+            assert_eq!(list.blank_lines_after(), 1);
+            assert_eq!(list.to_tokens(), tokens);
+        }),
+        ("delimited", {
+            let source = Source::new("<test>", "{ # open\n  a # a\n  # end\n}");
+            let mut b = TokenBuilder::new();
+            let tokens = vec![b.delimited(Delimiter::Brace, |b| {
+                vec![
+                    b.ws(" "),
+                    b.comment(" open", ("#", "")),
+                    b.newline(),
+                    b.ws("  "),
+                    b.word("a"),
+                    b.ws(" "),
+                    b.comment(" a", ("#", "")),
+                    b.newline(),
+                    b.ws("  "),
+                    b.comment(" end", ("#", "")),
+                    b.newline(),
+                ]
+            })];
+
+            let list = attach_trivia(&tokens);
+            let delimited = match list.items()[0].token().item() {
+                AttachedToken::Delimited(delimited) => delimited,
+                other => panic!("expected a delimited token, got {:?}", other),
+            };
+
+            assert_eq!(texts(&source, delimited.after_open()), &[" ", "# open"]);
+
+            let children = delimited.children();
+            assert_eq!(children.items().len(), 1);
+            assert_eq!(texts(&source, children.items()[0].leading()), &["\n", "  "]);
+            assert_eq!(
+                texts(&source, children.items()[0].trailing()),
+                &[" ", "# a"]
+            );
+            assert_eq!(
+                texts(&source, children.dangling()),
+                &["\n", "  ", "# end", "\n"]
+            );
+            assert_eq!(list.to_tokens(), tokens);
+        }),
+        ("only trivia", {
+            let mut b = TokenBuilder::new();
+            let tokens = vec![b.delimited(Delimiter::Paren, |b| vec![b.ws(" "), b.newline()])];
+
+            let list = attach_trivia(&tokens);
+            let delimited = match list.items()[0].token().item() {
+                AttachedToken::Delimited(delimited) => delimited,
+                other => panic!("expected a delimited token, got {:?}", other),
+            };
+
+            assert_eq!(delimited.after_open().len(), 1);
+            assert!(delimited.children().items().is_empty());
+            assert_eq!(delimited.children().dangling().len(), 1);
+            assert_eq!(list.to_tokens(), tokens);
+        })
+    )
+);
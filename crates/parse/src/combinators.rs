@@ -0,0 +1,422 @@
+//! Combinators that build parsers out of other parsers.
+//!
+//! [alt] backtracks: if an alternative fails, the next one starts from the same input. The
+//! repetition combinators ([many], [sep_by] and [opt]) only stop when their parser fails without
+//! getting past the first piece of input. A parser that fails after consuming some input is an
+//! error, so `many(seq((word(), punct(';'))))` reports the missing `;` in `a; b c;` rather than
+//! stopping after `a;`.
+
+use wyst_core::{unit_tests, WystData};
+use wyst_source::{AddSpan, Spanned};
+
+use crate::{
+    error::ParseError,
+    input::Input,
+    parser::{ParseResult, Parser},
+    recovery::report_furthest,
+};
+
+/// Parsers that run one after another. Implemented for tuples of parsers.
+pub trait Seq<'a> {
+    type Output;
+
+    fn parse_seq(&self, input: Input<'a>) -> ParseResult<'a, Self::Output>;
+}
+
+/// Parsers that are tried in order, until one of them succeeds. Implemented for tuples of parsers
+/// with the same output.
+pub trait Alt<'a> {
+    type Output;
+
+    fn parse_alt(&self, input: Input<'a>) -> ParseResult<'a, Self::Output>;
+}
+
+macro_rules! tuple_impls {
+    ($($parser:ident $var:ident),*) => {
+        impl<'a, $($parser: Parser<'a>),*> Seq<'a> for ($($parser,)*) {
+            type Output = ($(<$parser as Parser<'a>>::Output,)*);
+
+            fn parse_seq(&self, input: Input<'a>) -> ParseResult<'a, Self::Output> {
+                let ($($var,)*) = self;
+                $(let ($var, input) = $var.parse(input)?;)*
+
+                Ok((($($var,)*), input))
+            }
+        }
+
+        impl<'a, T, $($parser: Parser<'a, Output = T>),*> Alt<'a> for ($($parser,)*) {
+            type Output = T;
+
+            fn parse_alt(&self, input: Input<'a>) -> ParseResult<'a, T> {
+                let ($($var,)*) = self;
+                let mut error: Option<ParseError> = None;
+
+                $(
+                    match $var.parse(input.clone()) {
+                        Ok(result) => return Ok(result),
+                        Err(failure) => {
+                            error = Some(match error {
+                                Some(error) => error.merge(failure),
+                                None => failure,
+                            })
+                        }
+                    }
+                )*
+
+                Err(error.expect("BUG: alt always has at least one alternative"))
+            }
+        }
+    };
+}
+
+tuple_impls!(A a);
+tuple_impls!(A a, B b);
+tuple_impls!(A a, B b, C c);
+tuple_impls!(A a, B b, C c, D d);
+tuple_impls!(A a, B b, C c, D d, E e);
+tuple_impls!(A a, B b, C c, D d, E e, F f);
+tuple_impls!(A a, B b, C c, D d, E e, F f, G g);
+tuple_impls!(A a, B b, C c, D d, E e, F f, G g, H h);
+
+/// Run each parser in a tuple in order, producing a tuple of their outputs.
+pub fn seq<'a, S>(parsers: S) -> impl Parser<'a, Output = S::Output>
+where
+    S: Seq<'a>,
+{
+    move |input: Input<'a>| -> ParseResult<'a, S::Output> { parsers.parse_seq(input) }
+}
+
+/// Try each parser in a tuple in order. If they all fail, the error expects anything that any of
+/// them expected.
+pub fn alt<'a, A>(parsers: A) -> impl Parser<'a, Output = A::Output>
+where
+    A: Alt<'a>,
+{
+    move |input: Input<'a>| -> ParseResult<'a, A::Output> { parsers.parse_alt(input) }
+}
+
+/// Whether `error` happened at the start of `input`, which means that the parser that failed
+/// didn't consume anything.
+pub(crate) fn is_clean(error: &ParseError, input: &Input) -> bool {
+    let (failed_at, start): (usize, usize) = (error.span().start().into(), input.position().into());
+    failed_at <= start
+}
+
+/// Zero or more repetitions of `parser`.
+pub fn many<'a, P>(parser: P) -> impl Parser<'a, Output = Vec<P::Output>>
+where
+    P: Parser<'a>,
+{
+    move |mut input: Input<'a>| -> ParseResult<'a, Vec<P::Output>> {
+        let mut outputs = vec![];
+
+        loop {
+            match parser.parse(input.clone()) {
+                // A parser that succeeds without consuming anything would repeat forever.
+                Ok((_, rest)) if rest.position() == input.position() && !input.is_at_end() => {
+                    return Ok((outputs, input));
+                }
+                Ok((output, rest)) => {
+                    outputs.push(output);
+
+                    if rest.is_at_end() {
+                        return Ok((outputs, rest));
+                    }
+
+                    input = rest;
+                }
+                Err(error) if is_clean(&error, &input) => return Ok((outputs, input)),
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+/// Zero or more `item`s separated by `separator`, optionally followed by a trailing separator.
+pub fn sep_by<'a, P, S>(item: P, separator: S) -> impl Parser<'a, Output = Vec<P::Output>>
+where
+    P: Parser<'a>,
+    S: Parser<'a>,
+{
+    move |input: Input<'a>| -> ParseResult<'a, Vec<P::Output>> {
+        let mut outputs = vec![];
+
+        let mut input = match item.parse(input.clone()) {
+            Ok((output, rest)) => {
+                outputs.push(output);
+                rest
+            }
+            Err(error) if is_clean(&error, &input) => return Ok((outputs, input)),
+            Err(error) => return Err(error),
+        };
+
+        loop {
+            let after_separator = match separator.parse(input.clone()) {
+                Ok((_, rest)) => rest,
+                Err(error) if is_clean(&error, &input) => return Ok((outputs, input)),
+                Err(error) => return Err(error),
+            };
+
+            input = match item.parse(after_separator.clone()) {
+                Ok((output, rest)) => {
+                    outputs.push(output);
+                    rest
+                }
+                Err(error) if is_clean(&error, &after_separator) => {
+                    return Ok((outputs, after_separator))
+                }
+                Err(error) => return Err(error),
+            };
+        }
+    }
+}
+
+/// Like [sep_by], but an item (or separator) that fails to parse is reported and skipped, up to
+/// the next separator. Since it keeps going until the end of the input, it's meant for the
+/// children of a [delimited](crate::delimited) token, where recovery can't escape the delimiters.
+pub fn sep_by_recovering<'a, P, S>(
+    item: P,
+    separator: S,
+) -> impl Parser<'a, Output = Vec<P::Output>>
+where
+    P: Parser<'a>,
+    S: Parser<'a>,
+{
+    move |mut input: Input<'a>| -> ParseResult<'a, Vec<P::Output>> {
+        let mut outputs = vec![];
+
+        loop {
+            if input.is_at_end() {
+                return Ok((outputs, input));
+            }
+
+            let before = input.furthest_failure();
+
+            input = match item.parse(input.clone()) {
+                Ok((output, rest)) => {
+                    outputs.push(output);
+                    rest
+                }
+                Err(error) => {
+                    report_furthest(&input, error);
+                    let rest = skip_to(&separator, input);
+                    rest.set_furthest_failure(before);
+                    rest
+                }
+            };
+
+            if input.is_at_end() {
+                return Ok((outputs, input));
+            }
+
+            let before = input.furthest_failure();
+
+            input = match separator.parse(input.clone()) {
+                Ok((_, rest)) => rest,
+                Err(error) => {
+                    let error = error.merge(input.fail(input.end_description()));
+                    report_furthest(&input, error);
+
+                    let (_, rest) = input.next().expect("BUG: the input isn't at the end");
+                    let rest = skip_to(&separator, rest);
+                    let rest = match separator.parse(rest.clone()) {
+                        Ok((_, after)) => after,
+                        Err(_) => rest,
+                    };
+
+                    rest.set_furthest_failure(before);
+                    rest
+                }
+            };
+        }
+    }
+}
+
+/// The input at the next place where `separator` matches (or at the end).
+fn skip_to<'a, S>(separator: &S, mut input: Input<'a>) -> Input<'a>
+where
+    S: Parser<'a>,
+{
+    loop {
+        if separator.parse(input.clone()).is_ok() {
+            return input;
+        }
+
+        match input.next() {
+            Some((_, rest)) => input = rest,
+            None => return input,
+        }
+    }
+}
+
+/// `parser`, or nothing.
+pub fn opt<'a, P>(parser: P) -> impl Parser<'a, Output = Option<P::Output>>
+where
+    P: Parser<'a>,
+{
+    move |input: Input<'a>| -> ParseResult<'a, Option<P::Output>> {
+        match parser.parse(input.clone()) {
+            Ok((output, rest)) => Ok((Some(output), rest)),
+            Err(error) if is_clean(&error, &input) => Ok((None, input)),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+/// `parser`, but if it fails without consuming anything, the error expects `label` (such as "an
+/// expression") rather than everything that `parser` tried.
+pub fn label<'a, P>(parser: P, label: &'static str) -> impl Parser<'a, Output = P::Output>
+where
+    P: Parser<'a>,
+{
+    move |input: Input<'a>| -> ParseResult<'a, P::Output> {
+        let before = input.furthest_failure();
+
+        match parser.parse(input.clone()) {
+            Err(error) if is_clean(&error, &input) => {
+                input.set_furthest_failure(before);
+                Err(input.fail(label))
+            }
+            result => result,
+        }
+    }
+}
+
+/// Transform the output of `parser`.
+pub fn map<'a, P, F, T>(parser: P, mapper: F) -> impl Parser<'a, Output = T>
+where
+    P: Parser<'a>,
+    F: Fn(P::Output) -> T,
+{
+    move |input: Input<'a>| -> ParseResult<'a, T> {
+        let (output, rest) = parser.parse(input)?;
+        Ok((mapper(output), rest))
+    }
+}
+
+/// The output of `parser`, together with the span of everything that it consumed.
+pub fn spanned<'a, P>(parser: P) -> impl Parser<'a, Output = Spanned<P::Output>>
+where
+    P: Parser<'a>,
+    P::Output: WystData,
+{
+    move |input: Input<'a>| -> ParseResult<'a, Spanned<P::Output>> {
+        let start = input.position();
+        let (output, rest) = parser.parse(input)?;
+        let span = rest.span_from(start);
+
+        Ok((output.spanned(span), rest))
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_core::wyst_data;
+        use wyst_lex::Delimiter;
+        use wyst_source::{Diagnostic, Source, Span};
+
+        use crate::{
+            parser::{delimited, keyword, parse, punct, word},
+            testing::read,
+        };
+
+        #[wyst_data]
+        enum Expr {
+            Name(String),
+            Call(String, Vec<Spanned<Expr>>),
+        }
+
+        fn expr<'a>(input: Input<'a>) -> ParseResult<'a, Spanned<Expr>> {
+            spanned(alt((
+                map(
+                    seq((
+                        word(),
+                        delimited(Delimiter::Paren, sep_by(expr, punct(','))),
+                    )),
+                    |(name, args)| Expr::Call(name.item().to_string(), args),
+                ),
+                map(word(), |name| Expr::Name(name.item().to_string())),
+            )))
+            .parse(input)
+        }
+    }),
+    tests(
+        ("seq, alt, sep_by and spanned", {
+            let source = Source::new("<test>", "f(a, g(b,), c)");
+            let call = parse(expr, &read(&source), &source).unwrap();
+
+            assert_eq!(call.span(), Span::new(0, 14));
+
+            let args = match call.item() {
+                Expr::Call(name, args) => {
+                    assert_eq!(name, "f");
+                    args
+                }
+                other => panic!("expected a call, got {:?}", other),
+            };
+
+            assert_eq!(
+                args.iter().map(|arg| arg.span()).collect::<Vec<_>>(),
+                &[Span::new(2, 3), Span::new(5, 10), Span::new(12, 13)]
+            );
+            assert_eq!(
+                args[1].item(),
+                &Expr::Call(
+                    "g".to_string(),
+                    vec![Expr::Name("b".to_string()).spanned(Span::new(7, 8))]
+                )
+            );
+        }),
+        ("expected one of", {
+            let source = Source::new("<test>", "f(a b)");
+            assert_eq!(
+                parse(expr, &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(4, 5),
+                    "expected one of `(`, `)` or `,`, found `b`"
+                ))
+            );
+
+            let source = Source::new("<test>", "f(a, ;)");
+            assert_eq!(
+                parse(expr, &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(5, 6),
+                    "expected one of `)` or a word, found `;`"
+                ))
+            );
+        }),
+        ("many and opt", {
+            let statement = || seq((opt(keyword("let")), word(), punct(';')));
+            let source = Source::new("<test>", "let a; b;");
+            let tokens = read(&source);
+            let statements = parse(many(statement()), &tokens, &source).unwrap();
+
+            assert_eq!(
+                statements
+                    .iter()
+                    .map(|(kw, name, _)| (kw.is_some(), *name.item()))
+                    .collect::<Vec<_>>(),
+                &[(true, "a"), (false, "b")]
+            );
+
+            // `b` starts a statement, so the missing `;` is an error rather than the end of the
+            // repetition.
+            let source = Source::new("<test>", "let a; b c;");
+            let tokens = read(&source);
+            assert_eq!(
+                parse(many(statement()), &tokens, &source),
+                Err(Diagnostic::error(
+                    Span::new(9, 10),
+                    "expected `;`, found `c`"
+                ))
+            );
+
+            let source = Source::new("<test>", "");
+            assert_eq!(
+                parse(many(statement()), &read(&source), &source),
+                Ok(vec![])
+            );
+        })
+    )
+);
@@ -0,0 +1,102 @@
+use wyst_core::{unit_tests, wyst_data};
+use wyst_source::{Diagnostic, Span};
+
+/// A parser failed at `span`, where it expected one of `expected`.
+#[wyst_data]
+pub struct ParseError {
+    span: Span,
+    expected: Vec<String>,
+    found: String,
+}
+
+impl ParseError {
+    pub fn new(span: Span, expected: impl Into<String>, found: impl Into<String>) -> ParseError {
+        ParseError {
+            span,
+            expected: vec![expected.into()],
+            found: found.into(),
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Descriptions of what the parser expected (such as "`,`" or "a word"), sorted and without
+    /// duplicates.
+    pub fn expected(&self) -> &[String] {
+        &self.expected
+    }
+
+    /// A description of what the parser found instead.
+    pub fn found(&self) -> &str {
+        &self.found
+    }
+
+    /// Combine two failures. The one that got further wins, and if they failed at the same place,
+    /// the result expects everything that either of them expected.
+    pub fn merge(self, other: ParseError) -> ParseError {
+        let (start, other_start): (usize, usize) =
+            (self.span.start().into(), other.span.start().into());
+
+        if other_start > start {
+            return other;
+        } else if start > other_start {
+            return self;
+        }
+
+        let mut expected = self.expected;
+        expected.extend(other.expected);
+        expected.sort();
+        expected.dedup();
+
+        ParseError {
+            span: self.span,
+            expected,
+            found: self.found,
+        }
+    }
+
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        let message = match self.expected.as_slice() {
+            [] => format!("unexpected {}", self.found),
+            [expected] => format!("expected {}, found {}", expected, self.found),
+            [init @ .., last] => format!(
+                "expected one of {} or {}, found {}",
+                init.join(", "),
+                last,
+                self.found
+            ),
+        };
+
+        Diagnostic::error(self.span, message)
+    }
+}
+
+unit_tests!(tests(
+    ("merge", {
+        let comma = ParseError::new(Span::new(2, 3), "`,`", "`x`");
+        let close = ParseError::new(Span::new(2, 3), "`)`", "`x`");
+        let word = ParseError::new(Span::new(4, 5), "a word", "`)`");
+
+        let merged = comma.clone().merge(close.clone()).merge(comma.clone());
+        assert_eq!(merged.expected(), &["`)`".to_string(), "`,`".to_string()]);
+        assert_eq!(comma.clone().merge(word.clone()), word);
+        assert_eq!(word.clone().merge(comma), word);
+    }),
+    ("to_diagnostic", {
+        let error = ParseError::new(Span::new(2, 3), "`,`", "`x`");
+        assert_eq!(
+            error.to_diagnostic(),
+            Diagnostic::error(Span::new(2, 3), "expected `,`, found `x`")
+        );
+
+        let error = error
+            .merge(ParseError::new(Span::new(2, 3), "`)`", "`x`"))
+            .merge(ParseError::new(Span::new(2, 3), "`;`", "`x`"));
+        assert_eq!(
+            error.to_diagnostic().message(),
+            "expected one of `)`, `,` or `;`, found `x`"
+        );
+    })
+));
@@ -0,0 +1,364 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wyst_core::unit_tests;
+use wyst_lex::{Delimited, Leaf, LeafKind, Token};
+use wyst_source::{Diagnostic, Offset, Source, Span, Spanned};
+
+use crate::error::ParseError;
+
+/// The next piece of an [Input].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Next<'a> {
+    /// A run of letters, digits and underscores in a word.
+    Word(Span, &'a str),
+    /// Any other character in a word.
+    Punct(Span, char),
+    /// A quoted string, including its quotes.
+    Quoted(Span, &'a str),
+    Delimited(Span, &'a Delimited),
+    /// Whitespace, newlines and comments, which are only returned by an input that doesn't skip
+    /// trivia.
+    Trivia(Span, Leaf),
+    /// Any other leaf (such as an error token).
+    Other(Span, Leaf),
+}
+
+impl<'a> Next<'a> {
+    pub fn span(&self) -> Span {
+        match *self {
+            Next::Word(span, _)
+            | Next::Punct(span, _)
+            | Next::Quoted(span, _)
+            | Next::Delimited(span, _)
+            | Next::Trivia(span, _)
+            | Next::Other(span, _) => span,
+        }
+    }
+
+    /// A description of the piece for error messages.
+    fn describe(&self, source: &Source) -> String {
+        match *self {
+            Next::Word(_, text) | Next::Quoted(_, text) => format!("`{}`", text),
+            Next::Delimited(_, delimited) => format!("`{}`", delimited.delimiter().open_char()),
+            Next::Punct(_, c) => format!("`{}`", c),
+            Next::Trivia(_, Leaf::Newline) => "a newline".to_string(),
+            Next::Trivia(_, Leaf::Comment(_)) => "a comment".to_string(),
+            Next::Trivia(..) => "whitespace".to_string(),
+            Next::Other(span, _) => format!("`{}`", source.slice(span)),
+        }
+    }
+}
+
+/// A cursor over a list of sibling tokens. Inputs are cheap to clone, and parsers return the
+/// input after whatever they consumed, so backtracking is a matter of holding on to an earlier
+/// input.
+#[derive(Debug, Clone)]
+pub struct Input<'a> {
+    tokens: &'a [Spanned<Token>],
+    source: &'a Source,
+    index: usize,
+    /// The offset (in bytes) into the word at `index`.
+    offset: usize,
+    /// Where the input ends: the closing delimiter of a delimited token, or an empty span after
+    /// the last token.
+    end: Span,
+    /// The end of the last piece that was consumed.
+    consumed: Offset,
+    trivia: bool,
+    state: Rc<RefCell<State>>,
+}
+
+/// State that every input derived from the same [Input::new] shares.
+#[derive(Debug, Default)]
+struct State {
+    furthest: Option<ParseError>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(tokens: &'a [Spanned<Token>], source: &'a Source) -> Input<'a> {
+        let end = tokens
+            .last()
+            .map(|token| token.span().end())
+            .unwrap_or(Offset::from(0));
+
+        Input {
+            tokens,
+            source,
+            index: 0,
+            offset: 0,
+            end: Span::eof(end),
+            consumed: Offset::from(0),
+            trivia: false,
+            state: Rc::new(RefCell::new(State::default())),
+        }
+    }
+
+    /// An input over the children of a delimited token, which ends at the closing delimiter.
+    pub(crate) fn children(&self, span: Span, delimited: &'a Delimited) -> Input<'a> {
+        let close = delimited.delimiter().close_char();
+        let end = if delimited.is_closed() {
+            (span.end() - close).char_span(close)
+        } else {
+            Span::eof(span.end())
+        };
+
+        Input {
+            tokens: delimited.children(),
+            index: 0,
+            offset: 0,
+            end,
+            consumed: span.start() + delimited.delimiter().open_char(),
+            ..self.clone()
+        }
+    }
+
+    pub fn source(&self) -> &'a Source {
+        self.source
+    }
+
+    /// Whether trivia is returned by [Input::next] rather than skipped.
+    pub fn with_trivia(self, trivia: bool) -> Input<'a> {
+        Input { trivia, ..self }
+    }
+
+    pub fn is_trivia_sensitive(&self) -> bool {
+        self.trivia
+    }
+
+    /// The next piece of the input, and the input after it.
+    pub fn next(&self) -> Option<(Next<'a>, Input<'a>)> {
+        let mut index = self.index;
+        let offset = self.offset;
+
+        loop {
+            let token = self.tokens.get(index)?;
+            let span = token.span();
+
+            // The offset into the current word to continue from, if there's more to it.
+            let (next, rest) = match token.item() {
+                Token::Leaf(Leaf::EOF) => return None,
+                Token::Leaf(leaf) if leaf.is_trivia() => {
+                    if !self.trivia {
+                        index += 1;
+                        continue;
+                    }
+
+                    (Next::Trivia(span, *leaf), None)
+                }
+                Token::Leaf(Leaf::Word) => {
+                    let start: usize = span.start().into();
+                    let word = self.source.slice(span);
+                    let text = &word[offset..];
+                    let first = text.chars().next().expect("BUG: words aren't empty");
+
+                    let len = if is_word_char(first) {
+                        text.find(|c| !is_word_char(c)).unwrap_or(text.len())
+                    } else {
+                        first.len_utf8()
+                    };
+
+                    let piece = Span::new(start + offset, start + offset + len);
+
+                    let next = if is_word_char(first) {
+                        Next::Word(piece, &text[..len])
+                    } else {
+                        Next::Punct(piece, first)
+                    };
+
+                    let rest = if offset + len < word.len() {
+                        Some(offset + len)
+                    } else {
+                        None
+                    };
+
+                    (next, rest)
+                }
+                Token::Leaf(Leaf::Quoted(_)) => (Next::Quoted(span, self.source.slice(span)), None),
+                Token::Leaf(leaf) => (Next::Other(span, *leaf), None),
+                Token::Delimited(delimited) => (Next::Delimited(span, delimited), None),
+            };
+
+            let rest = match rest {
+                Some(offset) => Input {
+                    index,
+                    offset,
+                    consumed: next.span().end(),
+                    ..self.clone()
+                },
+                None => Input {
+                    index: index + 1,
+                    offset: 0,
+                    consumed: next.span().end(),
+                    ..self.clone()
+                },
+            };
+
+            return Some((next, rest));
+        }
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.next().is_none()
+    }
+
+    /// Where the next piece starts (or where the input ends).
+    pub fn position(&self) -> Offset {
+        match self.next() {
+            Some((next, _)) => next.span().start(),
+            None => self.end.start(),
+        }
+    }
+
+    /// The end of the last piece that was consumed.
+    pub fn consumed(&self) -> Offset {
+        self.consumed
+    }
+
+    /// The span from `start` to the end of the last piece that was consumed (or an empty span at
+    /// `start`, if nothing was consumed since then).
+    pub fn span_from(&self, start: Offset) -> Span {
+        let (start_offset, end): (usize, usize) = (start.into(), self.consumed.into());
+
+        if end > start_offset {
+            Span::new(start, self.consumed)
+        } else {
+            Span::eof(start)
+        }
+    }
+
+    /// A failure at the next piece, which expected `expected`. The failure is also recorded, so
+    /// that [Input::furthest_failure] can report everything that was expected at the furthest
+    /// point that any parser reached.
+    pub fn fail(&self, expected: impl Into<String>) -> ParseError {
+        let (span, found) = match self.next() {
+            Some((Next::Delimited(span, delimited), _)) => (
+                span.start().char_span(delimited.delimiter().open_char()),
+                format!("`{}`", delimited.delimiter().open_char()),
+            ),
+            Some((next, _)) => (next.span(), next.describe(self.source)),
+            None => (self.end, self.end_description()),
+        };
+
+        let error = ParseError::new(span, expected, found);
+        self.record(error.clone());
+        error
+    }
+
+    pub(crate) fn record(&self, error: ParseError) {
+        let furthest = &mut self.state.borrow_mut().furthest;
+
+        *furthest = Some(match furthest.take() {
+            Some(previous) => previous.merge(error),
+            None => error,
+        });
+    }
+
+    /// The failure that got the furthest, across every parser that has run on this input (or any
+    /// input derived from it).
+    pub fn furthest_failure(&self) -> Option<ParseError> {
+        self.state.borrow().furthest.clone()
+    }
+
+    /// Replace the furthest failure, such as when a labelled parser replaces its failures with its
+    /// label.
+    pub(crate) fn set_furthest_failure(&self, failure: Option<ParseError>) {
+        self.state.borrow_mut().furthest = failure;
+    }
+
+    /// Report an error that a parser recovered from.
+    pub fn report(&self, diagnostic: Diagnostic) {
+        self.state.borrow_mut().diagnostics.push(diagnostic);
+    }
+
+    /// The errors that parsers recovered from, in the order that they were reported.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.state.borrow().diagnostics.clone()
+    }
+
+    /// A description of the end of the input (such as "`)`").
+    pub(crate) fn end_description(&self) -> String {
+        if self.end.start() == self.end.end() {
+            "the end of the input".to_string()
+        } else {
+            format!("`{}`", self.source.slice(self.end))
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, FlatToken};
+
+        fn pieces(input: Input) -> Vec<String> {
+            let mut pieces = vec![];
+            let mut input = input;
+
+            while let Some((next, rest)) = input.next() {
+                pieces.push(match next {
+                    Next::Delimited(_, _) => "(...)".to_string(),
+                    Next::Trivia(_, Leaf::Newline) => "\\n".to_string(),
+                    next => input.source().slice(next.span()).to_string(),
+                });
+
+                input = rest;
+            }
+
+            pieces
+        }
+    }),
+    tests(
+        ("pieces", {
+            let source = Source::new("<test>", "foo.bar_1+=  \"xy\" (a, b)\n");
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+            let input = Input::new(&tokens, &source);
+
+            assert_eq!(
+                pieces(input.clone()),
+                &["foo", ".", "bar_1", "+", "=", "\"xy\"", "(...)"]
+            );
+            assert_eq!(
+                pieces(input.with_trivia(true)),
+                &["foo", ".", "bar_1", "+", "=", "  ", "\"xy\"", " ", "(...)", "\\n"]
+            );
+        }),
+        ("children", {
+            let source = Source::new("<test>", "(a, b) [c");
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+            let input = Input::new(&tokens, &source);
+
+            let (paren, rest) = input.next().unwrap();
+            let children = match paren {
+                Next::Delimited(span, delimited) => input.children(span, delimited),
+                other => panic!("expected a delimited token, got {:?}", other),
+            };
+
+            assert_eq!(pieces(children.clone()), &["a", ",", "b"]);
+            assert_eq!(children.position(), Offset::from(1));
+            assert_eq!(children.end_description(), "`)`");
+
+            let unclosed = match rest.next().unwrap().0 {
+                Next::Delimited(span, delimited) => rest.children(span, delimited),
+                other => panic!("expected a delimited token, got {:?}", other),
+            };
+
+            assert_eq!(unclosed.end_description(), "the end of the input");
+
+            // The inner `)` closes the inner parentheses, not the outer ones.
+            let source = Source::new("<test>", "((a)");
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+            let input = Input::new(&tokens, &source);
+
+            let outer = match input.next().unwrap().0 {
+                Next::Delimited(span, delimited) => input.children(span, delimited),
+                other => panic!("expected a delimited token, got {:?}", other),
+            };
+
+            assert_eq!(outer.end_description(), "the end of the input");
+        })
+    )
+);
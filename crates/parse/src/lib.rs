@@ -1,7 +1,37 @@
+//! Parser combinators over token trees.
+//!
+//! The input to a parser is an [Input], which is a cursor over a list of sibling tokens (the
+//! output of `wyst-lex`). Words are split into smaller pieces (runs of letters, digits and
+//! underscores, and single punctuation characters), trivia is skipped, and [delimited] runs a
+//! parser over the children of a delimited token.
+//!
+//! Rules can recover from syntax errors with [recover], which skips to a synchronization token
+//! and produces an error node. Typed syntax trees can derive their parsers with
+//! `#[derive(Parse)]` (see [node]).
+
+extern crate self as wyst_parse;
+
+mod combinators;
+mod error;
+mod input;
+pub mod node;
+mod parser;
+mod pratt;
+mod recovery;
 #[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
-    }
-}
+mod testing;
+
+pub use combinators::{
+    alt, label, many, map, opt, sep_by, sep_by_recovering, seq, spanned, Alt, Seq,
+};
+pub use error::ParseError;
+pub use input::{Input, Next};
+pub use node::{HasSpan, Parse, Quoted, Word};
+pub use parser::{
+    delimited, end, keyword, parse, parse_recovering, punct, quoted, symbol, word, ParseResult,
+    Parsed, Parser,
+};
+pub use pratt::{Assoc, Pratt};
+pub use recovery::{recover, Recovery};
+pub use wyst_core::Parse;
+pub use wyst_lex;
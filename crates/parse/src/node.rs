@@ -0,0 +1,300 @@
+//! Typed syntax tree nodes.
+//!
+//! A node implements [Parse], which is usually derived with `#[derive(Parse)]`, and [HasSpan].
+//! [Word] and [Quoted] are the leaves; `Box`, `Option` and `Vec` of a node are nodes too.
+//!
+//! A derived parser only recovers from syntax errors in a `#[wyst(sep = "..")]` field inside of a
+//! `#[wyst(delimited = "..")]` token (with [sep_by_recovering](crate::sep_by_recovering)). An
+//! error anywhere else fails the whole node, so a node that needs to recover elsewhere implements
+//! [Parse] by hand with [recover](crate::recover).
+
+use wyst_core::{unit_tests, wyst_data, WystData};
+use wyst_source::{Span, Spanned};
+
+use crate::{
+    combinators::{many, opt},
+    input::Input,
+    parser::{quoted, word, ParseResult, Parser},
+};
+
+/// A node that can be parsed from an [Input].
+pub trait Parse<'a>: Sized {
+    fn parse(input: Input<'a>) -> ParseResult<'a, Self>;
+}
+
+/// A node that knows where it came from.
+pub trait HasSpan {
+    fn span(&self) -> Span;
+}
+
+impl HasSpan for Span {
+    fn span(&self) -> Span {
+        *self
+    }
+}
+
+impl<T: WystData> HasSpan for Spanned<T> {
+    fn span(&self) -> Span {
+        Spanned::span(self)
+    }
+}
+
+impl<T: HasSpan> HasSpan for Box<T> {
+    fn span(&self) -> Span {
+        (**self).span()
+    }
+}
+
+impl<'a, T: Parse<'a>> Parse<'a> for Box<T> {
+    fn parse(input: Input<'a>) -> ParseResult<'a, Self> {
+        let (node, rest) = T::parse(input)?;
+        Ok((Box::new(node), rest))
+    }
+}
+
+impl<'a, T: Parse<'a>> Parse<'a> for Option<T> {
+    fn parse(input: Input<'a>) -> ParseResult<'a, Self> {
+        opt(T::parse).parse(input)
+    }
+}
+
+impl<'a, T: Parse<'a>> Parse<'a> for Vec<T> {
+    fn parse(input: Input<'a>) -> ParseResult<'a, Self> {
+        many(T::parse).parse(input)
+    }
+}
+
+/// A run of letters, digits and underscores.
+#[wyst_data]
+pub struct Word {
+    pub text: String,
+    pub span: Span,
+}
+
+impl<'a> Parse<'a> for Word {
+    fn parse(input: Input<'a>) -> ParseResult<'a, Self> {
+        let (word, rest) = word().parse(input)?;
+
+        Ok((
+            Word {
+                text: word.item().to_string(),
+                span: word.span(),
+            },
+            rest,
+        ))
+    }
+}
+
+impl HasSpan for Word {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// A quoted string, including its quotes.
+#[wyst_data]
+pub struct Quoted {
+    pub text: String,
+    pub span: Span,
+}
+
+impl<'a> Parse<'a> for Quoted {
+    fn parse(input: Input<'a>) -> ParseResult<'a, Self> {
+        let (quoted, rest) = quoted().parse(input)?;
+
+        Ok((
+            Quoted {
+                text: quoted.item().to_string(),
+                span: quoted.span(),
+            },
+            rest,
+        ))
+    }
+}
+
+impl HasSpan for Quoted {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_core::Parse;
+        use wyst_source::{Diagnostic, Source};
+
+        use crate::{
+            parser::{parse, parse_recovering},
+            testing::read,
+        };
+
+        #[wyst_data]
+        #[derive(Parse)]
+        struct Function {
+            #[wyst(token = "fn")]
+            keyword: Span,
+            name: Word,
+            #[wyst(delimited = "paren", sep = ",")]
+            params: Vec<Param>,
+            body: Option<Block>,
+            span: Span,
+        }
+
+        #[wyst_data]
+        #[derive(Parse)]
+        struct Param {
+            name: Word,
+            #[wyst(token = ":")]
+            colon: Span,
+            ty: Type,
+            span: Span,
+        }
+
+        #[wyst_data]
+        #[derive(Parse)]
+        enum Type {
+            Array {
+                #[wyst(delimited = "bracket")]
+                item: Box<Type>,
+                span: Span,
+            },
+            Named(Word),
+        }
+
+        /// Fields with the same names as the locals of the derived parser.
+        #[wyst_data]
+        #[derive(Parse)]
+        enum Setting {
+            Assign {
+                input: Word,
+                #[wyst(token = "=")]
+                error: Span,
+                start: Word,
+                span: Span,
+            },
+            Range {
+                start: Word,
+                #[wyst(token = "..")]
+                dots: Span,
+                end: Word,
+                span: Span,
+            },
+        }
+
+        #[wyst_data]
+        #[derive(Parse)]
+        struct Block {
+            #[wyst(delimited = "brace")]
+            statements: Vec<Word>,
+            span: Span,
+        }
+    }),
+    tests(
+        ("derive(Parse)", {
+            let source = Source::new("<test>", "fn f(a: int, b: [str]) { x y }");
+            let tokens = read(&source);
+            let function = parse(Function::parse, &tokens, &source).unwrap();
+
+            assert_eq!(function.span(), Span::new(0, 30));
+            assert_eq!(function.keyword, Span::new(0, 2));
+            assert_eq!(function.name.text, "f");
+            assert_eq!(
+                function
+                    .params
+                    .iter()
+                    .map(|param| (param.name.text.as_str(), param.span()))
+                    .collect::<Vec<_>>(),
+                &[("a", Span::new(5, 11)), ("b", Span::new(13, 21))]
+            );
+
+            match &function.params[1].ty {
+                Type::Array { item, span } => {
+                    assert_eq!(*span, Span::new(16, 21));
+                    assert_eq!(item.span(), Span::new(17, 20));
+                }
+                other => panic!("expected an array type, got {:?}", other),
+            }
+
+            let body = function.body.expect("the function has a body");
+            assert_eq!(body.span(), Span::new(23, 30));
+            assert_eq!(body.statements.len(), 2);
+
+            let source = Source::new("<test>", "fn f(a: int)");
+            let tokens = read(&source);
+            let function = parse(Function::parse, &tokens, &source).unwrap();
+
+            assert_eq!(function.span(), Span::new(0, 12));
+            assert_eq!(function.body, None);
+        }),
+        ("derive(Parse) with fields named like locals", {
+            let source = Source::new("<test>", "a..b");
+            let tokens = read(&source);
+            let setting = parse(Setting::parse, &tokens, &source).unwrap();
+
+            match setting {
+                Setting::Range {
+                    start, end, span, ..
+                } => {
+                    assert_eq!((start.text.as_str(), end.text.as_str()), ("a", "b"));
+                    assert_eq!(span, Span::new(0, 4));
+                }
+                other => panic!("expected a range, got {:?}", other),
+            }
+
+            let source = Source::new("<test>", "x = y");
+            let tokens = read(&source);
+            let setting = parse(Setting::parse, &tokens, &source).unwrap();
+
+            match setting {
+                Setting::Assign {
+                    input,
+                    error,
+                    start,
+                    span,
+                } => {
+                    assert_eq!((input.text.as_str(), start.text.as_str()), ("x", "y"));
+                    assert_eq!(error, Span::new(2, 3));
+                    assert_eq!(span, Span::new(0, 5));
+                }
+                other => panic!("expected an assignment, got {:?}", other),
+            }
+        }),
+        ("recovering inside of a delimited list", {
+            let source = Source::new("<test>", "fn f(a int, b: [str], c: ) {}");
+            let tokens = read(&source);
+            let parsed = parse_recovering(Function::parse, &tokens, &source);
+
+            assert_eq!(
+                parsed.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(7, 10), "expected `:`, found `int`"),
+                    Diagnostic::error(
+                        Span::new(25, 26),
+                        "expected one of `[` or a word, found `)`"
+                    ),
+                ]
+            );
+
+            let function = parsed.into_output().unwrap();
+            assert_eq!(
+                function
+                    .params
+                    .iter()
+                    .map(|param| param.name.text.as_str())
+                    .collect::<Vec<_>>(),
+                &["b"]
+            );
+
+            // Recovery doesn't escape the delimiters, so errors after them still fail the parse.
+            let source = Source::new("<test>", "fn f(a: x) {} g");
+            let tokens = read(&source);
+            assert_eq!(
+                parse(Function::parse, &tokens, &source),
+                Err(Diagnostic::error(
+                    Span::new(14, 15),
+                    "expected the end of the input, found `g`"
+                ))
+            );
+        })
+    )
+);
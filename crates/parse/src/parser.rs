@@ -0,0 +1,300 @@
+use wyst_core::unit_tests;
+use wyst_lex::{Delimiter, Token};
+use wyst_source::{AddSpan, Diagnostic, Source, Span, Spanned};
+
+use crate::input::{Input, Next};
+
+/// The output of a successful parse, and the input after it.
+pub type ParseResult<'a, T> = Result<(T, Input<'a>), crate::ParseError>;
+
+/// A parser is anything that turns an input into an output and the rest of the input. Any
+/// `Fn(Input<'a>) -> ParseResult<'a, T>` is a parser.
+pub trait Parser<'a> {
+    type Output;
+
+    fn parse(&self, input: Input<'a>) -> ParseResult<'a, Self::Output>;
+}
+
+impl<'a, T, F> Parser<'a> for F
+where
+    F: Fn(Input<'a>) -> ParseResult<'a, T>,
+{
+    type Output = T;
+
+    fn parse(&self, input: Input<'a>) -> ParseResult<'a, T> {
+        self(input)
+    }
+}
+
+/// The output of [parse_recovering].
+#[derive(Debug)]
+pub struct Parsed<T> {
+    output: Option<T>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<T> Parsed<T> {
+    /// The output, unless the parser failed without recovering.
+    pub fn output(&self) -> Option<&T> {
+        self.output.as_ref()
+    }
+
+    pub fn into_output(self) -> Option<T> {
+        self.output
+    }
+
+    /// The errors that the parser recovered from, followed by the error that it failed with (if
+    /// it failed).
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+/// Run `parser` over `tokens`, which it has to consume completely. If it fails, the diagnostic
+/// describes the furthest point that any parser reached. If it recovered from an error, the
+/// diagnostic is the first error that it recovered from.
+pub fn parse<'a, P>(
+    parser: P,
+    tokens: &'a [Spanned<Token>],
+    source: &'a Source,
+) -> Result<P::Output, Diagnostic>
+where
+    P: Parser<'a>,
+{
+    let parsed = parse_recovering(parser, tokens, source);
+
+    match parsed.diagnostics.into_iter().next() {
+        Some(diagnostic) => Err(diagnostic),
+        None => Ok(parsed
+            .output
+            .expect("BUG: a parse without diagnostics has an output")),
+    }
+}
+
+/// Run `parser` over `tokens`, keeping its output even if it had to recover from errors.
+pub fn parse_recovering<'a, P>(
+    parser: P,
+    tokens: &'a [Spanned<Token>],
+    source: &'a Source,
+) -> Parsed<P::Output>
+where
+    P: Parser<'a>,
+{
+    let input = Input::new(tokens, source);
+
+    let result = parser
+        .parse(input.clone())
+        .and_then(|(output, rest)| end().parse(rest).map(|(_, _)| output));
+
+    let mut diagnostics = input.diagnostics();
+
+    let output = match result {
+        Ok(output) => Some(output),
+        Err(error) => {
+            let failure = input.furthest_failure().unwrap_or(error);
+            diagnostics.push(failure.to_diagnostic());
+            None
+        }
+    };
+
+    Parsed {
+        output,
+        diagnostics,
+    }
+}
+
+/// A word that is exactly `text`, such as a keyword.
+pub fn keyword<'a>(text: &'static str) -> impl Parser<'a, Output = Span> {
+    move |input: Input<'a>| -> ParseResult<'a, Span> {
+        match input.next() {
+            Some((Next::Word(span, word), rest)) if word == text => Ok((span, rest)),
+            _ => Err(input.fail(format!("`{}`", text))),
+        }
+    }
+}
+
+/// A single punctuation character.
+pub fn punct<'a>(c: char) -> impl Parser<'a, Output = Span> {
+    move |input: Input<'a>| -> ParseResult<'a, Span> {
+        match input.next() {
+            Some((Next::Punct(span, found), rest)) if found == c => Ok((span, rest)),
+            _ => Err(input.fail(format!("`{}`", c))),
+        }
+    }
+}
+
+/// A run of adjacent pieces that spell `text`, such as an operator like `==` or `->`.
+pub fn symbol<'a>(text: &'static str) -> impl Parser<'a, Output = Span> {
+    move |input: Input<'a>| -> ParseResult<'a, Span> {
+        let start = input.position();
+        let mut matched = 0;
+        let mut rest = input.clone();
+
+        while matched < text.len() {
+            let piece = match rest.next() {
+                Some((Next::Word(span, word), next)) => Some((span, word.to_string(), next)),
+                Some((Next::Punct(span, c), next)) => Some((span, c.to_string(), next)),
+                _ => None,
+            };
+
+            match piece {
+                Some((span, piece, next))
+                    if (matched == 0 || span.start() == rest.consumed())
+                        && text[matched..].starts_with(&piece) =>
+                {
+                    matched += piece.len();
+                    rest = next;
+                }
+                _ => return Err(input.fail(format!("`{}`", text))),
+            }
+        }
+
+        Ok((rest.span_from(start), rest))
+    }
+}
+
+/// Any word (a run of letters, digits and underscores).
+pub fn word<'a>() -> impl Parser<'a, Output = Spanned<&'a str>> {
+    move |input: Input<'a>| -> ParseResult<'a, Spanned<&'a str>> {
+        match input.next() {
+            Some((Next::Word(span, word), rest)) => Ok((word.spanned(span), rest)),
+            _ => Err(input.fail("a word")),
+        }
+    }
+}
+
+/// A quoted string, including its quotes.
+pub fn quoted<'a>() -> impl Parser<'a, Output = Spanned<&'a str>> {
+    move |input: Input<'a>| -> ParseResult<'a, Spanned<&'a str>> {
+        match input.next() {
+            Some((Next::Quoted(span, text), rest)) => Ok((text.spanned(span), rest)),
+            _ => Err(input.fail("a quoted string")),
+        }
+    }
+}
+
+/// A delimited token whose children `parser` consumes completely.
+pub fn delimited<'a, P>(delimiter: Delimiter, parser: P) -> impl Parser<'a, Output = P::Output>
+where
+    P: Parser<'a>,
+{
+    move |input: Input<'a>| -> ParseResult<'a, P::Output> {
+        match input.next() {
+            Some((Next::Delimited(span, delimited), rest))
+                if delimited.delimiter() == delimiter =>
+            {
+                let children = input.children(span, delimited);
+                let (output, _) = parser.parse(children).and_then(|(output, inner)| {
+                    end().parse(inner).map(|(_, inner)| (output, inner))
+                })?;
+
+                Ok((output, rest))
+            }
+            _ => Err(input.fail(format!("`{}`", delimiter.open_char()))),
+        }
+    }
+}
+
+/// The end of the input (the end of the source, or the closing delimiter in [delimited]).
+pub fn end<'a>() -> impl Parser<'a, Output = ()> {
+    move |input: Input<'a>| -> ParseResult<'a, ()> {
+        if input.is_at_end() {
+            Ok(((), input))
+        } else {
+            Err(input.fail(input.end_description()))
+        }
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::testing::read;
+    }),
+    tests(
+        ("keyword and word", {
+            let source = Source::new("<test>", "fn foo");
+            assert_eq!(
+                parse(crate::seq((keyword("fn"), word())), &read(&source), &source),
+                Ok((Span::new(0, 2), "foo".spanned(Span::new(3, 6))))
+            );
+
+            let source = Source::new("<test>", "fun foo");
+            assert_eq!(
+                parse(crate::seq((keyword("fn"), word())), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(0, 3),
+                    "expected `fn`, found `fun`"
+                ))
+            );
+        }),
+        ("symbol", {
+            let source = Source::new("<test>", "a==b");
+            let tokens = read(&source);
+            let parser = || crate::seq((word(), symbol("=="), word()));
+            assert_eq!(
+                parse(parser(), &tokens, &source).map(|(_, op, _)| op),
+                Ok(Span::new(1, 3))
+            );
+
+            let source = Source::new("<test>", "a= =b");
+            assert_eq!(
+                parse(parser(), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(1, 2),
+                    "expected `==`, found `=`"
+                ))
+            );
+        }),
+        ("delimited", {
+            let source = Source::new("<test>", "(a b)");
+            assert_eq!(
+                parse(delimited(Delimiter::Paren, word()), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(3, 4),
+                    "expected `)`, found `b`"
+                ))
+            );
+
+            let source = Source::new("<test>", "()");
+            assert_eq!(
+                parse(delimited(Delimiter::Paren, word()), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(1, 2),
+                    "expected a word, found `)`"
+                ))
+            );
+
+            let source = Source::new("<test>", "[a]");
+            assert_eq!(
+                parse(delimited(Delimiter::Paren, word()), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(0, 1),
+                    "expected `(`, found `[`"
+                ))
+            );
+        }),
+        ("end", {
+            let source = Source::new("<test>", "a b");
+            assert_eq!(
+                parse(word(), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::new(2, 3),
+                    "expected the end of the input, found `b`"
+                ))
+            );
+
+            let source = Source::new("<test>", "");
+            assert_eq!(
+                parse(word(), &read(&source), &source),
+                Err(Diagnostic::error(
+                    Span::eof(0),
+                    "expected a word, found the end of the input"
+                ))
+            );
+        })
+    )
+);
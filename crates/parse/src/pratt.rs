@@ -0,0 +1,526 @@
+//! A Pratt (operator-precedence) expression parser.
+//!
+//! A [Pratt] parser is built from a parser for atoms (such as numbers and names) and a table of
+//! operators. Each operator has a precedence (higher binds tighter) and a callback that builds a
+//! node of the user's AST out of its operands and the spans of the operator and the whole node.
+
+use wyst_core::{unit_tests, wyst_copy};
+use wyst_lex::Delimiter;
+use wyst_source::{Offset, Span};
+
+use crate::{
+    combinators::{is_clean, label, sep_by},
+    input::{Input, Next},
+    parser::{end, punct, symbol, ParseResult, Parser},
+};
+
+#[wyst_copy]
+pub enum Assoc {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+}
+
+type Atom<'a, T> = Box<dyn Parser<'a, Output = T> + 'a>;
+type Prefix<'a, T> = Box<dyn Fn(Span, T, Span) -> T + 'a>;
+type Infix<'a, T> = Box<dyn Fn(T, Span, T, Span) -> T + 'a>;
+type Postfix<'a, T> = Box<dyn Fn(T, Span, Span) -> T + 'a>;
+type Ternary<'a, T> = Box<dyn Fn(T, T, T, Span) -> T + 'a>;
+type Call<'a, T> = Box<dyn Fn(T, Vec<T>, Span) -> T + 'a>;
+type Index<'a, T> = Box<dyn Fn(T, T, Span) -> T + 'a>;
+type Recover<'a, T> = Box<dyn Fn(Span) -> T + 'a>;
+
+/// An operator that follows its left operand.
+enum Trailing<'a, T> {
+    Infix(&'static str, Assoc, Infix<'a, T>),
+    Postfix(&'static str, Postfix<'a, T>),
+    Ternary(&'static str, &'static str, Ternary<'a, T>),
+    Call(Delimiter, Call<'a, T>),
+    Index(Delimiter, Index<'a, T>),
+}
+
+impl<'a, T> Trailing<'a, T> {
+    fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Trailing::Infix(op, ..) | Trailing::Postfix(op, ..) | Trailing::Ternary(op, ..) => {
+                Some(op)
+            }
+            Trailing::Call(..) | Trailing::Index(..) => None,
+        }
+    }
+
+    fn delimiter(&self) -> Option<Delimiter> {
+        match self {
+            Trailing::Call(delimiter, _) | Trailing::Index(delimiter, _) => Some(*delimiter),
+            _ => None,
+        }
+    }
+}
+
+/// An expression parser, driven by a table of operators.
+pub struct Pratt<'a, T> {
+    atom: Atom<'a, T>,
+    groups: Vec<Delimiter>,
+    prefix: Vec<(&'static str, u32, Prefix<'a, T>)>,
+    trailing: Vec<(u32, Trailing<'a, T>)>,
+    recover: Option<Recover<'a, T>>,
+}
+
+impl<'a, T> Pratt<'a, T> {
+    pub fn new(atom: impl Parser<'a, Output = T> + 'a) -> Pratt<'a, T> {
+        Pratt {
+            atom: Box::new(atom),
+            groups: vec![],
+            prefix: vec![],
+            trailing: vec![],
+            recover: None,
+        }
+    }
+
+    /// An expression in `delimiter` is a (parenthesized) operand.
+    pub fn group(mut self, delimiter: Delimiter) -> Self {
+        self.groups.push(delimiter);
+        self
+    }
+
+    /// A prefix operator, such as `-a`. The callback gets the operator's span, the operand and the
+    /// span of the whole expression.
+    pub fn prefix(
+        mut self,
+        op: &'static str,
+        precedence: u32,
+        build: impl Fn(Span, T, Span) -> T + 'a,
+    ) -> Self {
+        self.prefix.push((op, precedence, Box::new(build)));
+        // Try longer operators first, so that `--` wins over `-`.
+        self.prefix
+            .sort_by_key(|(op, ..)| std::cmp::Reverse(op.len()));
+        self
+    }
+
+    /// A binary operator, such as `a + b`.
+    pub fn infix(
+        mut self,
+        op: &'static str,
+        precedence: u32,
+        assoc: Assoc,
+        build: impl Fn(T, Span, T, Span) -> T + 'a,
+    ) -> Self {
+        let op = Trailing::Infix(op, assoc, Box::new(build));
+        self.trailing.push((precedence, op));
+        self
+    }
+
+    /// A postfix operator, such as `a?`.
+    pub fn postfix(
+        mut self,
+        op: &'static str,
+        precedence: u32,
+        build: impl Fn(T, Span, Span) -> T + 'a,
+    ) -> Self {
+        let op = Trailing::Postfix(op, Box::new(build));
+        self.trailing.push((precedence, op));
+        self
+    }
+
+    /// A right-associative conditional operator, such as `a ? b : c`. The callback gets the
+    /// three operands.
+    pub fn ternary(
+        mut self,
+        question: &'static str,
+        colon: &'static str,
+        precedence: u32,
+        build: impl Fn(T, T, T, Span) -> T + 'a,
+    ) -> Self {
+        let op = Trailing::Ternary(question, colon, Box::new(build));
+        self.trailing.push((precedence, op));
+        self
+    }
+
+    /// A call, such as `f(a, b)`: an operand followed by a delimited, comma-separated list of
+    /// expressions.
+    pub fn call(
+        mut self,
+        delimiter: Delimiter,
+        precedence: u32,
+        build: impl Fn(T, Vec<T>, Span) -> T + 'a,
+    ) -> Self {
+        self.trailing
+            .push((precedence, Trailing::Call(delimiter, Box::new(build))));
+        self
+    }
+
+    /// Indexing, such as `a[b]`: an operand followed by a delimited expression.
+    pub fn index(
+        mut self,
+        delimiter: Delimiter,
+        precedence: u32,
+        build: impl Fn(T, T, Span) -> T + 'a,
+    ) -> Self {
+        self.trailing
+            .push((precedence, Trailing::Index(delimiter, Box::new(build))));
+        self
+    }
+
+    /// Recover from a missing operand (as in `a + `) by reporting it and building a placeholder
+    /// node at the place where the operand was expected. Only operands that are required recover:
+    /// the operands of prefix, infix and ternary operators and the contents of groups and indexes.
+    /// A whole expression or a call argument can still be missing, so `f(a,)` is `f(a)` either
+    /// way.
+    pub fn recover(mut self, build: impl Fn(Span) -> T + 'a) -> Self {
+        self.recover = Some(Box::new(build));
+        self
+    }
+
+    /// Parse an expression whose operators all bind at least as tightly as `min`. If the
+    /// expression is `required`, a missing operand at the start recovers (see [Pratt::recover]).
+    fn expression(&self, input: Input<'a>, min: u32, required: bool) -> ParseResult<'a, T> {
+        let start = input.position();
+        let (mut lhs, mut input) = self.operand(input, start, required)?;
+
+        loop {
+            let (precedence, op, op_span, rest) = match self.trailing(&input) {
+                Some(found) => found,
+                None => return Ok((lhs, input)),
+            };
+
+            let (left, right) = binding_power(precedence, op);
+
+            if left < min {
+                return Ok((lhs, input));
+            }
+
+            let (node, rest) = match op {
+                Trailing::Infix(_, _, build) => {
+                    let (rhs, rest) = self.expression(rest, right, true)?;
+                    (build(lhs, op_span, rhs, rest.span_from(start)), rest)
+                }
+                Trailing::Postfix(_, build) => (build(lhs, op_span, rest.span_from(start)), rest),
+                Trailing::Ternary(_, colon, build) => {
+                    let (then, rest) = self.expression(rest, 0, true)?;
+                    let (_, rest) = symbol(colon).parse(rest)?;
+                    let (otherwise, rest) = self.expression(rest, right, true)?;
+
+                    (build(lhs, then, otherwise, rest.span_from(start)), rest)
+                }
+                Trailing::Call(_, build) => {
+                    let children = self.children(&input);
+                    let (args, inner) =
+                        sep_by(|input| self.expression(input, 0, false), punct(','))
+                            .parse(children)?;
+                    end().parse(inner)?;
+
+                    (build(lhs, args, rest.span_from(start)), rest)
+                }
+                Trailing::Index(_, build) => {
+                    let children = self.children(&input);
+                    let (index, inner) = self.expression(children, 0, true)?;
+                    end().parse(inner)?;
+
+                    (build(lhs, index, rest.span_from(start)), rest)
+                }
+            };
+
+            lhs = node;
+            input = rest;
+        }
+    }
+
+    /// An atom, a group or a prefix operator and its operand.
+    fn operand(&self, input: Input<'a>, start: Offset, required: bool) -> ParseResult<'a, T> {
+        let operand = |input: Input<'a>| -> ParseResult<'a, T> {
+            for (op, precedence, build) in &self.prefix {
+                if let Ok((op_span, rest)) = symbol(op).parse(input.clone()) {
+                    let (operand, rest) = self.expression(rest, precedence * 2 + 1, true)?;
+                    return Ok((build(op_span, operand, rest.span_from(start)), rest));
+                }
+            }
+
+            if let Some((Next::Delimited(span, delimited), rest)) = input.next() {
+                if self.groups.contains(&delimited.delimiter()) {
+                    let children = input.children(span, delimited);
+                    let (inner, children) = self.expression(children, 0, true)?;
+                    end().parse(children)?;
+
+                    return Ok((inner, rest));
+                }
+            }
+
+            self.atom.parse(input)
+        };
+
+        match label(operand, "an expression").parse(input.clone()) {
+            Err(error) if required && is_clean(&error, &input) => match &self.recover {
+                Some(recover) => {
+                    input.report(error.to_diagnostic());
+                    Ok((recover(Span::eof(input.position())), input))
+                }
+                None => Err(error),
+            },
+            result => result,
+        }
+    }
+
+    /// The operator after an operand (the longest one, if several match). If there isn't one,
+    /// the input expects "an operator" (rather than every operator in the table) at this point.
+    fn trailing(&self, input: &Input<'a>) -> Option<(u32, &Trailing<'a, T>, Span, Input<'a>)> {
+        let before = input.furthest_failure();
+        let next = input.next();
+        let mut found: Option<(u32, &Trailing<'a, T>, Span, Input<'a>)> = None;
+
+        for (precedence, op) in &self.trailing {
+            let matched = match (op.symbol(), op.delimiter(), &next) {
+                (Some(text), _, _) => match symbol(text).parse(input.clone()) {
+                    Ok((span, rest)) => Some((span, rest)),
+                    Err(_) => None,
+                },
+                (_, Some(delimiter), Some((Next::Delimited(span, delimited), rest)))
+                    if delimited.delimiter() == delimiter =>
+                {
+                    Some((*span, rest.clone()))
+                }
+                _ => None,
+            };
+
+            if let Some((span, rest)) = matched {
+                let longer = match &found {
+                    Some((_, _, previous, _)) => {
+                        let (end, previous): (usize, usize) =
+                            (span.end().into(), previous.end().into());
+                        end > previous
+                    }
+                    None => true,
+                };
+
+                if longer {
+                    found = Some((*precedence, op, span, rest));
+                }
+            }
+        }
+
+        input.set_furthest_failure(before);
+
+        if found.is_none() {
+            input.fail("an operator");
+        }
+
+        found
+    }
+
+    fn children(&self, input: &Input<'a>) -> Input<'a> {
+        match input.next() {
+            Some((Next::Delimited(span, delimited), _)) => input.children(span, delimited),
+            _ => unreachable!("BUG: calls and indexes start with a delimited token"),
+        }
+    }
+}
+
+/// The binding powers on either side of an operator. An operator applies if its left binding
+/// power is at least the minimum, and its right operand is parsed with its right binding power
+/// as the new minimum.
+fn binding_power<T>(precedence: u32, op: &Trailing<'_, T>) -> (u32, u32) {
+    let precedence = precedence * 2 + 1;
+
+    match op {
+        Trailing::Infix(_, Assoc::Left, _) => (precedence, precedence + 1),
+        Trailing::Infix(_, Assoc::Right, _) | Trailing::Ternary(..) => (precedence + 1, precedence),
+        Trailing::Postfix(..) | Trailing::Call(..) | Trailing::Index(..) => (precedence, 0),
+    }
+}
+
+impl<'a, T> Parser<'a> for Pratt<'a, T> {
+    type Output = T;
+
+    fn parse(&self, input: Input<'a>) -> ParseResult<'a, T> {
+        self.expression(input, 0, false)
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_core::wyst_data;
+        use wyst_source::{Diagnostic, Source, Spanned};
+
+        use crate::{
+            combinators::map,
+            parser::{parse, parse_recovering, word},
+            testing::read,
+        };
+
+        #[wyst_data]
+        enum Expr {
+            Atom(String),
+            Unary(String, Box<Expr>),
+            Binary(Box<Expr>, String, Box<Expr>),
+            If(Box<Expr>, Box<Expr>, Box<Expr>),
+            Call(Box<Expr>, Vec<Expr>),
+            Index(Box<Expr>, Box<Expr>),
+            Error,
+        }
+
+        /// Lisp-style output, to make precedence easy to see.
+        fn sexp(expr: &Expr) -> String {
+            match expr {
+                Expr::Atom(name) => name.clone(),
+                Expr::Unary(op, operand) => format!("({} {})", op, sexp(operand)),
+                Expr::Binary(lhs, op, rhs) => format!("({} {} {})", op, sexp(lhs), sexp(rhs)),
+                Expr::If(cond, then, otherwise) => {
+                    format!("(? {} {} {})", sexp(cond), sexp(then), sexp(otherwise))
+                }
+                Expr::Call(callee, args) => {
+                    let args: Vec<String> = args.iter().map(sexp).collect();
+                    format!("(call {} [{}])", sexp(callee), args.join(" "))
+                }
+                Expr::Index(lhs, index) => format!("(index {} {})", sexp(lhs), sexp(index)),
+                Expr::Error => "<error>".to_string(),
+            }
+        }
+
+        fn binary<'a>(source: &'a Source) -> impl Fn(Expr, Span, Expr, Span) -> Expr + 'a {
+            move |lhs, op, rhs, _| {
+                Expr::Binary(Box::new(lhs), source.slice(op).to_string(), Box::new(rhs))
+            }
+        }
+
+        fn grammar(source: &Source) -> Pratt<'_, Expr> {
+            Pratt::new(map(word(), |word: Spanned<&str>| {
+                Expr::Atom(word.item().to_string())
+            }))
+            .group(Delimiter::Paren)
+            .ternary("?", ":", 1, |cond, then, otherwise, _| {
+                Expr::If(Box::new(cond), Box::new(then), Box::new(otherwise))
+            })
+            .infix("==", 2, Assoc::Left, binary(source))
+            .infix("+", 3, Assoc::Left, binary(source))
+            .infix("-", 3, Assoc::Left, binary(source))
+            .infix("*", 4, Assoc::Left, binary(source))
+            .infix("**", 6, Assoc::Right, binary(source))
+            .prefix("-", 5, move |op, operand, _| {
+                Expr::Unary(source.slice(op).to_string(), Box::new(operand))
+            })
+            .postfix("!", 7, move |operand, op, _| {
+                Expr::Unary(source.slice(op).to_string(), Box::new(operand))
+            })
+            .call(Delimiter::Paren, 8, |callee, args, _| {
+                Expr::Call(Box::new(callee), args)
+            })
+            .index(Delimiter::Bracket, 8, |lhs, index, _| {
+                Expr::Index(Box::new(lhs), Box::new(index))
+            })
+        }
+
+        fn sexps(text: &str) -> Result<String, Diagnostic> {
+            let source = Source::new("<test>", text);
+            let tokens = read(&source);
+
+            parse(grammar(&source), &tokens, &source).map(|expr| sexp(&expr))
+        }
+    }),
+    tests(
+        ("precedence and associativity", {
+            assert_eq!(
+                sexps("a + b * c - d"),
+                Ok("(- (+ a (* b c)) d)".to_string())
+            );
+            assert_eq!(sexps("a ** b ** c"), Ok("(** a (** b c))".to_string()));
+            assert_eq!(sexps("-a ** b"), Ok("(- (** a b))".to_string()));
+            assert_eq!(sexps("-a * b"), Ok("(* (- a) b)".to_string()));
+            assert_eq!(sexps("-a! * b"), Ok("(* (- (! a)) b)".to_string()));
+            assert_eq!(sexps("a - -b"), Ok("(- a (- b))".to_string()));
+            assert_eq!(sexps("(a + b) * c"), Ok("(* (+ a b) c)".to_string()));
+            assert_eq!(sexps("a==b+c"), Ok("(== a (+ b c))".to_string()));
+        }),
+        ("mixfix", {
+            assert_eq!(
+                sexps("a ? b : c ? d : e"),
+                Ok("(? a b (? c d e))".to_string())
+            );
+            assert_eq!(
+                sexps("a == b ? c + d : e"),
+                Ok("(? (== a b) (+ c d) e)".to_string())
+            );
+            assert_eq!(
+                sexps("f(a, b + c)[i](g())"),
+                Ok("(call (index (call f [a (+ b c)]) i) [(call g [])])".to_string())
+            );
+            assert_eq!(sexps("-f(a)!"), Ok("(- (! (call f [a])))".to_string()));
+        }),
+        ("spans", {
+            let source = Source::new("<test>", "a + f(b) * c");
+            let tokens = read(&source);
+            let pratt = Pratt::new(map(word(), |word: Spanned<&str>| vec![word.span()]))
+                .infix("+", 1, Assoc::Left, |mut lhs, _, rhs, span| {
+                    lhs.extend(rhs);
+                    lhs.push(span);
+                    lhs
+                })
+                .infix("*", 2, Assoc::Left, |mut lhs, _, rhs, span| {
+                    lhs.extend(rhs);
+                    lhs.push(span);
+                    lhs
+                })
+                .call(Delimiter::Paren, 3, |mut callee, args, span| {
+                    callee.extend(args.into_iter().flatten());
+                    callee.push(span);
+                    callee
+                });
+
+            let spans = parse(pratt, &tokens, &source).unwrap();
+            let texts: Vec<&str> = spans.iter().map(|span| source.slice(*span)).collect();
+
+            assert_eq!(
+                texts,
+                &["a", "f", "b", "f(b)", "c", "f(b) * c", "a + f(b) * c"]
+            );
+        }),
+        ("errors", {
+            assert_eq!(
+                sexps("a + * b"),
+                Err(Diagnostic::error(
+                    Span::new(4, 5),
+                    "expected an expression, found `*`"
+                ))
+            );
+            assert_eq!(
+                sexps("a ? b c"),
+                Err(Diagnostic::error(
+                    Span::new(6, 7),
+                    "expected one of `:` or an operator, found `c`"
+                ))
+            );
+        }),
+        ("recovering from missing operands", {
+            let source = Source::new("<test>", "f(a +, -) * ");
+            let tokens = read(&source);
+            let pratt = grammar(&source).recover(|_| Expr::Error);
+            let parsed = parse_recovering(pratt, &tokens, &source);
+
+            assert_eq!(
+                parsed.output().map(sexp),
+                Some("(* (call f [(+ a <error>) (- <error>)]) <error>)".to_string())
+            );
+            assert_eq!(
+                parsed.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(5, 6), "expected an expression, found `,`"),
+                    Diagnostic::error(Span::new(8, 9), "expected an expression, found `)`"),
+                    Diagnostic::error(
+                        Span::eof(12),
+                        "expected an expression, found the end of the input"
+                    ),
+                ]
+            );
+
+            // A trailing comma isn't a missing argument, with or without recovery.
+            let source = Source::new("<test>", "f(a,)");
+            let tokens = read(&source);
+            let pratt = grammar(&source).recover(|_| Expr::Error);
+            let parsed = parse_recovering(pratt, &tokens, &source);
+
+            assert_eq!(parsed.output().map(sexp), Some("(call f [a])".to_string()));
+            assert_eq!(parsed.diagnostics(), &[]);
+            assert_eq!(sexps("f(a,)"), Ok("(call f [a])".to_string()));
+        })
+    )
+);
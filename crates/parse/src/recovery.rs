@@ -0,0 +1,279 @@
+//! Panic-mode error recovery.
+//!
+//! When a rule wrapped in [recover] fails, the error is reported, the input is skipped up to one
+//! of the rule's synchronization tokens, and the rule produces an error node instead of failing.
+//! Skipping stops at the end of the input, and the input inside of a
+//! [delimited](crate::delimited) token ends at its closing delimiter, so recovery never escapes
+//! the current brackets.
+
+use wyst_core::unit_tests;
+use wyst_source::Span;
+
+use crate::{
+    error::ParseError,
+    input::Input,
+    parser::{symbol, ParseResult, Parser},
+};
+
+/// Where a rule resumes after an error. Each rule that recovers has its own.
+#[derive(Debug, Clone, Default)]
+pub struct Recovery {
+    until: Vec<&'static str>,
+    through: Vec<&'static str>,
+}
+
+impl Recovery {
+    pub fn new() -> Recovery {
+        Recovery::default()
+    }
+
+    /// Stop skipping before `token`, which something else parses (such as the `,` that separates
+    /// the items in a list).
+    pub fn until(mut self, token: &'static str) -> Recovery {
+        self.until.push(token);
+        self
+    }
+
+    /// Stop skipping after `token`, which belongs to the rule (such as the `;` that ends a
+    /// statement).
+    pub fn through(mut self, token: &'static str) -> Recovery {
+        self.through.push(token);
+        self
+    }
+
+    /// The input after skipping to a synchronization token (or to the end).
+    fn skip<'a>(&self, mut input: Input<'a>) -> Input<'a> {
+        loop {
+            if self
+                .until
+                .iter()
+                .any(|token| symbol(token).parse(input.clone()).is_ok())
+            {
+                return input;
+            }
+
+            for token in &self.through {
+                if let Ok((_, rest)) = symbol(token).parse(input.clone()) {
+                    return rest;
+                }
+            }
+
+            match input.next() {
+                Some((_, rest)) => input = rest,
+                None => return input,
+            }
+        }
+    }
+}
+
+/// `parser`, but if it fails, the error is reported and `error` builds a node out of the span
+/// that was skipped. Skipping starts where the parser failed and ends at one of the
+/// synchronization tokens in `recovery`.
+///
+/// If recovering wouldn't skip anything (for example, because the parser failed right before an
+/// [until](Recovery::until) token), the parser fails as if it didn't recover.
+pub fn recover<'a, P, F>(
+    parser: P,
+    recovery: Recovery,
+    error: F,
+) -> impl Parser<'a, Output = P::Output>
+where
+    P: Parser<'a>,
+    F: Fn(Span) -> P::Output,
+{
+    move |input: Input<'a>| -> ParseResult<'a, P::Output> {
+        // Only the failures inside of the rule decide where it failed.
+        let before = input.furthest_failure();
+        input.set_furthest_failure(None);
+
+        let result = parser.parse(input.clone());
+        let during = input.furthest_failure();
+        input.set_furthest_failure(merge(before.clone(), during.clone()));
+
+        let failure = match result {
+            Ok(result) => return Ok(result),
+            Err(failure) => merge(during, Some(failure)).expect("BUG: there's a failure"),
+        };
+
+        let failed_at: usize = failure.span().start().into();
+
+        // Skip everything up to the failure, including any delimited token that it's inside of.
+        let mut rest = input.clone();
+        while let Some((next, after)) = rest.next() {
+            let start: usize = next.span().start().into();
+
+            if start >= failed_at {
+                break;
+            }
+
+            rest = after;
+        }
+
+        let rest = recovery.skip(rest);
+
+        if rest.consumed() == input.consumed() {
+            return Err(failure);
+        }
+
+        input.report(failure.to_diagnostic());
+        rest.set_furthest_failure(before);
+
+        let span = rest.span_from(input.position());
+        Ok((error(span), rest))
+    }
+}
+
+fn merge(left: Option<ParseError>, right: Option<ParseError>) -> Option<ParseError> {
+    match (left, right) {
+        (Some(left), Some(right)) => Some(left.merge(right)),
+        (left, None) => left,
+        (None, right) => right,
+    }
+}
+
+/// Report the furthest failure since `input`, including `error`.
+pub(crate) fn report_furthest(input: &Input, error: ParseError) {
+    let error = merge(input.furthest_failure(), Some(error)).expect("BUG: there's a failure");
+    input.report(error.to_diagnostic());
+}
+
+unit_tests!(
+    all({
+        use wyst_core::wyst_data;
+        use wyst_lex::Delimiter;
+        use wyst_source::{Diagnostic, Source};
+
+        use crate::{
+            combinators::{many, map, sep_by, seq},
+            parser::{delimited, keyword, parse_recovering, punct, word},
+            testing::read,
+        };
+
+        #[wyst_data]
+        enum Node {
+            Item(String),
+            Error(Span),
+        }
+
+        fn let_statement<'a>() -> impl Parser<'a, Output = Node> {
+            recover(
+                map(
+                    seq((keyword("let"), word(), punct('='), word(), punct(';'))),
+                    |(_, name, _, _, _)| Node::Item(name.item().to_string()),
+                ),
+                Recovery::new().through(";"),
+                Node::Error,
+            )
+        }
+
+        fn field<'a>() -> impl Parser<'a, Output = Node> {
+            recover(
+                map(seq((word(), punct(':'), word())), |(name, _, _)| {
+                    Node::Item(name.item().to_string())
+                }),
+                Recovery::new().until(","),
+                Node::Error,
+            )
+        }
+    }),
+    tests(
+        ("recovering through a terminator", {
+            let source = Source::new("<test>", "let a = b; let = c; let d e; let f = g;");
+            let tokens = read(&source);
+            let parsed = parse_recovering(many(let_statement()), &tokens, &source);
+
+            assert_eq!(
+                parsed.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(15, 16), "expected a word, found `=`"),
+                    Diagnostic::error(Span::new(26, 27), "expected `=`, found `e`"),
+                ]
+            );
+            assert_eq!(
+                parsed.output(),
+                Some(&vec![
+                    Node::Item("a".to_string()),
+                    Node::Error(Span::new(11, 19)),
+                    Node::Error(Span::new(20, 28)),
+                    Node::Item("f".to_string()),
+                ])
+            );
+        }),
+        ("recovering until a separator, inside of the delimiters", {
+            let source = Source::new("<test>", "(a: x, b y z, c: z) d");
+            let tokens = read(&source);
+            let parsed = parse_recovering(
+                seq((
+                    delimited(Delimiter::Paren, sep_by(field(), punct(','))),
+                    word(),
+                )),
+                &tokens,
+                &source,
+            );
+
+            assert_eq!(
+                parsed.diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(9, 10),
+                    "expected `:`, found `y`"
+                )]
+            );
+
+            let (fields, after) = parsed.into_output().unwrap();
+            assert_eq!(
+                fields,
+                vec![
+                    Node::Item("a".to_string()),
+                    Node::Error(Span::new(7, 12)),
+                    Node::Item("c".to_string()),
+                ]
+            );
+            assert_eq!(after.item(), &"d");
+
+            // The error at the end of the list can't skip past the `)`.
+            let source = Source::new("<test>", "(a: x, b) d");
+            let tokens = read(&source);
+            let parsed = parse_recovering(
+                seq((
+                    delimited(Delimiter::Paren, sep_by(field(), punct(','))),
+                    word(),
+                )),
+                &tokens,
+                &source,
+            );
+
+            assert_eq!(
+                parsed.diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(8, 9),
+                    "expected `:`, found `)`"
+                )]
+            );
+            assert_eq!(
+                parsed.into_output().map(|(fields, _)| fields),
+                Some(vec![
+                    Node::Item("a".to_string()),
+                    Node::Error(Span::new(7, 8))
+                ])
+            );
+        }),
+        ("recovering without skipping anything fails", {
+            let source = Source::new("<test>", "(, a: x)");
+            let tokens = read(&source);
+            let parsed = parse_recovering(
+                delimited(Delimiter::Paren, sep_by(field(), punct(','))),
+                &tokens,
+                &source,
+            );
+
+            assert_eq!(
+                parsed.diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(1, 2),
+                    "expected one of `)` or a word, found `,`"
+                )]
+            );
+            assert_eq!(parsed.output(), None);
+        })
+    )
+);
@@ -0,0 +1,9 @@
+//! Helpers for the unit tests.
+
+use wyst_lex::{DefaultDelegate, FlatToken, Token};
+use wyst_source::{Source, Spanned};
+
+/// Read a source into token trees with the default delegate.
+pub(crate) fn read(source: &Source) -> Vec<Spanned<Token>> {
+    FlatToken::read_tree::<DefaultDelegate>(source).into_tokens()
+}
@@ -0,0 +1,83 @@
+//! `proptest` strategies for HIR programs, for property-testing formatters built on
+//! `wyst-printer`. These are only available with the `proptest` feature.
+//!
+//! A generated [HirProgram] is a description of the calls to make on a [HirBuilder]. Groups and
+//! nests are always built through the builder's closures, so every program (including the shrunk
+//! ones) is well-formed.
+
+use proptest::prelude::*;
+use wyst_core::wyst_data;
+use wyst_style::Style;
+
+use crate::ir::HirBuilder;
+
+/// A single call on a [HirBuilder].
+#[wyst_data]
+pub enum HirOp {
+    Text(String),
+    Space(String),
+    Wbr(usize),
+    Br,
+    Group(Vec<HirOp>),
+    Nest(usize, Vec<HirOp>),
+}
+
+impl HirOp {
+    fn build<'texts, S: Style>(&self, builder: HirBuilder<'texts, S>) -> HirBuilder<'texts, S> {
+        match self {
+            HirOp::Text(text) => builder.text(text),
+            HirOp::Space(space) => builder.space(space),
+            HirOp::Wbr(level) => builder.wbr(*level),
+            HirOp::Br => builder.br(),
+            HirOp::Group(ops) => builder.group(|b| build_all(ops, b)),
+            HirOp::Nest(level, ops) => builder.nest(*level, |b| build_all(ops, b)),
+        }
+    }
+}
+
+fn build_all<'texts, S: Style>(
+    ops: &[HirOp],
+    builder: HirBuilder<'texts, S>,
+) -> HirBuilder<'texts, S> {
+    ops.iter().fold(builder, |builder, op| op.build(builder))
+}
+
+/// A well-formed HIR program.
+#[wyst_data]
+pub struct HirProgram {
+    ops: Vec<HirOp>,
+}
+
+impl HirProgram {
+    pub fn ops(&self) -> &[HirOp] {
+        &self.ops
+    }
+
+    /// Make the calls that this program describes on `builder`.
+    pub fn build<'texts, S: Style>(&self, builder: HirBuilder<'texts, S>) -> HirBuilder<'texts, S> {
+        build_all(&self.ops, builder)
+    }
+}
+
+/// A single [HirOp], with groups and nests at most `depth` levels deep.
+pub fn hir_op(depth: u32) -> impl Strategy<Value = HirOp> {
+    let leaf = prop_oneof![
+        4 => "[a-z(),.]{1,8}".prop_map(HirOp::Text),
+        2 => Just(HirOp::Space(" ".to_string())),
+        3 => (0..3usize).prop_map(HirOp::Wbr),
+        1 => Just(HirOp::Br),
+    ];
+
+    leaf.prop_recursive(depth, 64, 8, |inner| {
+        prop_oneof![
+            prop::collection::vec(inner.clone(), 0..8).prop_map(HirOp::Group),
+            (0..3usize, prop::collection::vec(inner, 0..8))
+                .prop_map(|(level, ops)| HirOp::Nest(level, ops)),
+        ]
+    })
+}
+
+/// A well-formed HIR program.
+pub fn hir_program() -> impl Strategy<Value = HirProgram> {
+    prop::collection::vec(hir_op(4), 0..16).prop_map(|ops| HirProgram { ops })
+}
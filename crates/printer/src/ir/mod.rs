@@ -4,6 +4,8 @@ pub mod printer;
 
 pub use self::hir::to_lines;
 pub use self::hir::HirBuilder;
+#[cfg(all(test, feature = "proptest"))]
+pub(crate) use self::hir::IndentationHIR;
 pub(crate) use self::hir::{Atomic, HIR};
 pub use self::lir::LirBuilder;
 pub(crate) use self::lir::LIR;
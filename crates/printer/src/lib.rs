@@ -2,6 +2,8 @@
 mod dev;
 
 mod algorithm;
+#[cfg(feature = "proptest")]
+pub mod arbitrary;
 mod builder;
 mod fragment;
 pub mod ir;
@@ -16,3 +18,4 @@ mod tests;
 pub use self::builder::FragmentBuilder;
 pub use self::ir::{to_lines, HirBuilder, PrintConfig, Printer};
 pub use self::range::{RangeBound, ResolvedRange, WystRange};
+pub use self::texts::Texts;
@@ -1 +1,3 @@
+#[cfg(feature = "proptest")]
+mod test_arbitrary;
 mod test_hir;
@@ -0,0 +1,30 @@
+use proptest::prelude::*;
+use wyst_style::PortableStyle;
+
+use crate::{
+    arbitrary::hir_program,
+    ir::{HirBuilder, IndentationHIR, HIR},
+    texts::Texts,
+};
+
+proptest! {
+    #[test]
+    fn test_generated_programs_are_balanced(program in hir_program()) {
+        let mut texts = Texts::default();
+        let hir = program.build(HirBuilder::<PortableStyle>::new(&mut texts)).done();
+
+        let mut depth = 0isize;
+
+        for op in hir.children.iter() {
+            match op {
+                HIR::Indentation(IndentationHIR::Indent) => depth += 1,
+                HIR::Indentation(IndentationHIR::Outdent) => depth -= 1,
+                _ => {}
+            }
+
+            prop_assert!(depth >= 0);
+        }
+
+        prop_assert_eq!(depth, 0);
+    }
+}
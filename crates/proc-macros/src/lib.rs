@@ -1,7 +1,7 @@
 #![feature(proc_macro_def_site)]
 
 use darling::{util::Flag, FromMeta};
-use derive_syn_parse::Parse;
+use derive_syn_parse::Parse as SynParse;
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 use syn::{
@@ -11,6 +11,9 @@ use syn::{
 use syn::{Generics, Ident};
 use unicode_xid::UnicodeXID;
 
+mod parse;
+mod tokens;
+
 #[derive(Debug, FromMeta)]
 struct WystDataArgs {
     #[darling(default)]
@@ -192,6 +195,13 @@ pub fn wyst_copy(args: TokenStream, input: TokenStream) -> TokenStream {
     data(ast, args.with_copy())
 }
 
+#[proc_macro_derive(Parse, attributes(wyst))]
+pub fn derive_parse(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    parse::expand(ast)
+}
+
 #[proc_macro_derive(new)]
 pub fn derive_new(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
@@ -365,22 +375,13 @@ fn data(ast: DeriveInput, args: WystDataArgs) -> TokenStream {
 
     let DeriveInput {
         ident, generics, ..
-    } = ast.clone();
-
-    let Generics {
-        lt_token,
-        params,
-        gt_token,
-        where_clause,
-    } = generics.clone();
+    } = &ast;
 
-    let generics = quote! {
-        #lt_token #params #gt_token
-    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let copy_impl = if args.copy.is_some() {
         quote! {
-            impl #generics Copy for #ident #generics #where_clause {}
+            impl #impl_generics Copy for #ident #ty_generics #where_clause {}
         }
     } else {
         quote! {}
@@ -410,22 +411,13 @@ fn data_value(ast: DeriveInput, args: WystDataArgs) -> TokenStream {
 
     let DeriveInput {
         ident, generics, ..
-    } = ast.clone();
-
-    let Generics {
-        lt_token,
-        params,
-        gt_token,
-        where_clause,
-    } = generics.clone();
+    } = &ast;
 
-    let generics = quote! {
-        #lt_token #params #gt_token
-    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
 
     let copy_impl = if args.copy.is_some() {
         quote! {
-            impl #generics Copy for #ident #generics #where_clause {}
+            impl #impl_generics Copy for #ident #ty_generics #where_clause {}
         }
     } else {
         quote! {}
@@ -448,7 +440,7 @@ fn data_value(ast: DeriveInput, args: WystDataArgs) -> TokenStream {
     expanded.into()
 }
 
-#[derive(Parse)]
+#[derive(SynParse)]
 struct UnitTest {
     desc: syn::LitStr,
     _comma: Token![,],
@@ -457,6 +449,31 @@ struct UnitTest {
     block: syn::Block,
 }
 
+/// Build the token trees (a `Vec<Spanned<Token>>`) for source-like input, computing spans from the
+/// layout of the input. For example, `tokens!{ hello { world() } $eof }` is the same as building
+/// the tokens for `"hello { world() }"` (plus the EOF token) with a `TokenBuilder`.
+///
+/// - Rust tokens that are next to each other are glued into a single word (`a.b`, `x,` or `->`).
+/// - Spaces between tokens become whitespace tokens, and line breaks become newline tokens. The
+///   indentation at the start of a line is ignored.
+/// - String and char literals become double-quoted and single-quoted tokens.
+///
+/// Escapes are used for anything else. Spaces next to an escape are ignored, so that escapes
+/// control the layout around them, but line breaks are not.
+///
+/// - `$ws(n)` and `$ws("\t")`: whitespace (`n` spaces, or the given text)
+/// - `$nl`: a newline
+/// - `$eof`: the EOF token
+/// - `$word("..")` and `$error("..")`: a word or error token with the given text
+/// - `$comment("#", " body")` and `$comment("/*", " body ", "*/")`: a comment
+/// - `$quote('`', "..")`: a quoted token with the given quote
+///
+/// The expansion refers to `::wyst_lex`.
+#[proc_macro]
+pub fn tokens(input: TokenStream) -> TokenStream {
+    tokens::expand(input)
+}
+
 #[proc_macro]
 pub fn unit_test(input: TokenStream) -> TokenStream {
     let UnitTest { desc, block, .. } = parse_macro_input!(input as UnitTest);
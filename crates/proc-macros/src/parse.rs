@@ -0,0 +1,300 @@
+//! The implementation of `#[derive(Parse)]`.
+//!
+//! Structs parse their fields in order. Enums try their variants in order, like `alt`. Fields are
+//! parsed with their type's `Parse` implementation, unless they're annotated:
+//!
+//! - `#[wyst(token = "fn")]`: a keyword or punctuation (the field is a `Span`)
+//! - `#[wyst(delimited = "paren")]`: the field is parsed from the children of a delimited token
+//!   (`"paren"`, `"brace"` or `"bracket"`), which it has to consume completely
+//! - `#[wyst(sep = ",")]`: the field is a `Vec` of items separated by the separator. Inside of a
+//!   delimited token, an item that fails to parse is reported and skipped.
+//!
+//! A field called `span` isn't parsed. It's the span of the whole node, which `HasSpan` returns.
+//! A tuple variant with a single field gets its span from that field.
+//!
+//! The only recovery is in `sep` fields inside of a `delimited` token: a syntax error anywhere else
+//! fails the whole node.
+//!
+//! Fields are bound to generated names (`__field_0`, ...) while they're parsed, so that a field
+//! can have the same name as a local of the generated code (such as `input` or `start`).
+//!
+//! The expansion refers to `::wyst_parse`.
+
+use darling::FromMeta;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    spanned::Spanned, Data, DeriveInput, Field, Fields, GenericArgument, PathArguments, Type,
+};
+
+type Result<T> = std::result::Result<T, syn::Error>;
+
+pub(crate) fn expand(input: DeriveInput) -> TokenStream {
+    match derive(input) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => TokenStream::from(err.to_compile_error()),
+    }
+}
+
+#[derive(Debug, Default, FromMeta)]
+struct FieldArgs {
+    #[darling(default)]
+    token: Option<String>,
+    #[darling(default)]
+    delimited: Option<String>,
+    #[darling(default)]
+    sep: Option<String>,
+}
+
+impl FieldArgs {
+    fn from_field(field: &Field) -> Result<FieldArgs> {
+        let mut args = FieldArgs::default();
+
+        for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("wyst")) {
+            let nested = match attr.parse_meta()? {
+                syn::Meta::List(list) => list.nested.into_iter().collect::<Vec<_>>(),
+                meta => {
+                    return Err(syn::Error::new(
+                        meta.span(),
+                        "expected #[wyst(token = \"..\")], #[wyst(delimited = \"..\")] or #[wyst(sep = \"..\")]",
+                    ))
+                }
+            };
+
+            let parsed = FieldArgs::from_list(&nested)
+                .map_err(|err| syn::Error::new(attr.span(), err.to_string()))?;
+
+            args = FieldArgs {
+                token: parsed.token.or(args.token),
+                delimited: parsed.delimited.or(args.delimited),
+                sep: parsed.sep.or(args.sep),
+            };
+        }
+
+        Ok(args)
+    }
+}
+
+fn derive(input: DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "#[derive(Parse)] doesn't support generic types yet",
+        ));
+    }
+
+    let name = &input.ident;
+
+    let (parse, span) = match &input.data {
+        Data::Struct(data) => {
+            let (bindings, construct) = parse_fields(&data.fields, quote! { #name })?;
+            let span = span_of(&data.fields, quote! { #name }, data.fields.span())?;
+
+            let parse = quote! {
+                let __start = input.position();
+                #bindings
+                Ok((#construct, input))
+            };
+
+            (parse, quote! { match self { #span } })
+        }
+        Data::Enum(data) => {
+            let mut variants = vec![];
+            let mut spans = vec![];
+
+            for variant in &data.variants {
+                let id = &variant.ident;
+                let (bindings, construct) = parse_fields(&variant.fields, quote! { #name::#id })?;
+
+                variants.push(quote! {
+                    let variant = |input: ::wyst_parse::Input<'a>| -> ::wyst_parse::ParseResult<'a, #name> {
+                        let __start = input.position();
+                        #bindings
+                        Ok((#construct, input))
+                    };
+
+                    match variant(input.clone()) {
+                        Ok(result) => return Ok(result),
+                        Err(failure) => {
+                            error = Some(match error {
+                                Some(error) => error.merge(failure),
+                                None => failure,
+                            })
+                        }
+                    }
+                });
+
+                spans.push(span_of(
+                    &variant.fields,
+                    quote! { #name::#id },
+                    variant.span(),
+                )?);
+            }
+
+            let parse = quote! {
+                let mut error: Option<::wyst_parse::ParseError> = None;
+                #(#variants)*
+                Err(error.expect("BUG: #[derive(Parse)] enums have at least one variant"))
+            };
+
+            (parse, quote! { match self { #(#spans)* } })
+        }
+        Data::Union(data) => {
+            return Err(syn::Error::new(
+                data.union_token.span,
+                "#[derive(Parse)] doesn't support unions",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        impl<'a> ::wyst_parse::Parse<'a> for #name {
+            #[allow(unused_variables)]
+            fn parse(input: ::wyst_parse::Input<'a>) -> ::wyst_parse::ParseResult<'a, Self> {
+                #parse
+            }
+        }
+
+        impl ::wyst_parse::HasSpan for #name {
+            fn span(&self) -> ::wyst_parse::wyst_lex::wyst_source::Span {
+                #span
+            }
+        }
+    })
+}
+
+/// The `let` statements that parse each field (in order), and the expression that builds the
+/// node out of them.
+fn parse_fields(fields: &Fields, path: TokenStream2) -> Result<(TokenStream2, TokenStream2)> {
+    let mut bindings = vec![];
+    let mut spans = vec![];
+    let mut values = vec![];
+
+    for (index, field) in fields.iter().enumerate() {
+        let binding = format_ident!("__field_{}", index);
+
+        // The span is the span of everything that the other fields parsed.
+        if is_span_field(field) {
+            spans.push(quote! { let #binding = input.span_from(__start); });
+        } else {
+            let parser = field_parser(field)?;
+
+            bindings.push(quote_spanned! { field.span() =>
+                let (#binding, input) = ::wyst_parse::Parser::parse(&#parser, input)?;
+            });
+        }
+
+        values.push(match &field.ident {
+            Some(ident) => quote! { #ident: #binding },
+            None => quote! { #binding },
+        });
+    }
+
+    let construct = match fields {
+        Fields::Named(_) => quote! { #path { #(#values),* } },
+        Fields::Unnamed(_) => quote! { #path(#(#values),*) },
+        Fields::Unit => {
+            return Err(syn::Error::new(
+                path.span(),
+                "#[derive(Parse)] doesn't support unit structs or variants (use a field with #[wyst(token = \"..\")])",
+            ))
+        }
+    };
+
+    Ok((quote! { #(#bindings)* #(#spans)* }, construct))
+}
+
+/// A match arm that returns the span of a struct or variant.
+fn span_of(fields: &Fields, path: TokenStream2, span: proc_macro2::Span) -> Result<TokenStream2> {
+    match fields {
+        Fields::Named(named) if named.named.iter().any(is_span_field) => {
+            Ok(quote! { #path { span, .. } => *span, })
+        }
+        Fields::Unnamed(unnamed) if unnamed.unnamed.len() == 1 => {
+            Ok(quote! { #path(node) => ::wyst_parse::HasSpan::span(node), })
+        }
+        _ => Err(syn::Error::new(
+            span,
+            "#[derive(Parse)] needs a `span: Span` field, or a single unnamed field to get the span from",
+        )),
+    }
+}
+
+fn is_span_field(field: &Field) -> bool {
+    field.ident.as_ref().map(|id| id == "span").unwrap_or(false)
+}
+
+/// An expression for the parser of a single field.
+fn field_parser(field: &Field) -> Result<TokenStream2> {
+    let args = FieldArgs::from_field(field)?;
+    let ty = &field.ty;
+
+    let parser = match (&args.token, &args.sep) {
+        (Some(_), Some(_)) => {
+            return Err(syn::Error::new(
+                field.span(),
+                "a field can't have both a `token` and a `sep`",
+            ))
+        }
+        (Some(token), None) => quote! { ::wyst_parse::symbol(#token) },
+        (None, Some(sep)) => {
+            let item = vec_item(ty).ok_or_else(|| {
+                syn::Error::new(ty.span(), "a field with a `sep` has to be a `Vec`")
+            })?;
+
+            let item = quote! { <#item as ::wyst_parse::Parse<'a>>::parse };
+            let separator = quote! { ::wyst_parse::symbol(#sep) };
+
+            if args.delimited.is_some() {
+                quote! { ::wyst_parse::sep_by_recovering(#item, #separator) }
+            } else {
+                quote! { ::wyst_parse::sep_by(#item, #separator) }
+            }
+        }
+        (None, None) => quote! { <#ty as ::wyst_parse::Parse<'a>>::parse },
+    };
+
+    match &args.delimited {
+        Some(delimiter) => {
+            let delimiter = match delimiter.as_str() {
+                "paren" => quote! { Paren },
+                "brace" => quote! { Brace },
+                "bracket" => quote! { Bracket },
+                _ => {
+                    return Err(syn::Error::new(
+                        field.span(),
+                        "`delimited` has to be \"paren\", \"brace\" or \"bracket\"",
+                    ))
+                }
+            };
+
+            Ok(quote! {
+                ::wyst_parse::delimited(::wyst_parse::wyst_lex::Delimiter::#delimiter, #parser)
+            })
+        }
+        None => Ok(parser),
+    }
+}
+
+/// `T`, if `ty` is `Vec<T>`.
+fn vec_item(ty: &Type) -> Option<&Type> {
+    let path = match ty {
+        Type::Path(path) => &path.path,
+        _ => return None,
+    };
+
+    let segment = path.segments.last()?;
+
+    if segment.ident != "Vec" {
+        return None;
+    }
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(ty) => Some(ty),
+            _ => None,
+        },
+        _ => None,
+    }
+}
@@ -0,0 +1,415 @@
+//! The implementation of the `tokens!` macro.
+//!
+//! The macro reads the layout of its input from the source locations of the Rust tokens it was
+//! given, so `tokens!{ hello { world() } }` knows that there is a space between `hello` and `{`,
+//! but not between `world` and `(`.
+
+use proc_macro::{Delimiter, Group, Literal, Span, TokenStream, TokenTree};
+use quote::quote;
+
+type Result<T> = std::result::Result<T, syn::Error>;
+
+pub(crate) fn expand(input: TokenStream) -> TokenStream {
+    let pieces = match Parser::new(input, Previous::Start(None)).parse() {
+        Ok(pieces) => pieces,
+        Err(err) => return TokenStream::from(err.to_compile_error()),
+    };
+
+    let mut offset = 0;
+    let tokens = generate(&pieces, &mut offset);
+
+    TokenStream::from(quote! {
+        {
+            let tokens: Vec<::wyst_lex::wyst_source::Spanned<::wyst_lex::Token>> = vec![#(#tokens),*];
+            tokens
+        }
+    })
+}
+
+/// A single token in the output, before spans have been computed.
+enum Piece {
+    Word(String),
+    Error(String),
+    Whitespace(String),
+    Newline,
+    Comment {
+        pre: String,
+        body: String,
+        post: String,
+    },
+    Quoted {
+        quote: char,
+        inner: String,
+    },
+    Delimited {
+        delimiter: Delimiter,
+        children: Vec<Piece>,
+    },
+    Eof,
+}
+
+#[derive(Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+}
+
+impl Position {
+    fn start(span: Span) -> Position {
+        let start = span.start();
+
+        Position {
+            line: start.line(),
+            column: start.column(),
+        }
+    }
+
+    fn end(span: Span) -> Position {
+        let end = span.end();
+
+        Position {
+            line: end.line(),
+            column: end.column(),
+        }
+    }
+}
+
+/// What came before the current token in the input.
+#[derive(Clone, Copy)]
+enum Previous {
+    /// The opening delimiter of the current group (or nothing, at the start of the input).
+    Start(Option<Position>),
+    /// A word that the next token can be glued onto if they are adjacent.
+    Word(Position),
+    /// Any other token.
+    Token(Position),
+    /// An escape: spaces after an escape aren't significant, but line breaks are.
+    Escape(Position),
+}
+
+impl Previous {
+    fn end(self) -> Option<Position> {
+        match self {
+            Previous::Start(end) => end,
+            Previous::Word(end) | Previous::Token(end) | Previous::Escape(end) => Some(end),
+        }
+    }
+}
+
+struct Parser {
+    tokens: std::iter::Peekable<proc_macro::token_stream::IntoIter>,
+    pieces: Vec<Piece>,
+    previous: Previous,
+}
+
+impl Parser {
+    fn new(input: TokenStream, previous: Previous) -> Parser {
+        Parser {
+            tokens: input.into_iter().peekable(),
+            pieces: vec![],
+            previous,
+        }
+    }
+
+    fn parse(mut self) -> Result<Vec<Piece>> {
+        self.parse_tokens()?;
+        Ok(self.pieces)
+    }
+
+    fn parse_tokens(&mut self) -> Result<()> {
+        while let Some(token) = self.tokens.next() {
+            match token {
+                TokenTree::Punct(punct) if punct.as_char() == '$' => self.escape(punct.span())?,
+                TokenTree::Group(group) if group.delimiter() == Delimiter::None => {
+                    // Invisible groups come from `macro_rules!` fragments, and don't affect the
+                    // output.
+                    let mut inner = Parser::new(group.stream(), self.previous);
+                    inner.pieces = std::mem::take(&mut self.pieces);
+                    inner.parse_tokens()?;
+
+                    self.pieces = inner.pieces;
+                    self.previous = inner.previous;
+                }
+                TokenTree::Group(group) => self.group(group)?,
+                TokenTree::Literal(literal) => self.literal(literal),
+                TokenTree::Ident(ident) => self.word(ident.to_string(), ident.span()),
+                TokenTree::Punct(punct) => self.word(punct.to_string(), punct.span()),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the layout between the previous token and a token that starts at `start`, and return
+    /// true if the token is adjacent to the previous token.
+    fn layout(&mut self, start: Span, escape: bool) -> bool {
+        let start = Position::start(start);
+
+        let end = match self.previous.end() {
+            Some(end) => end,
+            None => return false,
+        };
+
+        if start.line > end.line {
+            for _ in end.line..start.line {
+                self.pieces.push(Piece::Newline);
+            }
+
+            return false;
+        }
+
+        let spaces = start.column.saturating_sub(end.column);
+
+        if spaces == 0 {
+            return true;
+        }
+
+        if !escape && !matches!(self.previous, Previous::Escape(_)) {
+            self.pieces.push(Piece::Whitespace(" ".repeat(spaces)));
+        }
+
+        false
+    }
+
+    fn word(&mut self, text: String, span: Span) {
+        let glue = matches!(self.previous, Previous::Word(_));
+        let adjacent = self.layout(span, false);
+
+        match self.pieces.last_mut() {
+            Some(Piece::Word(word)) if glue && adjacent => word.push_str(&text),
+            _ => self.pieces.push(Piece::Word(text)),
+        }
+
+        self.previous = Previous::Word(Position::end(span));
+    }
+
+    fn literal(&mut self, literal: Literal) {
+        let text = literal.to_string();
+        let span = literal.span();
+
+        let quote = match text.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => return self.word(text, span),
+        };
+
+        self.layout(span, false);
+        self.pieces.push(Piece::Quoted {
+            quote,
+            inner: text[1..text.len() - 1].to_string(),
+        });
+        self.previous = Previous::Token(Position::end(span));
+    }
+
+    fn group(&mut self, group: Group) -> Result<()> {
+        self.layout(group.span_open(), false);
+
+        let open = Previous::Start(Some(Position::end(group.span_open())));
+        let mut children = Parser::new(group.stream(), open);
+        children.parse_tokens()?;
+        children.layout(group.span_close(), false);
+
+        self.pieces.push(Piece::Delimited {
+            delimiter: group.delimiter(),
+            children: children.pieces,
+        });
+        self.previous = Previous::Token(Position::end(group.span_close()));
+
+        Ok(())
+    }
+
+    fn escape(&mut self, dollar: Span) -> Result<()> {
+        let name = match self.tokens.next() {
+            Some(TokenTree::Ident(ident)) => ident,
+            _ => return Err(error(dollar, "expected an escape name after `$`")),
+        };
+
+        self.layout(dollar, true);
+
+        let args = match self.tokens.peek() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                let group = group.clone();
+                self.tokens.next();
+                Some(group)
+            }
+            _ => None,
+        };
+
+        let end = match &args {
+            Some(args) => args.span_close(),
+            None => name.span(),
+        };
+
+        let args = match args {
+            Some(args) => arguments(args)?,
+            None => vec![],
+        };
+
+        let piece = match (name.to_string().as_str(), &args[..]) {
+            ("ws", [Arg::Int(n)]) => Piece::Whitespace(" ".repeat(*n)),
+            ("ws", [Arg::Str(ws)]) => Piece::Whitespace(ws.clone()),
+            ("nl", []) => Piece::Newline,
+            ("eof", []) => Piece::Eof,
+            ("word", [Arg::Str(word)]) => Piece::Word(word.clone()),
+            ("error", [Arg::Str(text)]) => Piece::Error(text.clone()),
+            ("comment", [Arg::Str(pre), Arg::Str(body)]) => Piece::Comment {
+                pre: pre.clone(),
+                body: body.clone(),
+                post: String::new(),
+            },
+            ("comment", [Arg::Str(pre), Arg::Str(body), Arg::Str(post)]) => Piece::Comment {
+                pre: pre.clone(),
+                body: body.clone(),
+                post: post.clone(),
+            },
+            ("quote", [Arg::Char(quote), Arg::Str(inner)]) => Piece::Quoted {
+                quote: *quote,
+                inner: inner.clone(),
+            },
+            (other, _) => {
+                return Err(error(
+                    name.span(),
+                    format!(
+                        "invalid escape `${}`: expected `$ws(n)`, `$ws(\"..\")`, `$nl`, `$eof`, \
+                         `$word(\"..\")`, `$error(\"..\")`, `$comment(\"pre\", \"body\")`, \
+                         `$comment(\"pre\", \"body\", \"post\")` or `$quote('q', \"..\")`",
+                        other
+                    ),
+                ))
+            }
+        };
+
+        self.pieces.push(piece);
+        self.previous = Previous::Escape(Position::end(end));
+
+        Ok(())
+    }
+}
+
+/// An argument to an escape.
+enum Arg {
+    Int(usize),
+    Str(String),
+    Char(char),
+}
+
+fn arguments(group: Group) -> Result<Vec<Arg>> {
+    let mut args = vec![];
+
+    for token in group.stream() {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => {}
+            TokenTree::Literal(literal) => {
+                let stream = TokenStream::from(TokenTree::Literal(literal));
+                let lit: syn::Lit = syn::parse(stream)?;
+
+                args.push(match lit {
+                    syn::Lit::Int(int) => Arg::Int(int.base10_parse()?),
+                    syn::Lit::Str(string) => Arg::Str(string.value()),
+                    syn::Lit::Char(char) => Arg::Char(char.value()),
+                    other => return Err(syn::Error::new(other.span(), "unexpected literal")),
+                });
+            }
+            other => return Err(error(other.span(), "expected a literal")),
+        }
+    }
+
+    Ok(args)
+}
+
+fn error(span: Span, message: impl std::fmt::Display) -> syn::Error {
+    syn::Error::new(span.into(), message)
+}
+
+/// Generate the expressions for a list of pieces, starting at `offset`.
+fn generate(pieces: &[Piece], offset: &mut usize) -> Vec<proc_macro2::TokenStream> {
+    let leaf = |leaf: proc_macro2::TokenStream, span: proc_macro2::TokenStream| {
+        quote! {
+            ::wyst_lex::wyst_source::Spanned::new(#span, ::wyst_lex::Token::Leaf(::wyst_lex::Leaf::#leaf))
+        }
+    };
+
+    let mut tokens = vec![];
+
+    for piece in pieces {
+        let token = match piece {
+            Piece::Word(word) => leaf(quote! { Word }, consume(offset, word.len())),
+            Piece::Error(text) => leaf(quote! { Error }, consume(offset, text.len())),
+            Piece::Whitespace(ws) => leaf(quote! { Whitespace }, consume(offset, ws.len())),
+            Piece::Newline => leaf(quote! { Newline }, consume(offset, 1)),
+            Piece::Eof => {
+                let at = *offset;
+                leaf(
+                    quote! { EOF },
+                    quote! { ::wyst_lex::wyst_source::Span::eof(#at) },
+                )
+            }
+            Piece::Comment { pre, body, post } => {
+                let start = *offset;
+                consume(offset, pre.len());
+                let body = consume(offset, body.len());
+                consume(offset, post.len());
+                let end = *offset;
+
+                leaf(
+                    quote! { Comment(#body) },
+                    quote! { ::wyst_lex::wyst_source::Span::new(#start, #end) },
+                )
+            }
+            Piece::Quoted { quote: q, inner } => {
+                let start = *offset;
+                consume(offset, q.len_utf8());
+                let inner = consume(offset, inner.len());
+                consume(offset, q.len_utf8());
+                let end = *offset;
+
+                let quote = match q {
+                    '"' => quote! { DoubleQuote },
+                    '\'' => quote! { SingleQuote },
+                    _ => quote! { Backtick },
+                };
+
+                leaf(
+                    quote! { Quoted(::wyst_lex::QuoteResult::new(::wyst_lex::Quote::#quote, #inner)) },
+                    quote! { ::wyst_lex::wyst_source::Span::new(#start, #end) },
+                )
+            }
+            Piece::Delimited {
+                delimiter,
+                children,
+            } => {
+                let start = *offset;
+                *offset += 1;
+                let children = generate(children, offset);
+                *offset += 1;
+                let end = *offset;
+
+                let delimiter = match delimiter {
+                    Delimiter::Parenthesis => quote! { Paren },
+                    Delimiter::Bracket => quote! { Bracket },
+                    Delimiter::Brace => quote! { Brace },
+                    Delimiter::None => unreachable!("BUG: invisible groups are flattened"),
+                };
+
+                quote! {
+                    ::wyst_lex::wyst_source::Spanned::new(
+                        ::wyst_lex::wyst_source::Span::new(#start, #end),
+                        ::wyst_lex::Token::delimited(::wyst_lex::Delimiter::#delimiter, vec![#(#children),*]),
+                    )
+                }
+            }
+        };
+
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+/// Advance `offset` past `len` bytes, and return the span of those bytes.
+fn consume(offset: &mut usize, len: usize) -> proc_macro2::TokenStream {
+    let start = *offset;
+    *offset += len;
+    let end = *offset;
+
+    quote! { ::wyst_lex::wyst_source::Span::new(#start, #end) }
+}
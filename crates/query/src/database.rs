@@ -0,0 +1,518 @@
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use wyst_core::{unit_tests, wyst_copy};
+use wyst_lex::{split_list, FlatToken, ReadResult, Separator, StandardDelegate, TokenTree};
+use wyst_source::{Source, Span, Spanned};
+
+use crate::{
+    hash::ContentHash,
+    stats::{Stage, Stats},
+};
+
+/// The language-specific stages of the pipeline. Lexing and reading token trees are the same for
+/// every language (apart from the delegate), so the database does those itself.
+pub trait Language {
+    type Delegate: for<'source> StandardDelegate<'source>;
+    type Ast;
+    type Hir;
+    type Layout;
+
+    /// The spans of the top-level items in a file, which are parsed, lowered and laid out
+    /// separately. By default, every top-level line is an item (a delimited token that contains
+    /// newlines is still part of a single line).
+    fn items(&self, tree: &ReadResult, source: &Source) -> Vec<Span> {
+        split_list(tree.tokens(), Separator::Newline, source)
+            .items()
+            .iter()
+            .map(|item| item.span())
+            .collect()
+    }
+
+    /// Parse a single item. `source` contains nothing but the item, so spans are relative to the
+    /// start of the item (which is what lets an item that moved reuse its answers).
+    fn parse(&self, tree: &ReadResult, source: &Source) -> Self::Ast;
+
+    fn lower(&self, ast: &Self::Ast, source: &Source) -> Self::Hir;
+
+    fn layout(&self, hir: &Self::Hir) -> Self::Layout;
+}
+
+/// A top-level item in a file.
+#[wyst_copy]
+pub struct Item {
+    span: Span,
+    hash: ContentHash,
+}
+
+impl Item {
+    /// Where the item is in its file.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn hash(&self) -> ContentHash {
+        self.hash
+    }
+}
+
+/// The answers to one stage's queries, by the hash of the contents that they were computed from.
+struct Memo<T> {
+    stage: Stage,
+    entries: HashMap<ContentHash, Rc<T>>,
+}
+
+impl<T> Memo<T> {
+    fn new(stage: Stage) -> Memo<T> {
+        Memo {
+            stage,
+            entries: HashMap::new(),
+        }
+    }
+
+    fn get(&self, hash: ContentHash, stats: &mut Stats) -> Option<Rc<T>> {
+        match self.entries.get(&hash) {
+            Some(value) => {
+                stats.hit(self.stage);
+                Some(value.clone())
+            }
+            None => {
+                stats.miss(self.stage);
+                None
+            }
+        }
+    }
+
+    fn insert(&mut self, hash: ContentHash, value: T) -> Rc<T> {
+        let value = Rc::new(value);
+        self.entries.insert(hash, value.clone());
+        value
+    }
+
+    fn evict(&mut self, hashes: &HashSet<ContentHash>, stats: &mut Stats) {
+        let before = self.entries.len();
+        self.entries.retain(|hash, _| !hashes.contains(hash));
+        stats.evict(self.stage, before - self.entries.len());
+    }
+}
+
+struct File {
+    source: Rc<Source>,
+    hash: ContentHash,
+    /// The hashes of the items that were in the file the last time that its items were queried.
+    items: Vec<ContentHash>,
+}
+
+/// Memoized answers to `lex -> tree -> items -> parse -> HIR -> layout` for a set of files.
+///
+/// Files are replaced wholesale with [Database::set_source]. Answers are keyed by the hash of the
+/// contents that they were computed from, so after an edit, a file is lexed again, but only the
+/// items that changed are parsed, lowered and laid out again. Answers for contents that no file
+/// contains anymore are evicted.
+pub struct Database<L: Language> {
+    language: L,
+    files: HashMap<Utf8PathBuf, File>,
+    lexed: Memo<Vec<Spanned<FlatToken>>>,
+    trees: Memo<ReadResult>,
+    items: Memo<Vec<Item>>,
+    item_sources: HashMap<ContentHash, Rc<Source>>,
+    parsed: Memo<L::Ast>,
+    lowered: Memo<L::Hir>,
+    layouts: Memo<L::Layout>,
+    stats: Stats,
+}
+
+impl<L: Language> Database<L> {
+    pub fn new(language: L) -> Database<L> {
+        Database {
+            language,
+            files: HashMap::new(),
+            lexed: Memo::new(Stage::Lex),
+            trees: Memo::new(Stage::Tree),
+            items: Memo::new(Stage::Items),
+            item_sources: HashMap::new(),
+            parsed: Memo::new(Stage::Parse),
+            lowered: Memo::new(Stage::Hir),
+            layouts: Memo::new(Stage::Layout),
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+
+    /// The number of answers that a stage has cached.
+    pub fn cached(&self, stage: Stage) -> usize {
+        match stage {
+            Stage::Lex => self.lexed.entries.len(),
+            Stage::Tree => self.trees.entries.len(),
+            Stage::Items => self.items.entries.len(),
+            Stage::Parse => self.parsed.entries.len(),
+            Stage::Hir => self.lowered.entries.len(),
+            Stage::Layout => self.layouts.entries.len(),
+        }
+    }
+
+    /// Add a file, or replace the contents of the file with the same name. Returns whether the
+    /// contents changed.
+    pub fn set_source(&mut self, source: Source) -> bool {
+        let hash = ContentHash::of_source(&source);
+        let filename = source.filename().to_path_buf();
+
+        let previous = match self.files.get_mut(&filename) {
+            Some(file) if file.hash == hash => return false,
+            Some(file) => {
+                file.source = Rc::new(source);
+                Some(std::mem::replace(&mut file.hash, hash))
+            }
+            None => {
+                self.files.insert(
+                    filename,
+                    File {
+                        source: Rc::new(source),
+                        hash,
+                        items: vec![],
+                    },
+                );
+                None
+            }
+        };
+
+        // The file's old items stay cached until its new items are queried, since most of them
+        // are usually still there.
+        if let Some(previous) = previous {
+            self.evict_files(Some(previous));
+        }
+
+        true
+    }
+
+    /// Remove a file, evicting anything that only it used. Returns whether the file was there.
+    pub fn remove_source(&mut self, filename: &Utf8Path) -> bool {
+        match self.files.remove(filename) {
+            Some(file) => {
+                self.evict_files(Some(file.hash));
+                self.evict_items(file.items);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn source(&self, filename: &Utf8Path) -> Option<Rc<Source>> {
+        self.files.get(filename).map(|file| file.source.clone())
+    }
+
+    /// The flat tokens in a file.
+    pub fn tokens(&mut self, filename: &Utf8Path) -> Option<Rc<Vec<Spanned<FlatToken>>>> {
+        let (source, hash) = self.file(filename)?;
+
+        if let Some(tokens) = self.lexed.get(hash, &mut self.stats) {
+            return Some(tokens);
+        }
+
+        let tokens = FlatToken::lex_source::<L::Delegate>(source.contents()).collect();
+        Some(self.lexed.insert(hash, tokens))
+    }
+
+    /// The token tree of a file.
+    pub fn tree(&mut self, filename: &Utf8Path) -> Option<Rc<ReadResult>> {
+        let (source, hash) = self.file(filename)?;
+
+        if let Some(tree) = self.trees.get(hash, &mut self.stats) {
+            return Some(tree);
+        }
+
+        let tokens = self.tokens(filename)?;
+        let tree = TokenTree::read(&source, tokens.iter().cloned());
+        Some(self.trees.insert(hash, tree))
+    }
+
+    /// The top-level items in a file (see [Language::items]).
+    pub fn items(&mut self, filename: &Utf8Path) -> Option<Rc<Vec<Item>>> {
+        let (source, hash) = self.file(filename)?;
+
+        let items = match self.items.get(hash, &mut self.stats) {
+            Some(items) => items,
+            None => {
+                let tree = self.tree(filename)?;
+
+                let items = self
+                    .language
+                    .items(&tree, &source)
+                    .into_iter()
+                    .map(|span| Item {
+                        span,
+                        hash: ContentHash::of(source.slice(span)),
+                    })
+                    .collect();
+
+                self.items.insert(hash, items)
+            }
+        };
+
+        // The items' sources may have been evicted even though the file's items are still cached
+        // (when another file with the same contents was removed before this file's items were
+        // queried), so this makes sure that they're all there.
+        for item in items.iter() {
+            self.item_sources.entry(item.hash).or_insert_with(|| {
+                Rc::new(Source::new(
+                    source.filename().to_path_buf(),
+                    source.slice(item.span),
+                ))
+            });
+        }
+
+        let file = self
+            .files
+            .get_mut(filename)
+            .expect("BUG: the file was there a moment ago");
+        let previous = std::mem::replace(
+            &mut file.items,
+            items.iter().map(|item| item.hash).collect(),
+        );
+        self.evict_items(previous);
+
+        Some(items)
+    }
+
+    /// The AST of an item, or `None` if no file contains the item anymore.
+    pub fn parse(&mut self, item: &Item) -> Option<Rc<L::Ast>> {
+        let source = self.item_sources.get(&item.hash)?.clone();
+
+        if let Some(ast) = self.parsed.get(item.hash, &mut self.stats) {
+            return Some(ast);
+        }
+
+        let tree = FlatToken::lex_source::<L::Delegate>(source.contents()).read_tree(&source);
+        let ast = self.language.parse(&tree, &source);
+        Some(self.parsed.insert(item.hash, ast))
+    }
+
+    /// The HIR of an item, or `None` if no file contains the item anymore.
+    pub fn hir(&mut self, item: &Item) -> Option<Rc<L::Hir>> {
+        let source = self.item_sources.get(&item.hash)?.clone();
+
+        if let Some(hir) = self.lowered.get(item.hash, &mut self.stats) {
+            return Some(hir);
+        }
+
+        let ast = self.parse(item)?;
+        let hir = self.language.lower(&ast, &source);
+        Some(self.lowered.insert(item.hash, hir))
+    }
+
+    /// The layout of an item, or `None` if no file contains the item anymore.
+    pub fn layout(&mut self, item: &Item) -> Option<Rc<L::Layout>> {
+        if !self.item_sources.contains_key(&item.hash) {
+            return None;
+        }
+
+        if let Some(layout) = self.layouts.get(item.hash, &mut self.stats) {
+            return Some(layout);
+        }
+
+        let hir = self.hir(item)?;
+        let layout = self.language.layout(&hir);
+        Some(self.layouts.insert(item.hash, layout))
+    }
+
+    /// The layouts of every item in a file.
+    pub fn layout_file(&mut self, filename: &Utf8Path) -> Option<Vec<Rc<L::Layout>>> {
+        let items = self.items(filename)?;
+
+        Some(
+            items
+                .iter()
+                .map(|item| {
+                    self.layout(item)
+                        .expect("BUG: the file's items were just queried")
+                })
+                .collect(),
+        )
+    }
+
+    fn file(&self, filename: &Utf8Path) -> Option<(Rc<Source>, ContentHash)> {
+        self.files
+            .get(filename)
+            .map(|file| (file.source.clone(), file.hash))
+    }
+
+    /// Evict the per-file answers for contents that no file has anymore.
+    fn evict_files(&mut self, hashes: impl IntoIterator<Item = ContentHash>) {
+        let unused: HashSet<ContentHash> = hashes
+            .into_iter()
+            .filter(|hash| !self.files.values().any(|file| file.hash == *hash))
+            .collect();
+
+        if unused.is_empty() {
+            return;
+        }
+
+        self.lexed.evict(&unused, &mut self.stats);
+        self.trees.evict(&unused, &mut self.stats);
+        self.items.evict(&unused, &mut self.stats);
+    }
+
+    /// Evict the per-item answers for items that no file has anymore.
+    fn evict_items(&mut self, hashes: impl IntoIterator<Item = ContentHash>) {
+        let unused: HashSet<ContentHash> = hashes
+            .into_iter()
+            .filter(|hash| !self.files.values().any(|file| file.items.contains(hash)))
+            .collect();
+
+        if unused.is_empty() {
+            return;
+        }
+
+        self.item_sources.retain(|hash, _| !unused.contains(hash));
+        self.parsed.evict(&unused, &mut self.stats);
+        self.lowered.evict(&unused, &mut self.stats);
+        self.layouts.evict(&unused, &mut self.stats);
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, Leaf, Token};
+
+        use crate::stats::StageStats;
+
+        /// A language whose items are lines of words, which are laid out in upper case.
+        struct Words;
+
+        impl Language for Words {
+            type Delegate = DefaultDelegate;
+            type Ast = Vec<String>;
+            type Hir = Vec<String>;
+            type Layout = String;
+
+            fn parse(&self, tree: &ReadResult, source: &Source) -> Vec<String> {
+                tree.tokens()
+                    .iter()
+                    .filter(|token| matches!(token.item(), Token::Leaf(Leaf::Word)))
+                    .map(|token| source.slice(token.span()).to_string())
+                    .collect()
+            }
+
+            fn lower(&self, ast: &Vec<String>, _source: &Source) -> Vec<String> {
+                ast.iter().map(|word| word.to_uppercase()).collect()
+            }
+
+            fn layout(&self, hir: &Vec<String>) -> String {
+                hir.join(" ")
+            }
+        }
+
+        fn layout(db: &mut Database<Words>, filename: &str) -> Vec<String> {
+            db.layout_file(Utf8Path::new(filename))
+                .unwrap()
+                .iter()
+                .map(|layout| layout.to_string())
+                .collect()
+        }
+
+        fn stats(hits: usize, misses: usize, evictions: usize) -> StageStats {
+            StageStats {
+                hits,
+                misses,
+                evictions,
+            }
+        }
+    }),
+    tests(
+        ("memoizing a file", {
+            let mut db = Database::new(Words);
+
+            assert!(db.set_source(Source::new("a.txt", "a b\nc d\n")));
+            assert_eq!(layout(&mut db, "a.txt"), &["A B", "C D"]);
+
+            for stage in Stage::ALL.iter() {
+                let expected = match stage {
+                    Stage::Lex | Stage::Tree | Stage::Items => 1,
+                    _ => 2,
+                };
+
+                assert_eq!(
+                    db.stats().stage(*stage),
+                    stats(0, expected, 0),
+                    "{:?}",
+                    stage
+                );
+            }
+
+            db.reset_stats();
+            assert_eq!(layout(&mut db, "a.txt"), &["A B", "C D"]);
+            assert_eq!(db.stats().stage(Stage::Items), stats(1, 0, 0));
+            assert_eq!(db.stats().stage(Stage::Layout), stats(2, 0, 0));
+            assert_eq!(db.stats().misses(), 0);
+
+            // Setting the same contents again doesn't invalidate anything.
+            assert!(!db.set_source(Source::new("a.txt", "a b\nc d\n")));
+            assert_eq!(db.cached(Stage::Lex), 1);
+        }),
+        ("an edit only recomputes the items that changed", {
+            let mut db = Database::new(Words);
+
+            db.set_source(Source::new("a.txt", "a b\nc d\n"));
+            layout(&mut db, "a.txt");
+            db.reset_stats();
+
+            assert!(db.set_source(Source::new("a.txt", "x\na b\nc e\n")));
+            assert_eq!(layout(&mut db, "a.txt"), &["X", "A B", "C E"]);
+
+            assert_eq!(db.stats().stage(Stage::Lex), stats(0, 1, 1));
+            assert_eq!(db.stats().stage(Stage::Items), stats(0, 1, 1));
+            assert_eq!(db.stats().stage(Stage::Parse), stats(0, 2, 1));
+            assert_eq!(db.stats().stage(Stage::Layout), stats(1, 2, 1));
+
+            // `a b` moved, but its answers are still good.
+            let items = db.items(Utf8Path::new("a.txt")).unwrap();
+            assert_eq!(items[1].span(), Span::new(2, 5));
+            assert_eq!(db.cached(Stage::Layout), 3);
+        }),
+        ("files share answers for the same contents", {
+            let mut db = Database::new(Words);
+
+            db.set_source(Source::new("a.txt", "a b\nc d\n"));
+            db.set_source(Source::new("b.txt", "c d\n"));
+            layout(&mut db, "a.txt");
+            layout(&mut db, "b.txt");
+
+            assert_eq!(db.stats().stage(Stage::Layout), stats(1, 2, 0));
+
+            // `c d` is still in b.txt, so removing a.txt only evicts `a b`.
+            let a_b = db.items(Utf8Path::new("a.txt")).unwrap()[0];
+            assert!(db.remove_source(Utf8Path::new("a.txt")));
+            assert_eq!(db.cached(Stage::Layout), 1);
+            assert_eq!(db.layout(&a_b), None);
+            assert_eq!(layout(&mut db, "b.txt"), &["C D"]);
+        }),
+        (
+            "removing a file with the same contents as a file that wasn't queried yet",
+            {
+                let mut db = Database::new(Words);
+
+                db.set_source(Source::new("a.txt", "x y\n"));
+                db.set_source(Source::new("b.txt", "x y\n"));
+                assert_eq!(layout(&mut db, "a.txt"), &["X Y"]);
+
+                // b.txt's items were never queried, so removing a.txt evicts the item's answers, but
+                // the items of their shared contents are still cached.
+                assert!(db.remove_source(Utf8Path::new("a.txt")));
+                assert_eq!(db.cached(Stage::Items), 1);
+                assert_eq!(db.cached(Stage::Layout), 0);
+
+                assert_eq!(layout(&mut db, "b.txt"), &["X Y"]);
+            }
+        )
+    )
+);
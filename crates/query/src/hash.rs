@@ -0,0 +1,28 @@
+use std::fmt::Display;
+
+use wyst_core::wyst_copy;
+use wyst_source::Source;
+
+/// A hash of the contents of a file or an item. Queries about the same contents have the same
+/// answers, so they're memoized by the hash of what they read rather than by where it came from.
+#[wyst_copy]
+pub struct ContentHash(blake3::Hash);
+
+impl ContentHash {
+    pub fn of(contents: &str) -> ContentHash {
+        ContentHash(blake3::hash(contents.as_bytes()))
+    }
+
+    pub fn of_source(source: &Source) -> ContentHash {
+        ContentHash::of(source.contents())
+    }
+}
+
+impl Display for ContentHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut hash = self.0.to_hex();
+        hash.truncate(7);
+
+        write!(f, "{}", hash.as_ref())
+    }
+}
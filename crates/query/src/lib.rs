@@ -0,0 +1,15 @@
+//! A memoizing query database for editor integrations.
+//!
+//! A [Database] answers questions about a set of files (their tokens, token trees, top-level
+//! items, and the AST, HIR and layout of each item) and remembers the answers, keyed by a hash of
+//! the contents that they were computed from. Replacing a file's contents after an edit only
+//! invalidates the answers for contents that changed, and [Stats] reports how often each stage
+//! was answered from the cache.
+
+mod database;
+mod hash;
+mod stats;
+
+pub use database::{Database, Item, Language};
+pub use hash::ContentHash;
+pub use stats::{Stage, StageStats, Stats};
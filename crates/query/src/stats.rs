@@ -0,0 +1,80 @@
+use wyst_core::wyst_copy;
+
+/// A step in the pipeline that the database memoizes.
+#[wyst_copy]
+pub enum Stage {
+    /// Lexing a file into flat tokens.
+    Lex,
+    /// Reading the flat tokens into a token tree.
+    Tree,
+    /// Splitting the token tree into top-level items.
+    Items,
+    Parse,
+    Hir,
+    Layout,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Lex,
+        Stage::Tree,
+        Stage::Items,
+        Stage::Parse,
+        Stage::Hir,
+        Stage::Layout,
+    ];
+
+    fn index(self) -> usize {
+        match self {
+            Stage::Lex => 0,
+            Stage::Tree => 1,
+            Stage::Items => 2,
+            Stage::Parse => 3,
+            Stage::Hir => 4,
+            Stage::Layout => 5,
+        }
+    }
+}
+
+/// How often a stage was answered from the cache.
+#[wyst_copy]
+#[derive(Default)]
+pub struct StageStats {
+    pub hits: usize,
+    pub misses: usize,
+    /// Cached answers that were thrown away because nothing uses their contents anymore.
+    pub evictions: usize,
+}
+
+/// Hit and miss counts for every [Stage].
+#[wyst_copy]
+#[derive(Default)]
+pub struct Stats {
+    stages: [StageStats; 6],
+}
+
+impl Stats {
+    pub fn stage(&self, stage: Stage) -> StageStats {
+        self.stages[stage.index()]
+    }
+
+    pub fn hits(&self) -> usize {
+        self.stages.iter().map(|stage| stage.hits).sum()
+    }
+
+    pub fn misses(&self) -> usize {
+        self.stages.iter().map(|stage| stage.misses).sum()
+    }
+
+    pub(crate) fn hit(&mut self, stage: Stage) {
+        self.stages[stage.index()].hits += 1;
+    }
+
+    pub(crate) fn miss(&mut self, stage: Stage) {
+        self.stages[stage.index()].misses += 1;
+    }
+
+    pub(crate) fn evict(&mut self, stage: Stage, count: usize) {
+        self.stages[stage.index()].evictions += count;
+    }
+}
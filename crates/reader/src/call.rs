@@ -0,0 +1,627 @@
+use wyst_core::{unit_tests, wyst_data};
+use wyst_lex::{Leaf, LeafKind, Token};
+use wyst_source::{AddSpan, Diagnostic, Offset, Source, Span, Spanned};
+
+use crate::{
+    shape::{Shape, Value},
+    signature::{Flag, Signature},
+};
+
+/// A flag that was passed to a command.
+#[wyst_data]
+pub struct NamedArg {
+    long: String,
+    /// The flag itself (such as `--depth` or the `d` in `-ld`).
+    span: Span,
+    value: Option<Spanned<Value>>,
+}
+
+impl NamedArg {
+    pub fn long(&self) -> &str {
+        &self.long
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn value(&self) -> Option<&Spanned<Value>> {
+        self.value.as_ref()
+    }
+}
+
+/// A command with its arguments, read according to its [Signature].
+#[wyst_data]
+pub struct Call {
+    head: Spanned<String>,
+    positional: Vec<(String, Spanned<Value>)>,
+    rest: Vec<Spanned<Value>>,
+    named: Vec<NamedArg>,
+    span: Span,
+}
+
+impl Call {
+    /// The name of the command.
+    pub fn head(&self) -> &Spanned<String> {
+        &self.head
+    }
+
+    /// A positional argument, by its name in the signature.
+    pub fn positional(&self, name: &str) -> Option<&Spanned<Value>> {
+        self.positional
+            .iter()
+            .find(|(positional, _)| positional == name)
+            .map(|(_, value)| value)
+    }
+
+    /// The positional arguments that were passed, in order.
+    pub fn positionals(&self) -> impl Iterator<Item = &Spanned<Value>> {
+        self.positional.iter().map(|(_, value)| value)
+    }
+
+    pub fn rest(&self) -> &[Spanned<Value>] {
+        &self.rest
+    }
+
+    /// A flag, by its long name.
+    pub fn named(&self, long: &str) -> Option<&NamedArg> {
+        self.named.iter().find(|named| named.long == long)
+    }
+
+    pub fn has_flag(&self, long: &str) -> bool {
+        self.named(long).is_some()
+    }
+
+    pub fn flags(&self) -> &[NamedArg] {
+        &self.named
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The result of [read_call]. The call is always there, even if some of its arguments had errors.
+#[wyst_data]
+pub struct ReadCall {
+    call: Call,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ReadCall {
+    pub fn call(&self) -> &Call {
+        &self.call
+    }
+
+    pub fn into_call(self) -> Call {
+        self.call
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    /// Report `diagnostic` before the diagnostics from reading the arguments.
+    pub(crate) fn with_error(mut self, diagnostic: Diagnostic) -> ReadCall {
+        self.diagnostics.insert(0, diagnostic);
+        self
+    }
+}
+
+/// Read a command according to `signature`. The first significant token is the command's name,
+/// and the rest are its arguments.
+///
+/// A word that starts with `--` is a long flag, and any other word that starts with `-` (and isn't
+/// a number) is one or more short flags. A flag's value is the rest of the word after an `=` (or a
+/// quoted string right after the `=`), or the next argument unless that argument is a flag itself.
+/// After a `--` argument, everything is positional.
+pub fn read_call(signature: &Signature, tokens: &[Spanned<Token>], source: &Source) -> ReadCall {
+    let tokens: Vec<&Spanned<Token>> = tokens
+        .iter()
+        .filter(|token| match token.item() {
+            Token::Leaf(leaf) => !leaf.is_trivia() && *leaf != Leaf::EOF,
+            Token::Delimited(_) => true,
+        })
+        .collect();
+
+    let mut reader = CallReader {
+        signature,
+        source,
+        tokens: tokens.into_iter(),
+        diagnostics: vec![],
+    };
+
+    reader.read()
+}
+
+struct CallReader<'a> {
+    signature: &'a Signature,
+    source: &'a Source,
+    tokens: std::vec::IntoIter<&'a Spanned<Token>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> CallReader<'a> {
+    fn read(&mut self) -> ReadCall {
+        let head = match self.tokens.next() {
+            Some(token) => {
+                let span = token.span();
+                self.source.slice(span).to_string().spanned(span)
+            }
+            None => {
+                let span = Span::eof(Offset::from(0));
+                self.diagnostics
+                    .push(Diagnostic::error(span, "expected a command"));
+                String::new().spanned(span)
+            }
+        };
+
+        let mut end = head.span();
+        let mut positional = vec![];
+        let mut position = 0;
+        let mut rest = vec![];
+        let mut named = vec![];
+        let mut flags_done = false;
+
+        while let Some(token) = self.tokens.next() {
+            end = token.span();
+            let text = self.source.slice(token.span());
+            let is_word = token.item() == &Token::Leaf(Leaf::Word);
+
+            if is_word && !flags_done && text == "--" {
+                flags_done = true;
+            } else if !flags_done && text.starts_with("--") && is_flag(token, self.source) {
+                if let Some(arg) = self.long_flag(token.span(), text, &mut end) {
+                    named.push(arg);
+                }
+            } else if !flags_done && is_flag(token, self.source) {
+                named.extend(self.short_flags(token.span(), text, &mut end));
+            } else if let Some(expected) = self.signature.positionals().get(position) {
+                // An argument with the wrong shape still fills its position, so that the arguments
+                // after it aren't read as the wrong arguments.
+                position += 1;
+
+                match expected.shape().read(token, self.source) {
+                    Ok(value) => positional.push((expected.name().to_string(), value)),
+                    Err(diagnostic) => self.diagnostics.push(diagnostic),
+                }
+            } else if let Some(expected) = self.signature.rest_arguments() {
+                match expected.shape().read(token, self.source) {
+                    Ok(value) => rest.push(value),
+                    Err(diagnostic) => self.diagnostics.push(diagnostic),
+                }
+            } else {
+                self.diagnostics.push(Diagnostic::error(
+                    token.span(),
+                    format!(
+                        "unexpected argument `{}` (`{}` takes {})",
+                        text,
+                        self.signature.name(),
+                        plural(self.signature.positionals().len(), "argument")
+                    ),
+                ));
+            }
+        }
+
+        for missing in self.signature.positionals()[position..]
+            .iter()
+            .filter(|positional| positional.is_required())
+        {
+            self.diagnostics.push(Diagnostic::error(
+                Span::eof(end.end()),
+                format!(
+                    "missing argument `{}` ({})",
+                    missing.name(),
+                    missing.shape().description()
+                ),
+            ));
+        }
+
+        let span = Span::new(head.span().start(), end.end());
+
+        ReadCall {
+            call: Call {
+                head,
+                positional,
+                rest,
+                named,
+                span,
+            },
+            diagnostics: std::mem::take(&mut self.diagnostics),
+        }
+    }
+
+    /// `--long` or `--long=value`. A quoted value right after the `=` (`--long="value"`) is lexed
+    /// as a token of its own, and is read as the value.
+    fn long_flag(&mut self, span: Span, text: &str, end: &mut Span) -> Option<NamedArg> {
+        let start: usize = span.start().into();
+        let (name, inline) = match text[2..].find('=') {
+            Some(eq) => {
+                let value_start = start + 2 + eq + 1;
+                let value = &text[2 + eq + 1..];
+                (
+                    &text[2..2 + eq],
+                    Some((Span::new(value_start, span.end()), value)),
+                )
+            }
+            None => (&text[2..], None),
+        };
+
+        let flag_span = Span::new(start, start + 2 + name.len());
+
+        let flag = match self.signature.flag(name) {
+            Some(flag) => flag,
+            None => {
+                self.unknown_flag(flag_span, &format!("--{}", name));
+                return None;
+            }
+        };
+
+        let value = match (flag.shape(), inline) {
+            (None, None) => None,
+            (None, Some((value_span, _))) => {
+                self.diagnostics.push(Diagnostic::error(
+                    value_span,
+                    format!("`--{}` doesn't take a value", flag.long()),
+                ));
+                None
+            }
+            (Some(shape), Some((_, ""))) => match self.tokens.as_slice().first().copied() {
+                Some(token)
+                    if matches!(token.item(), Token::Leaf(Leaf::Quoted(_)))
+                        && token.span().start() == span.end() =>
+                {
+                    *end = token.span();
+                    self.tokens.next();
+                    self.value(shape.read(token, self.source))
+                }
+                _ => {
+                    self.missing_value(flag, shape, flag_span);
+                    None
+                }
+            },
+            (Some(shape), Some((value_span, value))) => {
+                self.value(shape.read_word(value_span, value))
+            }
+            (Some(shape), None) => self.next_value(flag, shape, flag_span, end),
+        };
+
+        Some(NamedArg {
+            long: flag.long().to_string(),
+            span: flag_span,
+            value,
+        })
+    }
+
+    /// `-s`, or several switches at once (`-la`). Only the last flag in a group can take a value.
+    fn short_flags(&mut self, span: Span, text: &str, end: &mut Span) -> Vec<NamedArg> {
+        let mut named = vec![];
+        let count = text.chars().count() - 1;
+        let start: usize = span.start().into();
+
+        for (index, (offset, c)) in text.char_indices().skip(1).enumerate() {
+            let flag_span = Span::new(start + offset, start + offset + c.len_utf8());
+
+            let flag = match self.signature.short_flag(c) {
+                Some(flag) => flag,
+                None => {
+                    self.unknown_flag(flag_span, &format!("-{}", c));
+                    continue;
+                }
+            };
+
+            let value = match flag.shape() {
+                None => None,
+                Some(shape) if index + 1 == count => self.next_value(flag, shape, flag_span, end),
+                Some(shape) => {
+                    self.diagnostics.push(Diagnostic::error(
+                        flag_span,
+                        format!(
+                            "`-{}` needs a value ({}), so it has to be the last flag in `{}`",
+                            c,
+                            shape.description(),
+                            text
+                        ),
+                    ));
+                    None
+                }
+            };
+
+            named.push(NamedArg {
+                long: flag.long().to_string(),
+                span: flag_span,
+                value,
+            });
+        }
+
+        named
+    }
+
+    /// The value of a flag that takes the next argument as its value.
+    fn next_value(
+        &mut self,
+        flag: &Flag,
+        shape: Shape,
+        flag_span: Span,
+        end: &mut Span,
+    ) -> Option<Spanned<Value>> {
+        match self.tokens.as_slice().first().copied() {
+            Some(token) if !is_flag(token, self.source) => {
+                self.tokens.next();
+                *end = token.span();
+                self.value(shape.read(token, self.source))
+            }
+            _ => {
+                self.missing_value(flag, shape, flag_span);
+                None
+            }
+        }
+    }
+
+    fn missing_value(&mut self, flag: &Flag, shape: Shape, flag_span: Span) {
+        self.diagnostics.push(Diagnostic::error(
+            flag_span,
+            format!(
+                "`--{}` needs a value ({})",
+                flag.long(),
+                shape.description()
+            ),
+        ));
+    }
+
+    fn value(&mut self, value: Result<Spanned<Value>, Diagnostic>) -> Option<Spanned<Value>> {
+        match value {
+            Ok(value) => Some(value),
+            Err(diagnostic) => {
+                self.diagnostics.push(diagnostic);
+                None
+            }
+        }
+    }
+
+    fn unknown_flag(&mut self, span: Span, flag: &str) {
+        self.diagnostics.push(Diagnostic::error(
+            span,
+            format!("unknown flag `{}` for `{}`", flag, self.signature.name()),
+        ));
+    }
+}
+
+/// Whether a word is a group of short flags (rather than a negative number).
+/// Whether `token` is a flag (or a group of short flags), which can't be the value of another flag.
+pub(crate) fn is_flag(token: &Spanned<Token>, source: &Source) -> bool {
+    let text = source.slice(token.span());
+
+    token.item() == &Token::Leaf(Leaf::Word) && (text.starts_with("--") || is_short_flags(text))
+}
+
+pub(crate) fn is_short_flags(text: &str) -> bool {
+    text.len() > 1 && text.starts_with('-') && !is_number(&text[1..])
+}
+
+/// Whether `text` is a decimal number, such as `12`, `.5`, `1.5` or `2e-3`.
+fn is_number(text: &str) -> bool {
+    let digits = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+
+    let (mantissa, exponent) = match text.find(['e', 'E']) {
+        Some(e) => (&text[..e], Some(&text[e + 1..])),
+        None => (text, None),
+    };
+
+    let mantissa = match mantissa.split_once('.') {
+        Some(("", fraction)) => digits(fraction),
+        Some((int, "")) => digits(int),
+        Some((int, fraction)) => digits(int) && digits(fraction),
+        None => digits(mantissa),
+    };
+
+    let exponent = match exponent {
+        Some(exponent) => digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent)),
+        None => true,
+    };
+
+    mantissa && exponent
+}
+
+fn plural(count: usize, noun: &str) -> String {
+    match count {
+        0 => format!("no {}s", noun),
+        1 => format!("1 {}", noun),
+        n => format!("{} {}s", n, noun),
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, FlatToken};
+
+        fn ls() -> Signature {
+            Signature::new("ls")
+                .required("path", Shape::String)
+                .optional("depth", Shape::Int)
+                .switch("all", Some('a'))
+                .switch("long", Some('l'))
+                .named("sort", Shape::Word, Some('s'))
+        }
+
+        fn read(signature: &Signature, text: &str) -> ReadCall {
+            let source = Source::new("<test>", text);
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+
+            read_call(signature, &tokens, &source)
+        }
+
+        fn word(text: &str, start: usize) -> Spanned<Value> {
+            Value::Word(text.to_string()).spanned(Span::new(start, start + text.len()))
+        }
+    }),
+    tests(
+        ("positional arguments and flags", {
+            let read = read(&ls(), "ls src --all -s name 2");
+            assert_eq!(read.diagnostics(), &[]);
+
+            let call = read.call();
+            assert_eq!(call.head().item(), "ls");
+            assert_eq!(
+                call.positional("path"),
+                Some(&Value::String("src".to_string()).spanned(Span::new(3, 6)))
+            );
+            assert_eq!(
+                call.positional("depth"),
+                Some(&Value::Int(2).spanned(Span::new(21, 22)))
+            );
+            assert!(call.has_flag("all"));
+            assert!(!call.has_flag("long"));
+            assert_eq!(
+                call.named("sort").and_then(NamedArg::value),
+                Some(&word("name", 16))
+            );
+            assert_eq!(call.span(), Span::new(0, 22));
+        }),
+        ("inline values, grouped switches and --", {
+            let read = read(&ls(), "ls --sort=size -la -- -src");
+            assert_eq!(read.diagnostics(), &[]);
+
+            let call = read.call();
+            assert_eq!(
+                call.named("sort").and_then(NamedArg::value),
+                Some(&word("size", 10))
+            );
+            assert_eq!(
+                call.flags()
+                    .iter()
+                    .map(|flag| (flag.long(), flag.span()))
+                    .collect::<Vec<_>>(),
+                &[
+                    ("sort", Span::new(3, 9)),
+                    ("long", Span::new(16, 17)),
+                    ("all", Span::new(17, 18))
+                ]
+            );
+            assert_eq!(
+                call.positional("path").map(|path| path.item()),
+                Some(&Value::String("-src".to_string()))
+            );
+        }),
+        ("quoted inline values", {
+            let find = Signature::new("find").named("name", Shape::String, None);
+            let quoted = read(&find, "find --name=\"src\"");
+            assert_eq!(quoted.diagnostics(), &[]);
+            assert_eq!(
+                quoted.call().named("name").and_then(NamedArg::value),
+                Some(&Value::String("src".to_string()).spanned(Span::new(12, 17)))
+            );
+            assert_eq!(quoted.call().span(), Span::new(0, 17));
+
+            // The quoted value has to be right after the `=`.
+            let spaced = read(&find, "find --name= 'x'");
+            assert_eq!(
+                spaced.diagnostics()[0],
+                Diagnostic::error(Span::new(5, 11), "`--name` needs a value (a string)")
+            );
+            assert_eq!(spaced.call().named("name").and_then(NamedArg::value), None);
+        }),
+        ("negative numbers aren't flags", {
+            for number in &["-1", "-2.5", "-.5", "-1.", "-1e3", "-2E-3"] {
+                assert!(!is_short_flags(number), "{}", number);
+            }
+
+            for flags in &["-la", "-inf", "-nan", "-e", "-1x", "-1.2.3"] {
+                assert!(is_short_flags(flags), "{}", flags);
+            }
+        }),
+        ("rest and block arguments", {
+            let each = Signature::new("each")
+                .required("body", Shape::Block)
+                .rest("items", Shape::Int);
+            let read = read(&each, "each { echo } 1 2 3");
+            assert_eq!(read.diagnostics(), &[]);
+
+            let call = read.call();
+            assert!(matches!(
+                call.positional("body").map(|body| body.item()),
+                Some(Value::Block(_))
+            ));
+            assert_eq!(
+                call.rest()
+                    .iter()
+                    .map(|item| item.item().clone())
+                    .collect::<Vec<_>>(),
+                &[Value::Int(1), Value::Int(2), Value::Int(3)]
+            );
+        }),
+        ("errors", {
+            assert_eq!(
+                read(&ls(), "ls --bogus src -x").diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(3, 10), "unknown flag `--bogus` for `ls`"),
+                    Diagnostic::error(Span::new(16, 17), "unknown flag `-x` for `ls`"),
+                ]
+            );
+            assert_eq!(
+                read(&ls(), "ls").diagnostics(),
+                &[Diagnostic::error(
+                    Span::eof(2),
+                    "missing argument `path` (a string)"
+                )]
+            );
+            assert_eq!(
+                read(&ls(), "ls src deep extra").diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(7, 11), "expected an integer, found `deep`"),
+                    Diagnostic::error(
+                        Span::new(12, 17),
+                        "unexpected argument `extra` (`ls` takes 2 arguments)"
+                    ),
+                ]
+            );
+            assert_eq!(
+                read(&ls(), "ls src --sort").diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(7, 13),
+                    "`--sort` needs a value (a word)"
+                )]
+            );
+            assert_eq!(
+                read(&ls(), "ls src --sort=").diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(7, 13),
+                    "`--sort` needs a value (a word)"
+                )]
+            );
+
+            // A flag isn't the value of the flag before it.
+            let sorted = read(&ls(), "ls src --sort --all");
+            assert_eq!(
+                sorted.diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(7, 13),
+                    "`--sort` needs a value (a word)"
+                )]
+            );
+            assert!(sorted.call().named("all").is_some());
+            assert_eq!(
+                read(&ls(), "ls src -s -la").diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(8, 9),
+                    "`--sort` needs a value (a word)"
+                )]
+            );
+            assert_eq!(read(&ls(), "ls src --sort -1").diagnostics(), &[]);
+            assert_eq!(
+                read(&ls(), "ls src --all=yes -sl").diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(13, 16), "`--all` doesn't take a value"),
+                    Diagnostic::error(
+                        Span::new(18, 19),
+                        "`-s` needs a value (a word), so it has to be the last flag in `-sl`"
+                    ),
+                ]
+            );
+        })
+    )
+);
@@ -0,0 +1,392 @@
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_lex::{Delimiter, Leaf, Token};
+use wyst_source::{Offset, Source, Span, Spanned};
+
+use crate::{
+    call::{is_flag, is_short_flags},
+    pipeline::{is_trivia, pieces, Piece},
+    shape::Shape,
+    signature::{Commands, Flag, Signature},
+};
+
+/// What the command at the cursor expects there.
+#[wyst_data]
+pub enum Expected {
+    /// The name of a command.
+    Command,
+    /// A positional argument (or a rest argument), or a flag.
+    Argument { name: String, shape: Shape },
+    /// The value of the named flag.
+    FlagValue { flag: String, shape: Shape },
+    /// Only a flag, because the command's arguments are all filled in.
+    Flag,
+    /// Nothing that can be completed, because the command is unknown or takes no more arguments.
+    Nothing,
+}
+
+#[wyst_copy]
+pub enum CandidateKind {
+    Command,
+    Flag,
+}
+
+/// A suggestion that replaces the text in its span.
+#[wyst_data]
+pub struct Candidate {
+    text: String,
+    kind: CandidateKind,
+    span: Span,
+}
+
+impl Candidate {
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn kind(&self) -> CandidateKind {
+        self.kind
+    }
+
+    /// The span that the candidate replaces: the word at the cursor, or an empty span at the
+    /// cursor if it isn't in a word.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The result of [complete].
+#[wyst_data]
+pub struct Completion {
+    expected: Expected,
+    candidates: Vec<Candidate>,
+}
+
+impl Completion {
+    pub fn expected(&self) -> &Expected {
+        &self.expected
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+}
+
+/// Complete the word at `cursor`, given the token tree of a (possibly partial) source.
+///
+/// The cursor belongs to the command that it's in, looking inside of parenthesized
+/// subexpressions (which may be unclosed), and the words before it are read the same way that
+/// [read_call](crate::read_call) reads them. Command names are suggested for the first word of a
+/// command, and flag names for a word that starts with `-` (or for any word once the command's
+/// arguments are filled in).
+pub fn complete(
+    commands: &Commands,
+    tokens: &[Spanned<Token>],
+    source: &Source,
+    cursor: impl Into<Offset>,
+) -> Completion {
+    let cursor: usize = cursor.into().into();
+    let tokens = innermost(tokens, cursor);
+
+    // The significant tokens of the command at the cursor that come before the token at the
+    // cursor, if there is one.
+    let mut before = vec![];
+    let mut current = None;
+
+    for piece in pieces(tokens, source) {
+        match piece {
+            Piece::Token(token) => {
+                let (start, end) = bounds(token.span());
+
+                if start >= cursor {
+                    break;
+                } else if is_trivia(&token) {
+                    continue;
+                } else if end >= cursor {
+                    current = Some(token);
+                    break;
+                }
+
+                before.push(token);
+            }
+            Piece::Pipe(span) | Piece::Semicolon(span) | Piece::Newline(span) => {
+                if bounds(span).0 >= cursor {
+                    break;
+                }
+
+                before.clear();
+            }
+        }
+    }
+
+    // The part of the word at the cursor that comes before the cursor. A string, block or list at
+    // the cursor has no prefix, and gets no candidates.
+    let (prefix, span) = match &current {
+        Some(token) if token.item() == &Token::Leaf(Leaf::Word) => {
+            let start = bounds(token.span()).0;
+            (
+                Some(&source.slice(token.span())[..cursor - start]),
+                token.span(),
+            )
+        }
+        Some(token) => (None, token.span()),
+        None => (Some(""), Span::eof(cursor)),
+    };
+
+    let (name, args) = match before.split_first() {
+        Some((name, args)) => (source.slice(name.span()), args),
+        None => {
+            let candidates = commands
+                .signatures()
+                .iter()
+                .map(Signature::name)
+                .filter(|name| prefix.is_some_and(|prefix| name.starts_with(prefix)))
+                .map(|name| Candidate {
+                    text: name.to_string(),
+                    kind: CandidateKind::Command,
+                    span,
+                })
+                .collect();
+
+            return Completion {
+                expected: Expected::Command,
+                candidates,
+            };
+        }
+    };
+
+    match commands.get(name) {
+        Some(signature) => complete_argument(signature, args, source, prefix, span),
+        None => Completion {
+            expected: Expected::Nothing,
+            candidates: vec![],
+        },
+    }
+}
+
+fn complete_argument(
+    signature: &Signature,
+    args: &[Spanned<Token>],
+    source: &Source,
+    prefix: Option<&str>,
+    span: Span,
+) -> Completion {
+    let mut position = 0;
+    let mut flags_done = false;
+    let mut pending: Option<&Flag> = None;
+    // The end of a `--flag=` word, which a quoted value can follow.
+    let mut inline_end = None;
+
+    for token in args {
+        let text = source.slice(token.span());
+        let is_word = token.item() == &Token::Leaf(Leaf::Word);
+        let is_inline_value = inline_end.take() == Some(token.span().start())
+            && matches!(token.item(), Token::Leaf(Leaf::Quoted(_)));
+
+        if (pending.take().is_some() && !is_flag(token, source)) || is_inline_value {
+            // The token is the value of the previous flag (a flag can't be the value).
+        } else if is_word && !flags_done && text == "--" {
+            flags_done = true;
+        } else if is_word && !flags_done && text.starts_with("--") {
+            if text.ends_with('=') {
+                inline_end = Some(token.span().end());
+            } else if !text.contains('=') {
+                pending = signature.flag(&text[2..]);
+            }
+        } else if is_word && !flags_done && is_short_flags(text) {
+            pending = text.chars().last().and_then(|c| signature.short_flag(c));
+        } else {
+            position += 1;
+        }
+
+        pending = pending.filter(|flag| !flag.is_switch());
+    }
+
+    let value_flag = match (pending, prefix) {
+        (Some(flag), _) => Some(flag),
+        (None, Some(prefix)) if !flags_done && prefix.starts_with("--") => prefix[2..]
+            .find('=')
+            .and_then(|equals| signature.flag(&prefix[2..2 + equals]))
+            .filter(|flag| !flag.is_switch()),
+        _ => None,
+    };
+
+    if let Some(flag) = value_flag {
+        return Completion {
+            expected: Expected::FlagValue {
+                flag: flag.long().to_string(),
+                shape: flag.shape().expect("BUG: the flag isn't a switch"),
+            },
+            candidates: vec![],
+        };
+    }
+
+    let expected = match signature
+        .positionals()
+        .get(position)
+        .or_else(|| signature.rest_arguments())
+    {
+        Some(positional) => Expected::Argument {
+            name: positional.name().to_string(),
+            shape: positional.shape(),
+        },
+        None if flags_done => Expected::Nothing,
+        None => Expected::Flag,
+    };
+
+    let candidates = match prefix {
+        Some(prefix) if !flags_done && (prefix.starts_with('-') || expected == Expected::Flag) => {
+            signature
+                .flags()
+                .iter()
+                .map(|flag| format!("--{}", flag.long()))
+                .filter(|text| text.starts_with(prefix))
+                .map(|text| Candidate {
+                    text,
+                    kind: CandidateKind::Flag,
+                    span,
+                })
+                .collect()
+        }
+        _ => vec![],
+    };
+
+    Completion {
+        expected,
+        candidates,
+    }
+}
+
+/// The children of the innermost parentheses that contain the cursor, or `tokens` if no
+/// parentheses do. The cursor is inside of unclosed parentheses at the end of their span.
+fn innermost(tokens: &[Spanned<Token>], cursor: usize) -> &[Spanned<Token>] {
+    for token in tokens {
+        if let Token::Delimited(delimited) = token.item() {
+            let (start, end) = bounds(token.span());
+
+            if delimited.delimiter() == Delimiter::Paren
+                && start < cursor
+                && (cursor < end || (cursor == end && !delimited.is_closed()))
+            {
+                return innermost(delimited.children(), cursor);
+            }
+        }
+    }
+
+    tokens
+}
+
+fn bounds(span: Span) -> (usize, usize) {
+    (span.start().into(), span.end().into())
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, FlatToken};
+
+        fn commands() -> Commands {
+            Commands::new()
+                .command(
+                    Signature::new("ls")
+                        .optional("path", Shape::String)
+                        .switch("all", Some('a'))
+                        .named("sort", Shape::Word, Some('s')),
+                )
+                .command(Signature::new("let").required("name", Shape::Word))
+                .command(Signature::new("count"))
+        }
+
+        /// Complete at the `^` in `text`, which is removed.
+        fn complete_at(text: &str) -> Completion {
+            let cursor = text.find('^').expect("the text has a cursor");
+            let text = text.replace('^', "");
+            let source = Source::new("<test>", text);
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+
+            complete(&commands(), &tokens, &source, cursor)
+        }
+
+        fn texts(completion: &Completion) -> Vec<&str> {
+            completion
+                .candidates()
+                .iter()
+                .map(Candidate::text)
+                .collect()
+        }
+    }),
+    tests(
+        ("command names", {
+            let completion = complete_at("l^");
+            assert_eq!(completion.expected(), &Expected::Command);
+            assert_eq!(texts(&completion), &["ls", "let"]);
+            assert_eq!(completion.candidates()[0].span(), Span::new(0, 1));
+
+            let completion = complete_at("ls src | co^unt x");
+            assert_eq!(texts(&completion), &["count"]);
+            assert_eq!(completion.candidates()[0].span(), Span::new(9, 14));
+
+            let completion = complete_at("ls (^");
+            assert_eq!(texts(&completion), &["ls", "let", "count"]);
+            assert_eq!(completion.candidates()[0].span(), Span::eof(4));
+
+            let completion = complete_at("ls (l^) --all");
+            assert_eq!(texts(&completion), &["ls", "let"]);
+
+            // The inner parentheses are closed, but the outer ones aren't.
+            assert_eq!(complete_at("ls ((ls)^").expected(), &Expected::Command);
+        }),
+        ("flags and arguments", {
+            let completion = complete_at("ls --s^");
+            assert_eq!(
+                completion.expected(),
+                &Expected::Argument {
+                    name: "path".to_string(),
+                    shape: Shape::String
+                }
+            );
+            assert_eq!(texts(&completion), &["--sort"]);
+            assert_eq!(completion.candidates()[0].span(), Span::new(3, 6));
+
+            let completion = complete_at("ls -a src ^");
+            assert_eq!(completion.expected(), &Expected::Flag);
+            assert_eq!(texts(&completion), &["--all", "--sort"]);
+
+            let completion = complete_at("ls src -- ^");
+            assert_eq!(completion.expected(), &Expected::Nothing);
+            assert_eq!(texts(&completion), Vec::<&str>::new());
+
+            let completion = complete_at("tally ^");
+            assert_eq!(completion.expected(), &Expected::Nothing);
+        }),
+        ("flag values", {
+            let flag_value = Expected::FlagValue {
+                flag: "sort".to_string(),
+                shape: Shape::Word,
+            };
+
+            assert_eq!(complete_at("ls --sort ^").expected(), &flag_value);
+            assert_eq!(complete_at("ls -as n^").expected(), &flag_value);
+            assert_eq!(complete_at("ls --sort=n^").expected(), &flag_value);
+            assert_eq!(
+                complete_at("ls --sort='name' ^").expected(),
+                &Expected::Argument {
+                    name: "path".to_string(),
+                    shape: Shape::String
+                }
+            );
+            assert_eq!(
+                complete_at("ls --sort name ^").expected(),
+                &Expected::Argument {
+                    name: "path".to_string(),
+                    shape: Shape::String
+                }
+            );
+            assert_eq!(
+                complete_at("ls --sort --all ^").expected(),
+                &Expected::Argument {
+                    name: "path".to_string(),
+                    shape: Shape::String
+                }
+            );
+        })
+    )
+);
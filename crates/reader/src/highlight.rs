@@ -0,0 +1,317 @@
+use std::error::Error;
+
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_lex::{Leaf, Token};
+use wyst_source::{AddSpan, Source, Span, Spanned};
+use wyst_style::{emit_styled, PlainStyle, PortableColor, PortableStyle, Print, Style};
+
+use crate::{
+    call::is_short_flags,
+    pipeline::{read_pipelines, Command, CommandList},
+    shape::Value,
+    signature::Commands,
+};
+
+/// The meaning of a span of source, for highlighting.
+#[wyst_copy]
+pub enum Highlight {
+    /// The name of a command.
+    Command,
+    /// A flag (`--long` or a group of short flags).
+    Flag,
+    /// A variable (`$name`).
+    Variable,
+    String,
+    /// A `|` or a `;` between commands.
+    Operator,
+    /// Anything that was reported as an error, or that the lexer couldn't read.
+    Error,
+}
+
+/// The highlighted spans of a source, from [highlight].
+#[wyst_data]
+pub struct Highlights {
+    spans: Vec<Spanned<Highlight>>,
+}
+
+impl Highlights {
+    /// The highlighted spans. A later span takes precedence over an earlier one that it overlaps,
+    /// except that errors take precedence over everything else.
+    pub fn spans(&self) -> &[Spanned<Highlight>] {
+        &self.spans
+    }
+
+    /// Print all of `source`, styling the highlighted spans and leaving the rest of the text
+    /// (including whitespace, comments and anything that wasn't read) in the normal style.
+    pub fn render<P>(&self, source: &Source, print: &mut P) -> Result<(), Box<dyn Error>>
+    where
+        P: Print,
+        P::Style: HighlightStyle,
+    {
+        let text = source.contents();
+        let mut styles = vec![P::Style::normal(); text.len()];
+
+        let (errors, others): (Vec<_>, Vec<_>) = self
+            .spans
+            .iter()
+            .partition(|span| *span.item() == Highlight::Error);
+
+        for span in others.into_iter().chain(errors) {
+            let (start, end): (usize, usize) =
+                (span.span().start().into(), span.span().end().into());
+
+            for slot in &mut styles[start..end] {
+                *slot = P::Style::highlight(*span.item());
+            }
+        }
+
+        emit_styled(print, text, &styles)
+    }
+}
+
+/// The styles that [Highlights::render] uses for each [Highlight].
+pub trait HighlightStyle: Style {
+    fn highlight(highlight: Highlight) -> Self;
+}
+
+impl HighlightStyle for PortableStyle {
+    fn highlight(highlight: Highlight) -> Self {
+        match highlight {
+            Highlight::Command => PortableStyle::fg(PortableColor::LightBlue).with_bold_hint(),
+            Highlight::Flag => PortableStyle::fg(PortableColor::LightCyan),
+            Highlight::Variable => PortableStyle::fg(PortableColor::LightMagenta),
+            Highlight::String => PortableStyle::fg(PortableColor::LightGreen),
+            Highlight::Operator => PortableStyle::fg(PortableColor::LightYellow),
+            Highlight::Error => {
+                PortableStyle::fg(PortableColor::White).with_bg(PortableColor::DarkRed)
+            }
+        }
+    }
+}
+
+impl HighlightStyle for PlainStyle {
+    fn highlight(_highlight: Highlight) -> Self {
+        PlainStyle
+    }
+}
+
+/// Highlight the token tree of a source by what its parts mean: each command is read into
+/// pipelines and then with its signature (see [Commands::read]), so that a word is highlighted
+/// as a command, a flag or a value according to where it is. Strings and variables inside of
+/// blocks and lists are highlighted by how they look, since their meaning depends on the
+/// command they're passed to.
+pub fn highlight(commands: &Commands, tokens: &[Spanned<Token>], source: &Source) -> Highlights {
+    let read = read_pipelines(tokens, source);
+
+    let mut highlighter = Highlighter {
+        commands,
+        source,
+        spans: vec![],
+    };
+
+    highlighter.list(read.list());
+    highlighter.error_tokens(tokens);
+
+    for diagnostic in read.diagnostics() {
+        highlighter.push(diagnostic.span(), Highlight::Error);
+    }
+
+    Highlights {
+        spans: highlighter.spans,
+    }
+}
+
+struct Highlighter<'a> {
+    commands: &'a Commands,
+    source: &'a Source,
+    spans: Vec<Spanned<Highlight>>,
+}
+
+impl<'a> Highlighter<'a> {
+    fn list(&mut self, list: &CommandList) {
+        for pipeline in list.pipelines() {
+            for pipe in pipeline.pipes() {
+                self.push(*pipe, Highlight::Operator);
+            }
+
+            for command in pipeline.commands() {
+                self.command(command);
+            }
+        }
+
+        for separator in list.separators() {
+            self.push(*separator, Highlight::Operator);
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        let read = self.commands.read(command, self.source);
+
+        if command.name(self.source).is_some() {
+            self.push(command.tokens()[0].span(), Highlight::Command);
+        }
+
+        let mut flags_done = false;
+
+        for token in &command.tokens()[1..] {
+            match token.item() {
+                Token::Leaf(Leaf::Word) if !flags_done => {
+                    let text = self.source.slice(token.span());
+                    let start: usize = token.span().start().into();
+
+                    if text == "--" {
+                        flags_done = true;
+                    } else if text.starts_with("--") {
+                        let end = text.find('=').unwrap_or(text.len());
+                        self.push(Span::new(start, start + end), Highlight::Flag);
+                    } else if is_short_flags(text) {
+                        self.push(token.span(), Highlight::Flag);
+                    }
+                }
+                Token::Delimited(delimited) => self.lexical(delimited.children()),
+                _ => {}
+            }
+        }
+
+        // Values come after flags, so that a value that looks like a flag isn't highlighted as one.
+        let call = read.call();
+        let flag_values = call.flags().iter().filter_map(|flag| flag.value());
+
+        for value in call.positionals().chain(call.rest()).chain(flag_values) {
+            match value.item() {
+                Value::String(_) => self.push(value.span(), Highlight::String),
+                Value::Variable(_) => self.push(value.span(), Highlight::Variable),
+                _ => {}
+            }
+        }
+
+        for diagnostic in read.diagnostics() {
+            self.push(diagnostic.span(), Highlight::Error);
+        }
+
+        for subexpression in command.subexpressions() {
+            self.list(subexpression.list());
+        }
+    }
+
+    /// Highlight strings and variables by how they look.
+    fn lexical(&mut self, tokens: &[Spanned<Token>]) {
+        for token in tokens {
+            match token.item() {
+                Token::Leaf(Leaf::Quoted(_)) => self.push(token.span(), Highlight::String),
+                Token::Leaf(Leaf::Word) if self.source.slice(token.span()).starts_with('$') => {
+                    self.push(token.span(), Highlight::Variable)
+                }
+                Token::Delimited(delimited) => self.lexical(delimited.children()),
+                _ => {}
+            }
+        }
+    }
+
+    fn error_tokens(&mut self, tokens: &[Spanned<Token>]) {
+        for token in tokens {
+            match token.item() {
+                Token::Leaf(Leaf::Error) => self.push(token.span(), Highlight::Error),
+                Token::Delimited(delimited) => self.error_tokens(delimited.children()),
+                _ => {}
+            }
+        }
+    }
+
+    /// Add a highlight, unless its span is empty (such as the span of a missing argument).
+    fn push(&mut self, span: Span, highlight: Highlight) {
+        if span.start() != span.end() {
+            self.spans.push(highlight.spanned(span));
+        }
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, FlatToken};
+        use wyst_style::Indent;
+
+        use crate::{Shape, Signature};
+
+        /// Highlight `text` and render it, marking each highlighted span with the first letter
+        /// of its class (such as `{c:ls}` for a command).
+        fn render(text: &str) -> String {
+            let commands = Commands::new()
+                .command(
+                    Signature::new("ls")
+                        .optional("path", Shape::String)
+                        .switch("all", Some('a'))
+                        .switch("long", Some('l'))
+                        .named("sort", Shape::Word, Some('s')),
+                )
+                .command(Signature::new("each").required("block", Shape::Block))
+                .command(Signature::new("echo").rest("values", Shape::Any))
+                .command(Signature::new("take").required("count", Shape::Int));
+            let source = Source::new("<test>", text);
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+
+            let mut print = Recorder::default();
+            highlight(&commands, &tokens, &source)
+                .render(&source, &mut print)
+                .unwrap();
+            print.out
+        }
+
+        #[derive(Debug, Default)]
+        struct Recorder {
+            out: String,
+        }
+
+        impl Print for Recorder {
+            type Style = PortableStyle;
+
+            fn emit_text(&mut self, text: &str, style: Self::Style) -> Result<(), Box<dyn Error>> {
+                let marker = [
+                    (Highlight::Command, 'c'),
+                    (Highlight::Flag, 'f'),
+                    (Highlight::Variable, 'v'),
+                    (Highlight::String, 's'),
+                    (Highlight::Operator, 'o'),
+                    (Highlight::Error, 'e'),
+                ]
+                .iter()
+                .find(|(highlight, _)| PortableStyle::highlight(*highlight) == style)
+                .map(|(_, marker)| *marker);
+
+                match marker {
+                    Some(marker) => self.out.push_str(&format!("{{{}:{}}}", marker, text)),
+                    None => self.out.push_str(text),
+                }
+
+                Ok(())
+            }
+
+            fn emit_break(&mut self, _indent: Indent<'_>) -> Result<(), Box<dyn Error>> {
+                self.out.push('\n');
+                Ok(())
+            }
+        }
+    }),
+    tests(
+        ("commands, flags and values", {
+            assert_eq!(
+                render("ls -al --sort=name 'src' |echo $x (ls) ; each { echo 'y' $z }\n"),
+                "{c:ls} {f:-al} {f:--sort}=name {s:'src'} {o:|}{c:echo} {v:$x} ({c:ls}) {o:;} \
+                 {c:each} { echo {s:'y'} {v:$z} }\n"
+            );
+        }),
+        ("variables in place of other shapes", {
+            assert_eq!(
+                render("take $n; ls $home --sort=$key; each $block"),
+                "{c:take} {v:$n}{o:;} {c:ls} {v:$home} {f:--sort}={v:$key}{o:;} {c:each} \
+                 {e:$block}"
+            );
+        }),
+        ("errors", {
+            assert_eq!(
+                render("ls --bogus a b | \n  | tally 'x"),
+                "{c:ls} {e:--bogus} {s:a} {e:b} {o:|} \n  {e:|} {e:tally} {s:'x}"
+            );
+        })
+    )
+);
@@ -0,0 +1,28 @@
+//! Shape-directed reading for command-style syntax.
+//!
+//! A command declares a [Signature] of positional arguments, flags and rest arguments, each with
+//! a [Shape]. [read_call] reads the tokens of a command (the output of `wyst-lex`) into a typed
+//! [Call] according to its signature, reporting unknown flags, missing arguments and arguments of
+//! the wrong shape with their spans.
+//!
+//! [read_pipelines] splits a token tree into `a | b` pipelines and `;`- or newline-separated
+//! command lists, with parenthesized subexpressions, and [Commands] reads each of the commands
+//! with the signature of the command it names.
+//!
+//! [complete] uses the same signatures to suggest command names and flags at a cursor, and to
+//! report the shape that's expected there, and [highlight] uses them to highlight a source by
+//! what its words mean rather than how they look.
+
+mod call;
+mod completion;
+mod highlight;
+mod pipeline;
+mod shape;
+mod signature;
+
+pub use call::{read_call, Call, NamedArg, ReadCall};
+pub use completion::{complete, Candidate, CandidateKind, Completion, Expected};
+pub use highlight::{highlight, Highlight, HighlightStyle, Highlights};
+pub use pipeline::{read_pipelines, Command, CommandList, Pipeline, ReadPipelines, Subexpression};
+pub use shape::{Shape, Value};
+pub use signature::{Commands, Flag, Positional, Signature};
@@ -0,0 +1,482 @@
+use wyst_core::{unit_tests, wyst_data};
+use wyst_lex::{Delimiter, Leaf, LeafKind, Token};
+use wyst_source::{AddSpan, Diagnostic, Source, Span, Spanned};
+
+use crate::{call::ReadCall, signature::Signature};
+
+/// A command in a pipeline, before it's read according to its signature.
+#[wyst_data]
+pub struct Command {
+    /// The command's tokens, from its first significant token to its last one. Words that
+    /// contained a `|` or a `;` are split into the words around them.
+    tokens: Vec<Spanned<Token>>,
+    subexpressions: Vec<Subexpression>,
+    span: Span,
+}
+
+impl Command {
+    pub fn tokens(&self) -> &[Spanned<Token>] {
+        &self.tokens
+    }
+
+    /// The command's name, if it starts with a word.
+    pub fn name<'a>(&self, source: &'a Source) -> Option<&'a str> {
+        match self.tokens.first() {
+            Some(token) if token.item() == &Token::Leaf(Leaf::Word) => {
+                Some(source.slice(token.span()))
+            }
+            _ => None,
+        }
+    }
+
+    /// The parenthesized command lists in the command's arguments (or its name).
+    pub fn subexpressions(&self) -> &[Subexpression] {
+        &self.subexpressions
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Read the command according to `signature`.
+    pub fn read(&self, signature: &Signature, source: &Source) -> ReadCall {
+        crate::call::read_call(signature, &self.tokens, source)
+    }
+}
+
+/// Commands separated by `|`.
+#[wyst_data]
+pub struct Pipeline {
+    commands: Vec<Command>,
+    pipes: Vec<Span>,
+    span: Span,
+}
+
+impl Pipeline {
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    /// The spans of the `|`s.
+    pub fn pipes(&self) -> &[Span] {
+        &self.pipes
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Pipelines separated by `;` or newlines.
+#[wyst_data]
+pub struct CommandList {
+    pipelines: Vec<Pipeline>,
+    separators: Vec<Span>,
+}
+
+impl CommandList {
+    pub fn pipelines(&self) -> &[Pipeline] {
+        &self.pipelines
+    }
+
+    /// The spans of the `;`s (but not the newlines) between pipelines.
+    pub fn separators(&self) -> &[Span] {
+        &self.separators
+    }
+
+    /// Every command in the list, including the commands in subexpressions, in source order
+    /// (a command comes before the commands in its subexpressions).
+    pub fn commands(&self) -> Vec<&Command> {
+        let mut commands = vec![];
+
+        for command in self
+            .pipelines
+            .iter()
+            .flat_map(|pipeline| &pipeline.commands)
+        {
+            commands.push(command);
+
+            for subexpression in &command.subexpressions {
+                commands.extend(subexpression.list.commands());
+            }
+        }
+
+        commands
+    }
+}
+
+/// A command list in parentheses.
+#[wyst_data]
+pub struct Subexpression {
+    list: CommandList,
+    span: Span,
+}
+
+impl Subexpression {
+    pub fn list(&self) -> &CommandList {
+        &self.list
+    }
+
+    /// The span of the whole delimited token, including the parentheses.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// The result of [read_pipelines].
+#[wyst_data]
+pub struct ReadPipelines {
+    list: CommandList,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl ReadPipelines {
+    pub fn list(&self) -> &CommandList {
+        &self.list
+    }
+
+    pub fn into_list(self) -> CommandList {
+        self.list
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+}
+
+/// Split a token tree into pipelines of commands.
+///
+/// Pipelines end at a `;` or a newline (unless the newline comes right after a `|`), and the
+/// commands in a pipeline are separated by `|`. Delimited tokens are never split, but the
+/// children of parentheses are read as command lists of their own (see [Command::subexpressions]).
+/// Quoted strings are single tokens, so a `|` or a `;` inside of one is just part of the string.
+pub fn read_pipelines(tokens: &[Spanned<Token>], source: &Source) -> ReadPipelines {
+    let mut diagnostics = vec![];
+    let list = read_list(tokens, source, &mut diagnostics);
+
+    ReadPipelines { list, diagnostics }
+}
+
+pub(crate) enum Piece {
+    Token(Spanned<Token>),
+    Pipe(Span),
+    Semicolon(Span),
+    Newline(Span),
+}
+
+fn read_list(
+    tokens: &[Spanned<Token>],
+    source: &Source,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> CommandList {
+    let mut reader = ListReader {
+        source,
+        diagnostics,
+        pipelines: vec![],
+        separators: vec![],
+        commands: vec![],
+        pipes: vec![],
+        current: vec![],
+        after_pipe: false,
+    };
+
+    for piece in pieces(tokens, source) {
+        match piece {
+            Piece::Token(token) => {
+                if !is_trivia(&token) {
+                    reader.after_pipe = false;
+                }
+
+                reader.current.push(token);
+            }
+            Piece::Pipe(span) => {
+                if !reader.finish_command() {
+                    reader
+                        .diagnostics
+                        .push(Diagnostic::error(span, "expected a command before `|`"));
+                }
+
+                reader.pipes.push(span);
+                reader.after_pipe = true;
+            }
+            Piece::Semicolon(span) => {
+                reader.finish_pipeline();
+                reader.separators.push(span);
+            }
+            // A pipeline can continue on the next line after a `|`.
+            Piece::Newline(_) if reader.after_pipe => {}
+            Piece::Newline(_) => reader.finish_pipeline(),
+        }
+    }
+
+    reader.finish_pipeline();
+
+    CommandList {
+        pipelines: reader.pipelines,
+        separators: reader.separators,
+    }
+}
+
+struct ListReader<'a> {
+    source: &'a Source,
+    diagnostics: &'a mut Vec<Diagnostic>,
+    pipelines: Vec<Pipeline>,
+    separators: Vec<Span>,
+    commands: Vec<Command>,
+    pipes: Vec<Span>,
+    current: Vec<Spanned<Token>>,
+    after_pipe: bool,
+}
+
+impl<'a> ListReader<'a> {
+    /// Finish the current command, returning false if it was empty.
+    fn finish_command(&mut self) -> bool {
+        let tokens = std::mem::take(&mut self.current);
+
+        let start = match tokens.iter().position(|token| !is_trivia(token)) {
+            Some(start) => start,
+            None => return false,
+        };
+        let end = tokens
+            .iter()
+            .rposition(|token| !is_trivia(token))
+            .expect("BUG: there's a significant token");
+        let tokens = tokens[start..=end].to_vec();
+
+        let subexpressions = tokens
+            .iter()
+            .filter_map(|token| match token.item() {
+                Token::Delimited(delimited) if delimited.delimiter() == Delimiter::Paren => {
+                    Some(Subexpression {
+                        list: read_list(delimited.children(), self.source, self.diagnostics),
+                        span: token.span(),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+
+        let span = Span::new(
+            tokens[0].span().start(),
+            tokens[tokens.len() - 1].span().end(),
+        );
+
+        self.commands.push(Command {
+            tokens,
+            subexpressions,
+            span,
+        });
+
+        true
+    }
+
+    fn finish_pipeline(&mut self) {
+        let finished = self.finish_command();
+
+        if !finished && self.after_pipe {
+            let pipe = self.pipes[self.pipes.len() - 1];
+            self.diagnostics.push(Diagnostic::error(
+                Span::eof(pipe.end()),
+                "expected a command after `|`",
+            ));
+        }
+
+        self.after_pipe = false;
+
+        let commands = std::mem::take(&mut self.commands);
+        let pipes = std::mem::take(&mut self.pipes);
+
+        if let (Some(first), Some(last)) = (commands.first(), commands.last()) {
+            let span = Span::new(first.span.start(), last.span.end());
+            self.pipelines.push(Pipeline {
+                commands,
+                pipes,
+                span,
+            });
+        }
+    }
+}
+
+pub(crate) fn is_trivia(token: &Spanned<Token>) -> bool {
+    match token.item() {
+        Token::Leaf(leaf) => leaf.is_trivia() || *leaf == Leaf::EOF,
+        Token::Delimited(_) => false,
+    }
+}
+
+/// The tokens, with `|`s, `;`s and newlines pulled out (including out of the middle of words).
+pub(crate) fn pieces(tokens: &[Spanned<Token>], source: &Source) -> Vec<Piece> {
+    let mut pieces = vec![];
+
+    for token in tokens {
+        match token.item() {
+            Token::Leaf(Leaf::Newline) => pieces.push(Piece::Newline(token.span())),
+            Token::Leaf(Leaf::EOF) => {}
+            Token::Leaf(Leaf::Word) => {
+                let start: usize = token.span().start().into();
+                let text = source.slice(token.span());
+                let mut word_start = 0;
+
+                for (offset, c) in text.char_indices() {
+                    if c != '|' && c != ';' {
+                        continue;
+                    }
+
+                    if word_start < offset {
+                        pieces.push(Piece::Token(
+                            Token::Leaf(Leaf::Word)
+                                .spanned(Span::new(start + word_start, start + offset)),
+                        ));
+                    }
+
+                    let span = Span::new(start + offset, start + offset + 1);
+                    pieces.push(if c == '|' {
+                        Piece::Pipe(span)
+                    } else {
+                        Piece::Semicolon(span)
+                    });
+
+                    word_start = offset + 1;
+                }
+
+                if word_start < text.len() {
+                    pieces.push(Piece::Token(
+                        Token::Leaf(Leaf::Word)
+                            .spanned(Span::new(start + word_start, start + text.len())),
+                    ));
+                }
+            }
+            _ => pieces.push(Piece::Token(token.clone())),
+        }
+    }
+
+    pieces
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, FlatToken};
+
+        fn read(source: &Source) -> ReadPipelines {
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(source).into_tokens();
+            read_pipelines(&tokens, source)
+        }
+
+        /// The pipelines as text, with each command's text in brackets.
+        fn describe(list: &CommandList, source: &Source) -> Vec<String> {
+            list.pipelines()
+                .iter()
+                .map(|pipeline| {
+                    pipeline
+                        .commands()
+                        .iter()
+                        .map(|command| format!("[{}]", source.slice(command.span())))
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                })
+                .collect()
+        }
+    }),
+    tests(
+        ("pipelines and command lists", {
+            let source = Source::new(
+                "<test>",
+                "ls src | where size>10|sort; echo hi\necho 'a|b' |\n  count\n\n",
+            );
+            let read = read(&source);
+
+            assert_eq!(read.diagnostics(), &[]);
+            assert_eq!(
+                describe(read.list(), &source),
+                &[
+                    "[ls src] | [where size>10] | [sort]",
+                    "[echo hi]",
+                    "[echo 'a|b'] | [count]"
+                ]
+            );
+
+            let first = &read.list().pipelines()[0];
+            assert_eq!(first.span(), Span::new(0, 27));
+            assert_eq!(first.pipes(), &[Span::new(7, 8), Span::new(22, 23)]);
+            assert_eq!(read.list().separators(), &[Span::new(27, 28)]);
+
+            // `sort` was split out of `10|sort;`.
+            let sort = &first.commands()[2];
+            assert_eq!(sort.name(&source), Some("sort"));
+            assert_eq!(sort.tokens().len(), 1);
+        }),
+        ("subexpressions", {
+            let source = Source::new("<test>", "echo (ls | count) (pwd)");
+            let read = read(&source);
+
+            assert_eq!(read.diagnostics(), &[]);
+
+            let echo = &read.list().pipelines()[0].commands()[0];
+            assert_eq!(
+                echo.subexpressions()
+                    .iter()
+                    .map(|subexpression| describe(subexpression.list(), &source))
+                    .collect::<Vec<_>>(),
+                &[vec!["[ls] | [count]"], vec!["[pwd]"]]
+            );
+            assert_eq!(echo.subexpressions()[0].span(), Span::new(5, 17));
+            assert_eq!(
+                read.list()
+                    .commands()
+                    .iter()
+                    .map(|command| command.name(&source).unwrap())
+                    .collect::<Vec<_>>(),
+                &["echo", "ls", "count", "pwd"]
+            );
+        }),
+        ("empty commands", {
+            let source = Source::new("<test>", "| ls || sort |\n;; echo (a |)");
+            let read = read(&source);
+
+            assert_eq!(
+                read.diagnostics(),
+                &[
+                    Diagnostic::error(Span::new(0, 1), "expected a command before `|`"),
+                    Diagnostic::error(Span::new(6, 7), "expected a command before `|`"),
+                    Diagnostic::error(Span::eof(14), "expected a command after `|`"),
+                    Diagnostic::error(Span::eof(27), "expected a command after `|`"),
+                ]
+            );
+            assert_eq!(
+                describe(read.list(), &source),
+                &["[ls] | [sort]", "[echo (a |)]"]
+            );
+        }),
+        ("reading commands", {
+            use crate::{Commands, Shape, Value};
+
+            let commands = Commands::new()
+                .command(Signature::new("take").required("count", Shape::Int))
+                .command(Signature::new("count"));
+            let source = Source::new("<test>", "take (count)|tally x");
+            let read = read(&source);
+            let pipeline = &read.list().pipelines()[0];
+
+            let take = commands.read(&pipeline.commands()[0], &source);
+            assert_eq!(take.diagnostics(), &[]);
+            assert!(matches!(
+                take.call().positional("count").map(|value| value.item()),
+                Some(Value::Subexpression(_))
+            ));
+
+            let tally = commands.read(&pipeline.commands()[1], &source);
+            assert_eq!(
+                tally.diagnostics(),
+                &[Diagnostic::error(
+                    Span::new(13, 18),
+                    "unknown command `tally`"
+                )]
+            );
+            assert_eq!(tally.call().rest().len(), 1);
+        })
+    )
+);
@@ -1 +1,203 @@
-pub trait Shape {}
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+use wyst_lex::{Delimited, Delimiter, Leaf, Token};
+use wyst_source::{AddSpan, Diagnostic, Source, Span, Spanned};
+
+/// The kind of value that an argument expects.
+#[wyst_copy]
+pub enum Shape {
+    /// A word, a string, a block or a list.
+    Any,
+    /// A bare word.
+    Word,
+    /// A quoted string or a bare word.
+    String,
+    Int,
+    /// A delimited token in braces.
+    Block,
+    /// A delimited token in brackets.
+    List,
+}
+
+/// An argument that was read according to its [Shape].
+#[wyst_data]
+pub enum Value {
+    Word(String),
+    /// The contents of a string, without its quotes.
+    String(String),
+    Int(i64),
+    Block(Delimited),
+    List(Delimited),
+    /// A command list in parentheses, whose value isn't known until it runs. Subexpressions can be
+    /// used in place of any shape except blocks and lists.
+    Subexpression(Delimited),
+    /// The name of a variable (`$name`, without the `$`). Like a subexpression, a variable can be
+    /// used in place of any shape except blocks and lists.
+    Variable(String),
+}
+
+impl Shape {
+    /// The name of the shape in a signature (such as `int`).
+    pub fn name(self) -> &'static str {
+        match self {
+            Shape::Any => "any",
+            Shape::Word => "word",
+            Shape::String => "string",
+            Shape::Int => "int",
+            Shape::Block => "block",
+            Shape::List => "list",
+        }
+    }
+
+    /// A description of the shape for messages (such as "an integer").
+    pub fn description(self) -> &'static str {
+        match self {
+            Shape::Any => "a value",
+            Shape::Word => "a word",
+            Shape::String => "a string",
+            Shape::Int => "an integer",
+            Shape::Block => "a block",
+            Shape::List => "a list",
+        }
+    }
+
+    /// Read a single token as a value of this shape.
+    pub fn read(
+        self,
+        token: &Spanned<Token>,
+        source: &Source,
+    ) -> Result<Spanned<Value>, Diagnostic> {
+        let span = token.span();
+
+        match token.item() {
+            Token::Leaf(Leaf::Word) => self.read_word(span, source.slice(span)),
+            Token::Leaf(Leaf::Quoted(_)) => match self {
+                Shape::Any | Shape::String => {
+                    Ok(Value::String(unquote(source.slice(span)).to_string()).spanned(span))
+                }
+                _ => Err(self.mismatch(span, source.slice(span))),
+            },
+            Token::Delimited(delimited) => match (self, delimited.delimiter()) {
+                (Shape::Any, Delimiter::Brace) | (Shape::Block, Delimiter::Brace) => {
+                    Ok(Value::Block(delimited.clone()).spanned(span))
+                }
+                (Shape::Any, Delimiter::Bracket) | (Shape::List, Delimiter::Bracket) => {
+                    Ok(Value::List(delimited.clone()).spanned(span))
+                }
+                (Shape::Any, Delimiter::Paren)
+                | (Shape::Word, Delimiter::Paren)
+                | (Shape::String, Delimiter::Paren)
+                | (Shape::Int, Delimiter::Paren) => {
+                    Ok(Value::Subexpression(delimited.clone()).spanned(span))
+                }
+                (_, delimiter) => Err(self.mismatch(
+                    span.start().char_span(delimiter.open_char()),
+                    &delimiter.open_char().to_string(),
+                )),
+            },
+            Token::Leaf(_) => Err(self.mismatch(span, source.slice(span))),
+        }
+    }
+
+    /// Read the text of a word (or part of a word, such as the value in `--flag=value`).
+    pub fn read_word(self, span: Span, text: &str) -> Result<Spanned<Value>, Diagnostic> {
+        let variable = text.strip_prefix('$').filter(|name| !name.is_empty());
+
+        match (self, variable) {
+            (Shape::Block, _) | (Shape::List, _) => Err(self.mismatch(span, text)),
+            (_, Some(name)) => Ok(Value::Variable(name.to_string()).spanned(span)),
+            (Shape::Any, None) | (Shape::Word, None) => {
+                Ok(Value::Word(text.to_string()).spanned(span))
+            }
+            (Shape::String, None) => Ok(Value::String(text.to_string()).spanned(span)),
+            (Shape::Int, None) => match text.parse() {
+                Ok(int) => Ok(Value::Int(int).spanned(span)),
+                Err(_) => Err(self.mismatch(span, text)),
+            },
+        }
+    }
+
+    fn mismatch(self, span: Span, found: &str) -> Diagnostic {
+        Diagnostic::error(
+            span,
+            format!("expected {}, found `{}`", self.description(), found),
+        )
+    }
+}
+
+/// The contents of a quoted string (which may be missing its closing quote).
+fn unquote(text: &str) -> &str {
+    let mut chars = text.chars();
+
+    match chars.next() {
+        Some(quote) => {
+            let rest = chars.as_str();
+            rest.strip_suffix(quote).unwrap_or(rest)
+        }
+        None => text,
+    }
+}
+
+unit_tests!(
+    all({
+        use wyst_lex::{DefaultDelegate, FlatToken};
+
+        fn read(shape: Shape, text: &str) -> Result<Spanned<Value>, Diagnostic> {
+            let source = Source::new("<test>", text);
+            let tokens = FlatToken::read_tree::<DefaultDelegate>(&source).into_tokens();
+
+            shape.read(&tokens[0], &source)
+        }
+    }),
+    tests(("reading shapes", {
+        assert_eq!(
+            read(Shape::Int, "-12"),
+            Ok(Value::Int(-12).spanned(Span::new(0, 3)))
+        );
+        assert_eq!(
+            read(Shape::String, "'hello'"),
+            Ok(Value::String("hello".to_string()).spanned(Span::new(0, 7)))
+        );
+        assert_eq!(
+            read(Shape::Any, "hello"),
+            Ok(Value::Word("hello".to_string()).spanned(Span::new(0, 5)))
+        );
+        assert!(matches!(
+            read(Shape::Block, "{ x }").map(|value| value.item().clone()),
+            Ok(Value::Block(_))
+        ));
+        assert!(matches!(
+            read(Shape::Int, "(count)").map(|value| value.item().clone()),
+            Ok(Value::Subexpression(_))
+        ));
+        assert_eq!(
+            read(Shape::Int, "$n"),
+            Ok(Value::Variable("n".to_string()).spanned(Span::new(0, 2)))
+        );
+        assert_eq!(
+            read(Shape::String, "$"),
+            Ok(Value::String("$".to_string()).spanned(Span::new(0, 1)))
+        );
+
+        assert_eq!(
+            read(Shape::Int, "twelve"),
+            Err(Diagnostic::error(
+                Span::new(0, 6),
+                "expected an integer, found `twelve`"
+            ))
+        );
+        assert_eq!(
+            read(Shape::List, "$items"),
+            Err(Diagnostic::error(
+                Span::new(0, 6),
+                "expected a list, found `$items`"
+            ))
+        );
+        assert_eq!(
+            read(Shape::Block, "[x]"),
+            Err(Diagnostic::error(
+                Span::new(0, 1),
+                "expected a block, found `[`"
+            ))
+        );
+    }))
+);
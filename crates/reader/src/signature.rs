@@ -0,0 +1,211 @@
+use wyst_core::wyst_data;
+use wyst_source::{Diagnostic, Source};
+
+use crate::{call::ReadCall, pipeline::Command, shape::Shape};
+
+/// A positional argument (or the rest arguments) in a [Signature].
+#[wyst_data]
+pub struct Positional {
+    name: String,
+    shape: Shape,
+    required: bool,
+}
+
+impl Positional {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn shape(&self) -> Shape {
+        self.shape
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.required
+    }
+}
+
+/// A named flag (`--long` or `-s`) in a [Signature].
+#[wyst_data]
+pub struct Flag {
+    long: String,
+    short: Option<char>,
+    shape: Option<Shape>,
+}
+
+impl Flag {
+    pub fn long(&self) -> &str {
+        &self.long
+    }
+
+    pub fn short(&self) -> Option<char> {
+        self.short
+    }
+
+    /// The shape of the flag's value, or `None` if the flag is a switch that doesn't take one.
+    pub fn shape(&self) -> Option<Shape> {
+        self.shape
+    }
+
+    pub fn is_switch(&self) -> bool {
+        self.shape.is_none()
+    }
+}
+
+/// The arguments that a command accepts: positional arguments, optionally followed by rest
+/// arguments, and flags, which can appear anywhere after the command name. Positional arguments
+/// are filled in order, so optional arguments should come after the required ones.
+#[wyst_data]
+pub struct Signature {
+    name: String,
+    positional: Vec<Positional>,
+    rest: Option<Positional>,
+    flags: Vec<Flag>,
+}
+
+impl Signature {
+    pub fn new(name: impl Into<String>) -> Signature {
+        Signature {
+            name: name.into(),
+            positional: vec![],
+            rest: None,
+            flags: vec![],
+        }
+    }
+
+    pub fn required(self, name: impl Into<String>, shape: Shape) -> Signature {
+        self.add_positional(name, shape, true)
+    }
+
+    pub fn optional(self, name: impl Into<String>, shape: Shape) -> Signature {
+        self.add_positional(name, shape, false)
+    }
+
+    /// Any number of arguments after the positional arguments.
+    pub fn rest(self, name: impl Into<String>, shape: Shape) -> Signature {
+        Signature {
+            rest: Some(Positional {
+                name: name.into(),
+                shape,
+                required: false,
+            }),
+            ..self
+        }
+    }
+
+    /// A flag without a value.
+    pub fn switch(self, long: impl Into<String>, short: Option<char>) -> Signature {
+        self.add_flag(long, None, short)
+    }
+
+    /// A flag with a value (`--long value`, `--long=value` or `-s value`).
+    pub fn named(self, long: impl Into<String>, shape: Shape, short: Option<char>) -> Signature {
+        self.add_flag(long, Some(shape), short)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn positionals(&self) -> &[Positional] {
+        &self.positional
+    }
+
+    pub fn rest_arguments(&self) -> Option<&Positional> {
+        self.rest.as_ref()
+    }
+
+    pub fn flags(&self) -> &[Flag] {
+        &self.flags
+    }
+
+    pub fn flag(&self, long: &str) -> Option<&Flag> {
+        self.flags.iter().find(|flag| flag.long == long)
+    }
+
+    pub fn short_flag(&self, short: char) -> Option<&Flag> {
+        self.flags.iter().find(|flag| flag.short == Some(short))
+    }
+
+    fn add_positional(
+        mut self,
+        name: impl Into<String>,
+        shape: Shape,
+        required: bool,
+    ) -> Signature {
+        self.positional.push(Positional {
+            name: name.into(),
+            shape,
+            required,
+        });
+        self
+    }
+
+    fn add_flag(
+        mut self,
+        long: impl Into<String>,
+        shape: Option<Shape>,
+        short: Option<char>,
+    ) -> Signature {
+        self.flags.push(Flag {
+            long: long.into(),
+            short,
+            shape,
+        });
+        self
+    }
+}
+
+/// The signatures of the commands that are in scope.
+#[wyst_data]
+#[derive(Default)]
+pub struct Commands {
+    signatures: Vec<Signature>,
+}
+
+impl Commands {
+    pub fn new() -> Commands {
+        Commands::default()
+    }
+
+    /// Add a command, replacing any command with the same name.
+    pub fn command(mut self, signature: Signature) -> Commands {
+        self.signatures
+            .retain(|existing| existing.name != signature.name);
+        self.signatures.push(signature);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Signature> {
+        self.signatures
+            .iter()
+            .find(|signature| signature.name == name)
+    }
+
+    pub fn signatures(&self) -> &[Signature] {
+        &self.signatures
+    }
+
+    /// Read `command` according to the signature of the command it names. An unknown command is
+    /// reported, and its arguments are read as rest arguments of any shape.
+    pub fn read(&self, command: &Command, source: &Source) -> ReadCall {
+        let name = command.name(source);
+
+        match name.and_then(|name| self.get(name)) {
+            Some(signature) => command.read(signature, source),
+            None => {
+                let name = name.unwrap_or_default();
+                let read =
+                    command.read(&Signature::new(name).rest("arguments", Shape::Any), source);
+
+                match name {
+                    "" => read,
+                    _ => read.with_error(Diagnostic::error(
+                        command.tokens()[0].span(),
+                        format!("unknown command `{}`", name),
+                    )),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::fmt::Display;
+
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+
+use crate::span::Span;
+
+#[wyst_copy]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// A `Diagnostic` is a message about a span of a [crate::Source]. Phases that can recover from
+/// problems in the source (such as unbalanced delimiters) report them as diagnostics rather than
+/// failing.
+#[wyst_data]
+pub struct Diagnostic {
+    severity: Severity,
+    span: Span,
+    message: String,
+}
+
+impl Diagnostic {
+    pub fn error(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            span,
+            message: message.into(),
+        }
+    }
+
+    pub fn warning(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Warning,
+            span,
+            message: message.into(),
+        }
+    }
+
+    pub fn note(span: Span, message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Note,
+            span,
+            message: message.into(),
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}: {}",
+            self.severity,
+            self.span.start(),
+            self.span.end(),
+            self.message
+        )
+    }
+}
+
+unit_tests!(tests(
+    ("Diagnostic#is_error", {
+        assert!(Diagnostic::error(Span::new(0, 1), "oops").is_error());
+        assert!(!Diagnostic::warning(Span::new(0, 1), "hmm").is_error());
+    }),
+    ("Diagnostic (Display)", {
+        let diagnostic = Diagnostic::error(Span::new(3, 4), "unexpected `)`");
+
+        assert_eq!(format!("{}", diagnostic), "error at 3..4: unexpected `)`");
+    })
+));
@@ -1,9 +1,15 @@
+mod diagnostic;
 mod len;
+mod lines;
+mod provenance;
 mod source;
 mod span;
 mod spanned;
 
+pub use diagnostic::{Diagnostic, Severity};
 pub use len::HasLen;
-pub use source::Source;
+pub use lines::LineIndex;
+pub use provenance::{Expansion, ExpansionId, Provenance};
+pub use source::{Source, SourceId};
 pub use span::{InteriorSpan, Offset, Span};
 pub use spanned::{AddSpan, Spanned};
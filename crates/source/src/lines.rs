@@ -0,0 +1,59 @@
+use wyst_core::{unit_tests, wyst_data};
+
+use crate::span::Offset;
+
+/// Maps byte offsets in a source to (zero-based) line numbers.
+#[wyst_data]
+pub struct LineIndex {
+    /// The offset that each line starts at.
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> LineIndex {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        LineIndex { starts }
+    }
+
+    /// The line that contains `offset`. A newline belongs to the line that it ends.
+    pub fn line(&self, offset: impl Into<Offset>) -> usize {
+        let offset: usize = offset.into().into();
+
+        match self.starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next) => next - 1,
+        }
+    }
+
+    /// The offset that `line` starts at.
+    pub fn line_start(&self, line: usize) -> Option<Offset> {
+        self.starts.get(line).map(Offset::from)
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+}
+
+unit_tests!(tests(
+    ("LineIndex#line", {
+        let index = LineIndex::new("ab\nc\n\nd");
+
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line(0), 0);
+        assert_eq!(index.line(2), 0);
+        assert_eq!(index.line(3), 1);
+        assert_eq!(index.line(5), 2);
+        assert_eq!(index.line(6), 3);
+        assert_eq!(index.line(7), 3);
+    }),
+    ("LineIndex#line_start", {
+        let index = LineIndex::new("ab\nc");
+
+        assert_eq!(index.line_start(1), Some(Offset::from(3)));
+        assert_eq!(index.line_start(2), None);
+    })
+));
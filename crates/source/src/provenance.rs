@@ -0,0 +1,205 @@
+use wyst_core::{unit_tests, wyst_copy, wyst_data};
+
+use crate::{source::SourceId, span::Span, Diagnostic};
+
+/// Identifies a single expansion of a macro. Two tokens introduced by the same expansion have the
+/// same `ExpansionId`, which is what makes expansions hygienic.
+#[wyst_copy]
+pub struct ExpansionId {
+    id: usize,
+}
+
+impl From<usize> for ExpansionId {
+    fn from(id: usize) -> Self {
+        ExpansionId { id }
+    }
+}
+
+/// A single expansion of a macro: the macro's name, the span of its definition and the span of
+/// the call site, along with the sources that those spans point into. If the call site was itself
+/// produced by an expansion, `parent` is that expansion.
+#[wyst_data]
+pub struct Expansion {
+    id: ExpansionId,
+    name: String,
+    definition: (SourceId, Span),
+    call_site: (SourceId, Span),
+    parent: Option<Box<Expansion>>,
+}
+
+impl Expansion {
+    pub fn new(
+        id: impl Into<ExpansionId>,
+        name: impl Into<String>,
+        definition: (SourceId, Span),
+        call_site: (SourceId, Span),
+    ) -> Expansion {
+        Expansion {
+            id: id.into(),
+            name: name.into(),
+            definition,
+            call_site,
+            parent: None,
+        }
+    }
+
+    pub fn with_parent(mut self, parent: Option<Expansion>) -> Expansion {
+        self.parent = parent.map(Box::new);
+        self
+    }
+
+    pub fn id(&self) -> ExpansionId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn definition(&self) -> Span {
+        self.definition.1
+    }
+
+    /// The source that the macro was defined in.
+    pub fn definition_source(&self) -> &SourceId {
+        &self.definition.0
+    }
+
+    pub fn call_site(&self) -> Span {
+        self.call_site.1
+    }
+
+    /// The source that the macro was called from. This is the source of the parent expansion's
+    /// definition if the call was produced by an expansion.
+    pub fn call_site_source(&self) -> &SourceId {
+        &self.call_site.0
+    }
+
+    pub fn parent(&self) -> Option<&Expansion> {
+        self.parent.as_deref()
+    }
+
+    /// A note pointing at the call site, for diagnostics about tokens produced by this expansion.
+    pub fn note(&self) -> Diagnostic {
+        Diagnostic::note(
+            self.call_site(),
+            format!("in this expansion of `{}!`", self.name),
+        )
+    }
+}
+
+/// Where a span came from. A span that was written directly in the source has no expansion. A
+/// span that was produced by a macro expansion points into the macro's definition (which may be
+/// in a different source), and the expansion records where the macro was called.
+#[wyst_data]
+pub struct Provenance {
+    source: SourceId,
+    span: Span,
+    expansion: Option<Box<Expansion>>,
+}
+
+impl Provenance {
+    pub fn source(source: SourceId, span: Span) -> Provenance {
+        Provenance {
+            source,
+            span,
+            expansion: None,
+        }
+    }
+
+    pub fn expanded(source: SourceId, span: Span, expansion: Expansion) -> Provenance {
+        Provenance {
+            source,
+            span,
+            expansion: Some(Box::new(expansion)),
+        }
+    }
+
+    /// The span where the token was written, either in the source or in a macro definition.
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The source that [Provenance::span] points into.
+    pub fn source_id(&self) -> &SourceId {
+        &self.source
+    }
+
+    /// The innermost expansion that produced this span, if any.
+    pub fn expansion(&self) -> Option<&Expansion> {
+        self.expansion.as_deref()
+    }
+
+    /// The expansions that produced this span, from the innermost to the outermost.
+    pub fn expansions(&self) -> impl Iterator<Item = &Expansion> {
+        std::iter::successors(self.expansion(), |expansion| expansion.parent())
+    }
+
+    /// The span to point at in the source that the user wrote: the outermost call site for an
+    /// expanded span, or the span itself.
+    pub fn user_span(&self) -> Span {
+        match self.expansions().last() {
+            Some(expansion) => expansion.call_site(),
+            None => self.span,
+        }
+    }
+
+    /// A note for each expansion that produced this span, from the innermost to the outermost.
+    pub fn notes(&self) -> Vec<Diagnostic> {
+        self.expansions().map(Expansion::note).collect()
+    }
+}
+
+unit_tests!(
+    all({
+        use crate::Source;
+
+        fn source_id() -> SourceId {
+            Source::new("test.ts", "").id()
+        }
+
+        fn expansion(id: usize, name: &str, call_site: Span) -> Expansion {
+            Expansion::new(
+                id,
+                name,
+                (source_id(), Span::new(0, 10)),
+                (source_id(), call_site),
+            )
+        }
+    }),
+    tests(
+        ("Provenance (source)", {
+            let provenance = Provenance::source(source_id(), Span::new(3, 5));
+
+            assert_eq!(provenance.user_span(), Span::new(3, 5));
+            assert_eq!(provenance.expansions().count(), 0);
+            assert_eq!(provenance.notes(), vec![]);
+        }),
+        ("Provenance (nested expansions)", {
+            let outer = expansion(0, "outer", Span::new(20, 30));
+            let inner = expansion(1, "inner", Span::new(2, 8)).with_parent(Some(outer));
+            let provenance = Provenance::expanded(source_id(), Span::new(4, 5), inner);
+
+            assert_eq!(provenance.span(), Span::new(4, 5));
+            assert_eq!(provenance.user_span(), Span::new(20, 30));
+            assert_eq!(
+                provenance
+                    .expansions()
+                    .map(Expansion::name)
+                    .collect::<Vec<_>>(),
+                vec!["inner", "outer"]
+            );
+            assert_eq!(
+                provenance
+                    .notes()
+                    .iter()
+                    .map(|note| note.to_string())
+                    .collect::<Vec<_>>(),
+                vec![
+                    "note at 2..8: in this expansion of `inner!`",
+                    "note at 20..30: in this expansion of `outer!`"
+                ]
+            );
+        })
+    )
+);
@@ -1,14 +1,56 @@
 use wyst_core::{unit_tests, wyst_data};
 
-use crate::span::Span;
+use crate::{lines::LineIndex, span::Span};
 
-#[wyst_data(new)]
+#[wyst_data]
 pub struct Source {
     filename: camino::Utf8PathBuf,
     contents: String,
+    hash: blake3::Hash,
+}
+
+/// Identifies a [Source] by its filename and a hash of its contents, so that a span can remember
+/// which source it points into without borrowing the source. Two sources with the same filename
+/// (such as two versions of a file) have different ids unless they have the same contents.
+#[wyst_data]
+pub struct SourceId {
+    filename: camino::Utf8PathBuf,
+    hash: blake3::Hash,
+}
+
+impl SourceId {
+    pub fn filename(&self) -> &camino::Utf8Path {
+        &self.filename
+    }
 }
 
 impl Source {
+    pub fn new(filename: impl Into<camino::Utf8PathBuf>, contents: impl Into<String>) -> Source {
+        let contents = contents.into();
+
+        Source {
+            filename: filename.into(),
+            hash: blake3::hash(contents.as_bytes()),
+            contents,
+        }
+    }
+
+    pub fn id(&self) -> SourceId {
+        SourceId {
+            filename: self.filename.clone(),
+            hash: self.hash,
+        }
+    }
+
+    /// True if `id` is this source's id.
+    pub fn has_id(&self, id: &SourceId) -> bool {
+        self.hash == id.hash && self.filename == id.filename
+    }
+
+    pub fn filename(&self) -> &camino::Utf8Path {
+        &self.filename
+    }
+
     pub fn contents(&self) -> &str {
         &self.contents
     }
@@ -16,6 +58,10 @@ impl Source {
     pub fn slice(&self, span: Span) -> &str {
         span.slice(&self.contents)
     }
+
+    pub fn line_index(&self) -> LineIndex {
+        LineIndex::new(&self.contents)
+    }
 }
 
 unit_tests!(
@@ -67,6 +113,17 @@ unit_tests!(
             assert_eq!(map.get(&sources.s3), None);
             assert_eq!(map.get(&sources.s4), None);
         }),
+        ("Source#id", {
+            let sources = Sources::test();
+
+            assert_eq!(sources.main.id(), sources.main_eq.id());
+            assert_ne!(sources.main.id(), sources.s2.id());
+            assert_ne!(sources.main.id(), sources.s3.id());
+            assert_eq!(sources.main.id().filename(), "test.ts");
+
+            assert!(sources.main.has_id(&sources.main_eq.id()));
+            assert!(!sources.main.has_id(&sources.s3.id()));
+        }),
         ("Source#slice", {
             let Sources { main, .. } = Sources::test();
             let span = Span::new(6, 11);
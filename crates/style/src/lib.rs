@@ -3,5 +3,5 @@ mod print;
 mod style;
 
 pub use self::portable::*;
-pub use self::print::{Indent, Print, PrintCrossterm};
+pub use self::print::{emit_styled, Indent, Print, PrintCrossterm};
 pub use self::style::{PlainStyle, Style};
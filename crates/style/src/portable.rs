@@ -17,6 +17,28 @@ pub struct PortableStyle {
 }
 
 impl PortableStyle {
+    pub fn fg(color: PortableColor) -> PortableStyle {
+        PortableStyle {
+            fg: Some(color),
+            ..PortableStyle::normal()
+        }
+    }
+
+    pub fn with_bg(mut self, color: PortableColor) -> PortableStyle {
+        self.bg = Some(color);
+        self
+    }
+
+    pub fn with_bold_hint(mut self) -> PortableStyle {
+        self.bold_hint = true;
+        self
+    }
+
+    /// Does this style change any colors?
+    pub fn is_colored(self) -> bool {
+        self.fg.is_some() || self.bg.is_some()
+    }
+
     pub fn apply_style(self, buf: &mut impl ExecutableCommand) -> Result<(), Box<dyn Error>> {
         if let Some(fg) = self.fg {
             buf.execute(SetForegroundColor(fg.into()))?;
@@ -1,6 +1,9 @@
 use std::{error::Error, fmt::Debug};
 
-use crossterm::{style::Print as PrintCommand, ExecutableCommand};
+use crossterm::{
+    style::{Print as PrintCommand, ResetColor},
+    ExecutableCommand,
+};
 
 use crate::{PortableStyle, Print, Style};
 
@@ -9,6 +12,7 @@ where
     E: ExecutableCommand,
 {
     write: &'write mut E,
+    colored: bool,
 }
 
 impl<'write, E> PrintCrossterm<'write, E>
@@ -16,7 +20,10 @@ where
     E: ExecutableCommand,
 {
     pub fn new(write: &'write mut E) -> PrintCrossterm<'write, E> {
-        PrintCrossterm { write }
+        PrintCrossterm {
+            write,
+            colored: false,
+        }
     }
 }
 
@@ -36,13 +43,26 @@ where
     type Style = PortableStyle;
 
     fn emit_text(&mut self, text: &str, style: Self::Style) -> Result<(), Box<dyn Error>> {
+        // A style without colors doesn't set any, so the colors from a previous style have to be
+        // reset explicitly.
+        if self.colored {
+            self.write.execute(ResetColor)?;
+        }
+
         style.apply_style(self.write)?;
+        self.colored = style.is_colored();
+
         self.write.execute(PrintCommand(text))?;
 
         Ok(())
     }
 
     fn emit_break(&mut self, indent: crate::Indent<'_>) -> Result<(), Box<dyn Error>> {
+        if self.colored {
+            self.write.execute(ResetColor)?;
+            self.colored = false;
+        }
+
         PortableStyle::invisible().apply_style(self.write)?;
         self.write.execute(PrintCommand("\n"))?;
 
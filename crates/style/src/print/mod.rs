@@ -17,3 +17,33 @@ pub trait Print: std::fmt::Debug {
     fn emit_text(&mut self, text: &str, style: Self::Style) -> Result<(), Box<dyn Error>>;
     fn emit_break(&mut self, indent: Indent<'_>) -> Result<(), Box<dyn Error>>;
 }
+
+/// Print `text` with a style for each of its bytes, breaking lines at newlines. Adjacent bytes
+/// with the same style are printed together.
+pub fn emit_styled<P: Print>(
+    print: &mut P,
+    text: &str,
+    styles: &[P::Style],
+) -> Result<(), Box<dyn Error>> {
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        if c == '\n' {
+            if start < i {
+                print.emit_text(&text[start..i], styles[start])?;
+            }
+
+            print.emit_break(Indent { size: 0, chars: "" })?;
+            start = i + 1;
+        } else if i > start && styles[i] != styles[start] {
+            print.emit_text(&text[start..i], styles[start])?;
+            start = i;
+        }
+    }
+
+    if start < text.len() {
+        print.emit_text(&text[start..], styles[start])?;
+    }
+
+    Ok(())
+}